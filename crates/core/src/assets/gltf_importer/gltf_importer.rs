@@ -1,9 +1,11 @@
 use crate::core::gfx::resources::buffer::BufferMemory;
 use crate::core::gfx::resources::mesh::IndexBufferType;
+use crate::core::gfx::resources::sampler::SamplerDesc;
 use anyhow::{anyhow, Error};
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use gltf::mesh::util::ReadIndices;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
 use gltf::{Document, Gltf};
 use image::DynamicImage;
 use image::ImageFormat::{Jpeg, Png};
@@ -11,6 +13,7 @@ use std::env;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
+use vulkanalia::vk;
 
 pub struct GltfPrimitiveData {
     pub index: Option<BufferMemory<'static>>,
@@ -71,6 +74,14 @@ impl GltfImporter {
         Ok(image)
     }
 
+    /// Sampler of the first texture using the image, the glTF default sampler if there is none
+    pub fn image_sampler(&self, image_index: usize) -> SamplerDesc {
+        match self.document.textures().find(|texture| texture.source().index() == image_index) {
+            None => { SamplerDesc::default() }
+            Some(texture) => { sampler_desc(&texture.sampler()) }
+        }
+    }
+
     fn load_image_from_data(&self, data: &[u8], mime_type: Option<&str>) -> Result<DynamicImage, Error> {
         let encoded_format = if let Some(mime) = mime_type {
             match mime {
@@ -196,3 +207,32 @@ impl<'a> GltfDependencyUri<'a> {
         Ok(data)
     }
 }
+
+
+fn sampler_desc(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    let address_mode = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => { vk::SamplerAddressMode::CLAMP_TO_EDGE }
+        WrappingMode::MirroredRepeat => { vk::SamplerAddressMode::MIRRORED_REPEAT }
+        WrappingMode::Repeat => { vk::SamplerAddressMode::REPEAT }
+    };
+    let mut desc = SamplerDesc::default()
+        .address_modes(address_mode(sampler.wrap_s()), address_mode(sampler.wrap_t()), vk::SamplerAddressMode::REPEAT)
+        .anisotropy(16.0);
+    if let Some(MagFilter::Nearest) = sampler.mag_filter() {
+        desc = desc.mag_filter(vk::Filter::NEAREST);
+    }
+    let (min_filter, mipmap_mode, mipmaps) = match sampler.min_filter() {
+        None | Some(MinFilter::LinearMipmapLinear) => { (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR, true) }
+        Some(MinFilter::Nearest) => { (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST, false) }
+        Some(MinFilter::Linear) => { (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST, false) }
+        Some(MinFilter::NearestMipmapNearest) => { (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST, true) }
+        Some(MinFilter::LinearMipmapNearest) => { (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST, true) }
+        Some(MinFilter::NearestMipmapLinear) => { (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR, true) }
+    };
+    desc = desc.min_filter(min_filter).mipmap_mode(mipmap_mode);
+    if !mipmaps {
+        // Only sample the first level
+        desc = desc.lod(0.0, 0.25, 0.0);
+    }
+    desc
+}
\ No newline at end of file
@@ -1 +1,2 @@
-pub mod gltf_importer;
\ No newline at end of file
+pub mod gltf_importer;
+pub mod texture_importer;
\ No newline at end of file
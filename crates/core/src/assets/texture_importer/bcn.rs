@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Error};
+use vulkanalia::vk;
+
+/// Format produced by [`decode`], None when there is no CPU decoder for the given format
+pub fn decoded_format(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC2_UNORM_BLOCK | vk::Format::BC3_UNORM_BLOCK |
+        vk::Format::BC4_UNORM_BLOCK | vk::Format::BC5_UNORM_BLOCK => { Some(vk::Format::R8G8B8A8_UNORM) }
+        vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK | vk::Format::BC2_SRGB_BLOCK | vk::Format::BC3_SRGB_BLOCK => { Some(vk::Format::R8G8B8A8_SRGB) }
+        _ => { None }
+    }
+}
+
+/// Decode a BC1 to BC5 image to RGBA8. BC4 and BC5 channels are written to red and green.
+pub fn decode(format: vk::Format, width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let block_size = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK | vk::Format::BC4_UNORM_BLOCK => { 8 }
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK | vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK | vk::Format::BC5_UNORM_BLOCK => { 16 }
+        format => { return Err(anyhow!("No CPU decoder for {format:?}")) }
+    };
+    let (blocks_x, blocks_y) = (width.div_ceil(4) as usize, height.div_ceil(4) as usize);
+    if data.len() < blocks_x * blocks_y * block_size {
+        return Err(anyhow!("Not enough data for a {width}x{height} {format:?} image"));
+    }
+
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    let mut texels = [[0u8; 4]; 16];
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let offset = (block_y * blocks_x + block_x) * block_size;
+            let block = &data[offset..offset + block_size];
+            match format {
+                vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => {
+                    decode_color(block, &mut texels, true);
+                }
+                vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK => {
+                    decode_color(&block[8..], &mut texels, false);
+                    for (i, texel) in texels.iter_mut().enumerate() {
+                        texel[3] = ((block[i / 2] >> (4 * (i % 2))) & 0xF) * 17;
+                    }
+                }
+                vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => {
+                    decode_color(&block[8..], &mut texels, false);
+                    for (texel, alpha) in texels.iter_mut().zip(decode_channel(block)) {
+                        texel[3] = alpha;
+                    }
+                }
+                vk::Format::BC4_UNORM_BLOCK => {
+                    for (texel, red) in texels.iter_mut().zip(decode_channel(block)) {
+                        *texel = [red, 0, 0, 255];
+                    }
+                }
+                _ => {
+                    for ((texel, red), green) in texels.iter_mut().zip(decode_channel(block)).zip(decode_channel(&block[8..])) {
+                        *texel = [red, green, 0, 255];
+                    }
+                }
+            }
+            // Blocks on the right and bottom edges can overflow the image
+            for (i, texel) in texels.iter().enumerate() {
+                let (x, y) = (block_x * 4 + i % 4, block_y * 4 + i / 4);
+                if x < width as usize && y < height as usize {
+                    let pixel = (y * width as usize + x) * 4;
+                    pixels[pixel..pixel + 4].copy_from_slice(texel);
+                }
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+/// Two RGB565 endpoints and 2 bits indices. BC1 uses the 3 colors + transparent mode when the first endpoint is not greater.
+fn decode_color(block: &[u8], texels: &mut [[u8; 4]; 16], bc1: bool) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let expand = |color: u16| {
+        let (r, g, b) = ((color >> 11) & 0x1F, (color >> 5) & 0x3F, color & 0x1F);
+        [((r << 3) | (r >> 2)) as u32, ((g << 2) | (g >> 4)) as u32, ((b << 3) | (b >> 2)) as u32]
+    };
+    let (c0, c1) = (expand(color0), expand(color1));
+    let mix = |w0: u32, w1: u32| {
+        let mut color = [0u8, 0, 0, 255];
+        for channel in 0..3 {
+            color[channel] = ((c0[channel] * w0 + c1[channel] * w1) / (w0 + w1)) as u8;
+        }
+        color
+    };
+    let palette = if color0 > color1 || !bc1 {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0, 0, 0, 0]]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (2 * i)) & 0x3) as usize];
+    }
+}
+
+/// Two 8 bits endpoints and 3 bits indices (BC3 alpha, BC4, BC5)
+fn decode_channel(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [0u8; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+    let mut bits = 0u64;
+    for (i, byte) in block[2..8].iter().enumerate() {
+        bits |= (*byte as u64) << (8 * i);
+    }
+    let mut values = [0u8; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[((bits >> (3 * i)) & 0x7) as usize];
+    }
+    values
+}
@@ -0,0 +1,119 @@
+use crate::assets::texture_importer::texture_data::{read_bytes, read_u32, TextureData};
+use crate::core::gfx::resources::image::{self, level_size};
+use anyhow::{anyhow, Error};
+use vulkanalia::vk;
+
+pub const MAGIC: [u8; 4] = *b"DDS ";
+
+const HEADER_END: usize = 128;
+const DX10_HEADER_END: usize = 148;
+
+const PIXEL_FORMAT_FOURCC: u32 = 0x4;
+const PIXEL_FORMAT_RGB: u32 = 0x40;
+const CAPS2_CUBEMAP: u32 = 0x200;
+const CAPS2_VOLUME: u32 = 0x200000;
+const DX10_MISC_TEXTURECUBE: u32 = 0x4;
+const DX10_DIMENSION_TEXTURE3D: u32 = 4;
+
+/// DDS container, with legacy FourCC or DX10 headers
+pub fn parse(data: &[u8]) -> Result<TextureData, Error> {
+    let height = read_u32(data, 12)?;
+    let width = read_u32(data, 16)?;
+    let depth = read_u32(data, 24)?;
+    let mip_count = read_u32(data, 28)?.max(1);
+    let pixel_format_flags = read_u32(data, 80)?;
+    let four_cc = read_bytes(data, 84, 4)?;
+    let caps2 = read_u32(data, 112)?;
+
+    let mut cube = caps2 & CAPS2_CUBEMAP != 0;
+    let mut array_layers = if cube { 6 } else { 1 };
+    let mut volume = caps2 & CAPS2_VOLUME != 0 && depth > 1;
+    let mut data_offset = HEADER_END;
+
+    let format = if pixel_format_flags & PIXEL_FORMAT_FOURCC != 0 {
+        match four_cc {
+            b"DX10" => {
+                let dimension = read_u32(data, 132)?;
+                let misc_flags = read_u32(data, 136)?;
+                let array_size = read_u32(data, 140)?.max(1);
+                cube = misc_flags & DX10_MISC_TEXTURECUBE != 0;
+                volume = dimension == DX10_DIMENSION_TEXTURE3D;
+                array_layers = if cube { array_size.checked_mul(6).ok_or(anyhow!("Invalid array size {array_size}"))? } else { array_size };
+                data_offset = DX10_HEADER_END;
+                dxgi_format(read_u32(data, 128)?)?
+            }
+            b"DXT1" => { vk::Format::BC1_RGBA_UNORM_BLOCK }
+            b"DXT2" | b"DXT3" => { vk::Format::BC2_UNORM_BLOCK }
+            b"DXT4" | b"DXT5" => { vk::Format::BC3_UNORM_BLOCK }
+            b"ATI1" | b"BC4U" => { vk::Format::BC4_UNORM_BLOCK }
+            b"BC4S" => { vk::Format::BC4_SNORM_BLOCK }
+            b"ATI2" | b"BC5U" => { vk::Format::BC5_UNORM_BLOCK }
+            b"BC5S" => { vk::Format::BC5_SNORM_BLOCK }
+            four_cc => { return Err(anyhow!("Unsupported FourCC {}", String::from_utf8_lossy(four_cc))) }
+        }
+    } else if pixel_format_flags & PIXEL_FORMAT_RGB != 0 && read_u32(data, 88)? == 32 {
+        match (read_u32(data, 92)?, read_u32(data, 100)?) {
+            (0xFF, 0xFF0000) => { vk::Format::R8G8B8A8_UNORM }
+            (0xFF0000, 0xFF) => { vk::Format::B8G8R8A8_UNORM }
+            _ => { return Err(anyhow!("Unsupported 32 bits pixel format")) }
+        }
+    } else {
+        return Err(anyhow!("Unsupported pixel format"));
+    };
+
+    let depth = if volume { depth.max(1) } else { 1 };
+    if mip_count > image::mip_count(width, height.max(1), depth) {
+        return Err(anyhow!("Invalid mip count {mip_count} for a {width}x{height}x{depth} texture"));
+    }
+    let mut texture = TextureData {
+        format,
+        image_type: if volume { vk::ImageType::_3D } else { vk::ImageType::_2D },
+        width,
+        height: height.max(1),
+        depth,
+        array_layers,
+        cube,
+        layers: vec![],
+    };
+
+    // Each layer (or face) stores its whole mip chain
+    let mut offset = data_offset;
+    for _ in 0..array_layers {
+        let mut mips = vec![];
+        for level in 0..mip_count {
+            let (width, height, depth) = texture.mip_extent(level);
+            let size = level_size(format, width, height, depth).ok_or(anyhow!("Unsupported format {format:?}"))?;
+            mips.push(read_bytes(data, offset, size)?.to_vec());
+            offset += size;
+        }
+        texture.layers.push(mips);
+    }
+    texture.validate()?;
+    Ok(texture)
+}
+
+fn dxgi_format(format: u32) -> Result<vk::Format, Error> {
+    Ok(match format {
+        2 => { vk::Format::R32G32B32A32_SFLOAT }
+        10 => { vk::Format::R16G16B16A16_SFLOAT }
+        28 => { vk::Format::R8G8B8A8_UNORM }
+        29 => { vk::Format::R8G8B8A8_SRGB }
+        71 => { vk::Format::BC1_RGBA_UNORM_BLOCK }
+        72 => { vk::Format::BC1_RGBA_SRGB_BLOCK }
+        74 => { vk::Format::BC2_UNORM_BLOCK }
+        75 => { vk::Format::BC2_SRGB_BLOCK }
+        77 => { vk::Format::BC3_UNORM_BLOCK }
+        78 => { vk::Format::BC3_SRGB_BLOCK }
+        80 => { vk::Format::BC4_UNORM_BLOCK }
+        81 => { vk::Format::BC4_SNORM_BLOCK }
+        83 => { vk::Format::BC5_UNORM_BLOCK }
+        84 => { vk::Format::BC5_SNORM_BLOCK }
+        87 => { vk::Format::B8G8R8A8_UNORM }
+        91 => { vk::Format::B8G8R8A8_SRGB }
+        95 => { vk::Format::BC6H_UFLOAT_BLOCK }
+        96 => { vk::Format::BC6H_SFLOAT_BLOCK }
+        98 => { vk::Format::BC7_UNORM_BLOCK }
+        99 => { vk::Format::BC7_SRGB_BLOCK }
+        format => { return Err(anyhow!("Unsupported DXGI format {format}")) }
+    })
+}
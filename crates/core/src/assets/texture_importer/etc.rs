@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Error};
+use vulkanalia::vk;
+
+// Intensity modifiers of the individual and differential modes
+const MODIFIERS: [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+// Distances between the paint colors of the T and H modes
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Format produced by [`decode`], None when there is no CPU decoder for the given format
+pub fn decoded_format(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK |
+        vk::Format::EAC_R11_UNORM_BLOCK | vk::Format::EAC_R11G11_UNORM_BLOCK => { Some(vk::Format::R8G8B8A8_UNORM) }
+        vk::Format::ETC2_R8G8B8_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => { Some(vk::Format::R8G8B8A8_SRGB) }
+        _ => { None }
+    }
+}
+
+/// Decode an ETC2 or unsigned EAC image to RGBA8. EAC channels are written to red and green.
+pub fn decode(format: vk::Format, width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let block_size = match format {
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK |
+        vk::Format::EAC_R11_UNORM_BLOCK => { 8 }
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK | vk::Format::EAC_R11G11_UNORM_BLOCK => { 16 }
+        format => { return Err(anyhow!("No CPU decoder for {format:?}")) }
+    };
+    let (blocks_x, blocks_y) = (width.div_ceil(4) as usize, height.div_ceil(4) as usize);
+    if data.len() < blocks_x * blocks_y * block_size {
+        return Err(anyhow!("Not enough data for a {width}x{height} {format:?} image"));
+    }
+
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    let mut texels = [[0u8; 4]; 16];
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let offset = (block_y * blocks_x + block_x) * block_size;
+            let block = &data[offset..offset + block_size];
+            match format {
+                vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => {
+                    decode_color(block_bits(block), &mut texels, false);
+                }
+                vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK => {
+                    decode_color(block_bits(block), &mut texels, true);
+                }
+                vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => {
+                    decode_color(block_bits(&block[8..]), &mut texels, false);
+                    for (texel, alpha) in texels.iter_mut().zip(decode_alpha(block_bits(block))) {
+                        texel[3] = alpha;
+                    }
+                }
+                vk::Format::EAC_R11_UNORM_BLOCK => {
+                    for (texel, red) in texels.iter_mut().zip(decode_r11(block_bits(block))) {
+                        *texel = [red, 0, 0, 255];
+                    }
+                }
+                _ => {
+                    for ((texel, red), green) in texels.iter_mut().zip(decode_r11(block_bits(block))).zip(decode_r11(block_bits(&block[8..]))) {
+                        *texel = [red, green, 0, 255];
+                    }
+                }
+            }
+            // Blocks on the right and bottom edges can overflow the image
+            for (i, texel) in texels.iter().enumerate() {
+                let (x, y) = (block_x * 4 + i % 4, block_y * 4 + i / 4);
+                if x < width as usize && y < height as usize {
+                    let pixel = (y * width as usize + x) * 4;
+                    pixels[pixel..pixel + 4].copy_from_slice(texel);
+                }
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+// Blocks are stored in big endian
+fn block_bits(block: &[u8]) -> u64 {
+    u64::from_be_bytes(block[..8].try_into().unwrap())
+}
+
+fn bits(block: u64, shift: u32, count: u32) -> i32 {
+    ((block >> shift) & ((1 << count) - 1)) as i32
+}
+
+fn extend(value: i32, count: u32) -> i32 {
+    (value << (8 - count)) | (value >> (2 * count - 8))
+}
+
+fn add(color: [i32; 3], value: i32) -> [u8; 4] {
+    [(color[0] + value).clamp(0, 255) as u8, (color[1] + value).clamp(0, 255) as u8, (color[2] + value).clamp(0, 255) as u8, 255]
+}
+
+// Pixels are indexed in columns : the first four indices describe the first column
+fn pixel_index(block: u64, column_index: usize) -> usize {
+    let (msb, lsb) = ((block >> (16 + column_index)) & 1, (block >> column_index) & 1);
+    (msb << 1 | lsb) as usize
+}
+
+/// RGB part of an ETC2 block, written to `texels` in row order. Punch-through blocks use their differential bit as an opacity flag.
+fn decode_color(block: u64, texels: &mut [[u8; 4]; 16], punch_through: bool) {
+    let differential = bits(block, 33, 1) == 1;
+    let opaque = !punch_through || differential;
+    if !punch_through && !differential {
+        let base = [
+            [extend(bits(block, 60, 4), 4), extend(bits(block, 52, 4), 4), extend(bits(block, 44, 4), 4)],
+            [extend(bits(block, 56, 4), 4), extend(bits(block, 48, 4), 4), extend(bits(block, 40, 4), 4)],
+        ];
+        return decode_subblocks(block, base, texels, opaque);
+    }
+
+    let (red, green, blue) = (bits(block, 59, 5), bits(block, 51, 5), bits(block, 43, 5));
+    let signed = |value: i32| if value >= 4 { value - 8 } else { value };
+    let (red2, green2, blue2) = (red + signed(bits(block, 56, 3)), green + signed(bits(block, 48, 3)), blue + signed(bits(block, 40, 3)));
+    if !(0..32).contains(&red2) {
+        // T mode
+        let base1 = [extend(bits(block, 59, 2) << 2 | bits(block, 56, 2), 4), extend(bits(block, 52, 4), 4), extend(bits(block, 48, 4), 4)];
+        let base2 = [extend(bits(block, 44, 4), 4), extend(bits(block, 40, 4), 4), extend(bits(block, 36, 4), 4)];
+        let distance = DISTANCES[(bits(block, 34, 2) << 1 | bits(block, 32, 1)) as usize];
+        decode_paint_colors(block, [add(base1, 0), add(base2, distance), add(base2, 0), add(base2, -distance)], texels, opaque);
+    } else if !(0..32).contains(&green2) {
+        // H mode
+        let base1 = [bits(block, 59, 4), bits(block, 56, 3) << 1 | bits(block, 52, 1), bits(block, 51, 1) << 3 | bits(block, 47, 3)];
+        let base2 = [bits(block, 43, 4), bits(block, 39, 4), bits(block, 35, 4)];
+        let value = |color: [i32; 3]| color[0] << 8 | color[1] << 4 | color[2];
+        let distance_index = bits(block, 34, 1) << 2 | bits(block, 32, 1) << 1 | (value(base1) >= value(base2)) as i32;
+        let distance = DISTANCES[distance_index as usize];
+        let (base1, base2) = (base1.map(|channel| extend(channel, 4)), base2.map(|channel| extend(channel, 4)));
+        decode_paint_colors(block, [add(base1, distance), add(base1, -distance), add(base2, distance), add(base2, -distance)], texels, opaque);
+    } else if !(0..32).contains(&blue2) {
+        decode_planar(block, texels);
+    } else {
+        let base = [
+            [extend(red, 5), extend(green, 5), extend(blue, 5)],
+            [extend(red2, 5), extend(green2, 5), extend(blue2, 5)],
+        ];
+        decode_subblocks(block, base, texels, opaque);
+    }
+}
+
+/// Individual and differential modes : two sub-blocks with a base color and a modifier table each
+fn decode_subblocks(block: u64, base: [[i32; 3]; 2], texels: &mut [[u8; 4]; 16], opaque: bool) {
+    let flip = bits(block, 32, 1) == 1;
+    let tables = [bits(block, 37, 3) as usize, bits(block, 34, 3) as usize];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let subblock = if flip { (y >= 2) as usize } else { (x >= 2) as usize };
+        let [small, large] = MODIFIERS[tables[subblock]];
+        *texel = match (pixel_index(block, x * 4 + y), opaque) {
+            (0, true) => { add(base[subblock], small) }
+            (0, false) => { add(base[subblock], 0) }
+            (1, _) => { add(base[subblock], large) }
+            (2, true) => { add(base[subblock], -small) }
+            (2, false) => { [0, 0, 0, 0] }
+            _ => { add(base[subblock], -large) }
+        };
+    }
+}
+
+/// T and H modes : each pixel picks one of four paint colors
+fn decode_paint_colors(block: u64, paint_colors: [[u8; 4]; 4], texels: &mut [[u8; 4]; 16], opaque: bool) {
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let index = pixel_index(block, (i % 4) * 4 + i / 4);
+        *texel = if index == 2 && !opaque { [0, 0, 0, 0] } else { paint_colors[index] };
+    }
+}
+
+/// Planar mode : colors are interpolated from the origin, horizontal and vertical colors. Always opaque.
+fn decode_planar(block: u64, texels: &mut [[u8; 4]; 16]) {
+    let origin = [
+        extend(bits(block, 57, 6), 6),
+        extend(bits(block, 56, 1) << 6 | bits(block, 49, 6), 7),
+        extend(bits(block, 48, 1) << 5 | bits(block, 43, 2) << 3 | bits(block, 39, 3), 6),
+    ];
+    let horizontal = [extend(bits(block, 34, 5) << 1 | bits(block, 32, 1), 6), extend(bits(block, 25, 7), 7), extend(bits(block, 19, 6), 6)];
+    let vertical = [extend(bits(block, 13, 6), 6), extend(bits(block, 6, 7), 7), extend(bits(block, 0, 6), 6)];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        for channel in 0..3 {
+            let value = x * (horizontal[channel] - origin[channel]) + y * (vertical[channel] - origin[channel]) + 4 * origin[channel] + 2;
+            texel[channel] = (value >> 2).clamp(0, 255) as u8;
+        }
+        texel[3] = 255;
+    }
+}
+
+// Base value, multiplier and modifier table, then 3 bits indices in columns
+fn eac_values(block: u64) -> (i32, i32, [i32; 8], [usize; 16]) {
+    let mut indices = [0; 16];
+    for (i, index) in indices.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        *index = bits(block, 45 - 3 * (x * 4 + y) as u32, 3) as usize;
+    }
+    (bits(block, 56, 8), bits(block, 52, 4), EAC_MODIFIERS[bits(block, 48, 4) as usize], indices)
+}
+
+/// Alpha part of an ETC2 RGBA block
+fn decode_alpha(block: u64) -> [u8; 16] {
+    let (base, multiplier, modifiers, indices) = eac_values(block);
+    indices.map(|index| (base + modifiers[index] * multiplier).clamp(0, 255) as u8)
+}
+
+/// Unsigned 11 bits channel, reduced to 8 bits
+fn decode_r11(block: u64) -> [u8; 16] {
+    let (base, multiplier, modifiers, indices) = eac_values(block);
+    indices.map(|index| {
+        let modifier = if multiplier == 0 { modifiers[index] } else { modifiers[index] * multiplier * 8 };
+        let value = (base * 8 + 4 + modifier).clamp(0, 2047);
+        ((value * 255 + 1023) / 2047) as u8
+    })
+}
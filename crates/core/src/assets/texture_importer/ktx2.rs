@@ -0,0 +1,189 @@
+use crate::assets::texture_importer::texture_data::{read_bytes, read_u32, read_u64, TextureData};
+use crate::core::gfx::resources::image::{format_block, level_size, mip_count};
+use anyhow::{anyhow, Error};
+use vulkanalia::vk;
+
+pub const IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+const SUPERCOMPRESSION_ZSTANDARD: u32 = 2;
+const SUPERCOMPRESSION_ZLIB: u32 = 3;
+
+/// KTX2 container. Supercompressed textures (BasisLZ, Zstandard, ZLIB) and Basis Universal payloads (UASTC / ETC1S) are not supported yet.
+pub fn parse(data: &[u8]) -> Result<TextureData, Error> {
+    let format = vk::Format::from_raw(read_u32(data, 12)? as i32);
+    let width = read_u32(data, 20)?;
+    let height = read_u32(data, 24)?;
+    let depth = read_u32(data, 28)?;
+    let layer_count = read_u32(data, 32)?;
+    let face_count = read_u32(data, 36)?;
+    let level_count = read_u32(data, 40)?;
+    let supercompression_scheme = read_u32(data, 44)?;
+
+    match supercompression_scheme {
+        0 => {}
+        SUPERCOMPRESSION_BASIS_LZ => { return Err(anyhow!("Basis Universal (BasisLZ / ETC1S) textures are not supported")) }
+        SUPERCOMPRESSION_ZSTANDARD => { return Err(anyhow!("Zstandard supercompressed textures are not supported")) }
+        SUPERCOMPRESSION_ZLIB => { return Err(anyhow!("ZLIB supercompressed textures are not supported")) }
+        scheme => { return Err(anyhow!("Unknown supercompression scheme {scheme}")) }
+    }
+    if format == vk::Format::UNDEFINED {
+        return Err(anyhow!("Basis Universal (UASTC) textures are not supported"));
+    }
+    if format_block(format).is_none() {
+        return Err(anyhow!("Unsupported format {format:?}"));
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err(anyhow!("Invalid face count {face_count}"));
+    }
+    if level_count > mip_count(width, height, depth) {
+        return Err(anyhow!("Invalid level count {level_count} for a {width}x{height}x{depth} texture"));
+    }
+    let array_layers = layer_count.max(1).checked_mul(face_count).ok_or(anyhow!("Invalid layer count {layer_count}"))?;
+
+    let image_type = if depth > 0 { vk::ImageType::_3D } else if height > 0 { vk::ImageType::_2D } else { vk::ImageType::_1D };
+    let mut texture = TextureData {
+        format,
+        image_type,
+        width,
+        height: height.max(1),
+        depth: depth.max(1),
+        array_layers,
+        cube: face_count == 6,
+        layers: vec![],
+    };
+
+    // The level index starts from mip 0. A level count of 0 asks for the mips to be generated.
+    let mut levels = vec![];
+    for level in 0..level_count.max(1) {
+        let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+        let offset = read_u64(data, entry)? as usize;
+        let size = read_u64(data, entry + 8)? as usize;
+        // Each level stores its layers, then faces, then depth slices, without padding
+        let (width, height, depth) = texture.mip_extent(level);
+        let image_size = level_size(format, width, height, depth).ok_or(anyhow!("Level {level} is too large"))?;
+        let expected = image_size.checked_mul(array_layers as usize).ok_or(anyhow!("Level {level} is too large"))?;
+        if size != expected {
+            return Err(anyhow!("Invalid size for level {level} : {size} bytes, expected {expected}"));
+        }
+        levels.push((read_bytes(data, offset, size)?, image_size));
+    }
+    texture.layers = vec![vec![]; array_layers as usize];
+    for (level_data, image_size) in levels {
+        for (layer, image) in level_data.chunks(image_size).enumerate() {
+            texture.layers[layer].push(image.to_vec());
+        }
+    }
+    texture.validate()?;
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assets::texture_importer::ktx2::{parse, HEADER_SIZE, IDENTIFIER, LEVEL_INDEX_ENTRY_SIZE};
+    use vulkanalia::vk;
+
+    const FORMAT: usize = 12;
+    const FACE_COUNT: usize = 36;
+    const LEVEL_COUNT: usize = 40;
+    const SUPERCOMPRESSION: usize = 44;
+
+    // Header, level index and level data, without data format descriptor nor key / value data
+    fn ktx2(format: vk::Format, width: u32, height: u32, layers: u32, faces: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = IDENTIFIER.to_vec();
+        for value in [format.as_raw() as u32, 1, width, height, 0, layers, faces, levels.len() as u32, 0] {
+            data.extend(value.to_le_bytes());
+        }
+        data.resize(HEADER_SIZE, 0);
+        let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+        for level in levels {
+            for value in [offset, level.len(), level.len()] {
+                data.extend((value as u64).to_le_bytes());
+            }
+            offset += level.len();
+        }
+        for level in levels {
+            data.extend(level);
+        }
+        data
+    }
+
+    fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn parses_mip_chain() {
+        let levels = [vec![1u8; 64], vec![2u8; 16], vec![3u8; 4]];
+        let texture = parse(&ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 0, 1, &levels)).unwrap();
+        assert_eq!(texture.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(texture.image_type, vk::ImageType::_2D);
+        assert_eq!((texture.width, texture.height, texture.depth), (4, 4, 1));
+        assert_eq!(texture.array_layers, 1);
+        assert!(!texture.cube);
+        assert_eq!(texture.layers, vec![levels.to_vec()]);
+    }
+
+    #[test]
+    fn parses_cube_faces() {
+        // A single level holds the 6 faces one after the other
+        let faces = (0..6u8).map(|face| vec![face; 2 * 2 * 4]).collect::<Vec<_>>();
+        let texture = parse(&ktx2(vk::Format::R8G8B8A8_UNORM, 2, 2, 0, 6, &[faces.concat()])).unwrap();
+        assert!(texture.cube);
+        assert_eq!(texture.array_layers, 6);
+        for (face, layer) in texture.layers.iter().enumerate() {
+            assert_eq!(layer, &vec![faces[face].clone()]);
+        }
+    }
+
+    #[test]
+    fn parses_block_compressed_levels() {
+        // 8x8 BC1 : 4 blocks of 8 bytes, then 1 block for each smaller level
+        let levels = [vec![0u8; 32], vec![0u8; 8], vec![0u8; 8], vec![0u8; 8]];
+        let texture = parse(&ktx2(vk::Format::BC1_RGBA_UNORM_BLOCK, 8, 8, 0, 1, &levels)).unwrap();
+        assert_eq!(texture.mip_count(), 4);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 0, 1, &[vec![0u8; 64]]);
+        assert!(parse(&data[..40]).is_err());
+        assert!(parse(&data[..HEADER_SIZE + 8]).is_err());
+        assert!(parse(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_level_sizes() {
+        assert!(parse(&ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 0, 1, &[vec![0u8; 60]])).is_err());
+        assert!(parse(&ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 0, 1, &[vec![0u8; 68]])).is_err());
+        // The level index points past the end of the file
+        let mut data = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 0, 1, &[vec![0u8; 64]]);
+        data[HEADER_SIZE..HEADER_SIZE + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let data = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 0, 1, &[vec![0u8; 64]]);
+
+        let mut undefined_format = data.clone();
+        set_u32(&mut undefined_format, FORMAT, vk::Format::UNDEFINED.as_raw() as u32);
+        assert!(parse(&undefined_format).is_err());
+
+        let mut supercompressed = data.clone();
+        set_u32(&mut supercompressed, SUPERCOMPRESSION, 2);
+        assert!(parse(&supercompressed).is_err());
+
+        let mut face_count = data.clone();
+        set_u32(&mut face_count, FACE_COUNT, 3);
+        assert!(parse(&face_count).is_err());
+
+        // A 4x4 texture has 3 levels at most
+        let mut level_count = data.clone();
+        set_u32(&mut level_count, LEVEL_COUNT, 4);
+        assert!(parse(&level_count).is_err());
+    }
+}
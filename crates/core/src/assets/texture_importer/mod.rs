@@ -0,0 +1,5 @@
+pub mod texture_data;
+pub mod ktx2;
+pub mod dds;
+pub mod bcn;
+pub mod etc;
\ No newline at end of file
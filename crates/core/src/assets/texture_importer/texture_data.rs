@@ -0,0 +1,108 @@
+use crate::assets::texture_importer::{bcn, dds, etc, ktx2};
+use crate::core::gfx::resources::image::level_size;
+use anyhow::{anyhow, Error};
+use std::path::Path;
+use vulkanalia::vk;
+
+/// Texture loaded from a container (KTX2 or DDS), with its precomputed mip chain
+#[derive(Clone)]
+pub struct TextureData {
+    pub format: vk::Format,
+    pub image_type: vk::ImageType,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    /// Cube faces are stored as layers, in the +X, -X, +Y, -Y, +Z, -Z order
+    pub array_layers: u32,
+    pub cube: bool,
+    /// Tightly packed data, indexed by [layer][mip]
+    pub layers: Vec<Vec<Vec<u8>>>,
+}
+
+impl TextureData {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = std::fs::read(path).map_err(|e| anyhow!("Failed to read texture {} : {e}", path.display()))?;
+        Self::from_memory(&data).map_err(|e| anyhow!("Failed to load texture {} : {e}", path.display()))
+    }
+
+    /// Detect the container from its magic number
+    pub fn from_memory(data: &[u8]) -> Result<Self, Error> {
+        if data.starts_with(&ktx2::IDENTIFIER) {
+            ktx2::parse(data)
+        } else if data.starts_with(&dds::MAGIC) {
+            dds::parse(data)
+        } else {
+            Err(anyhow!("Unknown texture container"))
+        }
+    }
+
+    pub fn mip_count(&self) -> u32 {
+        self.layers.first().map(|mips| mips.len() as u32).unwrap_or(0)
+    }
+
+    pub fn mip_extent(&self, mip_level: u32) -> (u32, u32, u32) {
+        ((self.width >> mip_level).max(1), (self.height >> mip_level).max(1), (self.depth >> mip_level).max(1))
+    }
+
+    /// Decode a compressed texture to RGBA8, for devices that can't sample its format. BCn and ETC2 / EAC formats can be decoded, ASTC can't.
+    pub fn decompressed(&self) -> Result<Self, Error> {
+        type Decoder = fn(vk::Format, u32, u32, &[u8]) -> Result<Vec<u8>, Error>;
+        let (format, decode): (vk::Format, Decoder) = if let Some(format) = bcn::decoded_format(self.format) {
+            (format, bcn::decode)
+        } else if let Some(format) = etc::decoded_format(self.format) {
+            (format, etc::decode)
+        } else {
+            return Err(anyhow!("No CPU decoder for {:?}", self.format));
+        };
+        let mut layers = vec![];
+        for mips in &self.layers {
+            let mut decoded_mips = vec![];
+            for (level, mip) in mips.iter().enumerate() {
+                let (width, height, depth) = self.mip_extent(level as u32);
+                let slice_size = mip.len() / depth as usize;
+                let mut decoded = vec![];
+                for slice in mip.chunks(slice_size) {
+                    decoded.extend(decode(self.format, width, height, slice)?);
+                }
+                decoded_mips.push(decoded);
+            }
+            layers.push(decoded_mips);
+        }
+        Ok(Self { format, layers, ..self.clone() })
+    }
+
+    /// Check that every level holds the expected amount of data
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.width == 0 {
+            return Err(anyhow!("Invalid width 0"));
+        }
+        if self.layers.len() != self.array_layers as usize {
+            return Err(anyhow!("Expected {} layers, got {}", self.array_layers, self.layers.len()));
+        }
+        for mips in &self.layers {
+            if mips.len() != self.mip_count() as usize {
+                return Err(anyhow!("Every layer should have the same mip count"));
+            }
+            for (level, mip) in mips.iter().enumerate() {
+                let (width, height, depth) = self.mip_extent(level as u32);
+                let expected = level_size(self.format, width, height, depth).ok_or(anyhow!("Unsupported format {:?}", self.format))?;
+                if mip.len() != expected {
+                    return Err(anyhow!("Invalid size for mip {level} : {} bytes, expected {expected}", mip.len()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    Ok(u32::from_le_bytes(data.get(offset..offset + 4).ok_or(anyhow!("Unexpected end of file"))?.try_into()?))
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+    Ok(u64::from_le_bytes(data.get(offset..offset + 8).ok_or(anyhow!("Unexpected end of file"))?.try_into()?))
+}
+
+pub(crate) fn read_bytes(data: &[u8], offset: usize, size: usize) -> Result<&[u8], Error> {
+    offset.checked_add(size).and_then(|end| data.get(offset..end)).ok_or(anyhow!("Unexpected end of file"))
+}
@@ -0,0 +1,187 @@
+use crate::core::gfx::device::DeviceCtx;
+use crate::core::gfx::resources::sampler::SamplerDesc;
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use vulkanalia::vk;
+use vulkanalia::vk::{DeviceV1_0, HasBuilder};
+
+/// Descriptor set index of the bindless table in pipelines created with `PipelineConfig::bindless`
+pub const BINDLESS_SET: u32 = 1;
+const MAX_BINDLESS_IMAGES: u32 = 16384;
+const MAX_BINDLESS_SAMPLERS: u32 = 256;
+
+// Indices handed out for one binding of the table
+struct SlotAllocator {
+    next: u32,
+    capacity: u32,
+    free: Vec<u32>,
+}
+
+impl SlotAllocator {
+    fn allocate(&mut self) -> Result<u32, Error> {
+        if let Some(index) = self.free.pop() {
+            return Ok(index);
+        }
+        if self.next >= self.capacity {
+            return Err(anyhow!("Bindless table is full ({} slots)", self.capacity));
+        }
+        self.next += 1;
+        Ok(self.next - 1)
+    }
+}
+
+/// Slot of a destroyed image, given back to the table once the frames using it are complete
+pub(crate) struct BindlessImageSlot {
+    pub(crate) index: u32,
+    pub(crate) ctx: DeviceCtx,
+}
+
+impl Drop for BindlessImageSlot {
+    fn drop(&mut self) {
+        if let Some(bindless) = self.ctx.bindless() {
+            bindless.images.lock().unwrap().free.push(self.index);
+        }
+    }
+}
+
+/// Update-after-bind descriptor set holding every registered sampled image and sampler, indexed from the shaders :
+///
+/// ```hlsl
+/// [[vk::binding(0, 1)]] Texture2D textures[];
+/// [[vk::binding(1, 1)]] SamplerState samplers[];
+///
+/// textures[NonUniformResourceIndex(material.albedo)].Sample(samplers[material.sampler], uv);
+/// ```
+pub struct BindlessTable {
+    layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    images: Mutex<SlotAllocator>,
+    samplers: Mutex<HashMap<SamplerDesc, u32>>,
+    max_samplers: u32,
+    ctx: DeviceCtx,
+}
+
+impl BindlessTable {
+    pub fn new(ctx: DeviceCtx) -> Result<Self, Error> {
+        // Update after bind descriptors have their own limits
+        let limits = ctx.physical_device().descriptor_indexing_properties(&ctx.instance());
+        let max_images = MAX_BINDLESS_IMAGES
+            .min(limits.max_per_stage_descriptor_update_after_bind_sampled_images)
+            .min(limits.max_descriptor_set_update_after_bind_sampled_images);
+        let max_samplers = MAX_BINDLESS_SAMPLERS
+            .min(limits.max_per_stage_descriptor_update_after_bind_samplers)
+            .min(limits.max_descriptor_set_update_after_bind_samplers);
+        if max_images == 0 || max_samplers == 0 {
+            return Err(anyhow!("Update after bind descriptors are not supported by this device"));
+        }
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(max_images)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(max_samplers)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+                .build(),
+        ];
+        // Unused slots stay unwritten, and slots can be written while the set is bound to pending command buffers
+        let binding_flags = [vk::DescriptorBindingFlags::UPDATE_AFTER_BIND | vk::DescriptorBindingFlags::PARTIALLY_BOUND; 2];
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+            .binding_flags(&binding_flags);
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .bindings(&bindings)
+            .push_next(&mut binding_flags_info);
+        let layout = unsafe { ctx.device().create_descriptor_set_layout(&layout_info, None) }?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::SAMPLED_IMAGE).descriptor_count(max_images).build(),
+            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::SAMPLER).descriptor_count(max_samplers).build(),
+        ];
+        let pool = unsafe {
+            ctx.device().create_descriptor_pool(&vk::DescriptorPoolCreateInfo::builder()
+                .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+                .max_sets(1)
+                .pool_sizes(&pool_sizes)
+                .build(), None)
+        }?;
+
+        let layouts = [layout];
+        let set = unsafe {
+            ctx.device().allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts)
+                .build())
+        }?[0];
+
+        Ok(Self {
+            layout,
+            pool,
+            set,
+            images: Mutex::new(SlotAllocator { next: 0, capacity: max_images, free: vec![] }),
+            samplers: Default::default(),
+            max_samplers,
+            ctx,
+        })
+    }
+
+    pub fn layout(&self) -> &vk::DescriptorSetLayout {
+        &self.layout
+    }
+
+    pub fn ptr(&self) -> &vk::DescriptorSet {
+        &self.set
+    }
+
+    /// Write the view in a free slot. Prefer `Image::bindless_index`, which recycles the slot when the image is destroyed.
+    pub fn register_image(&self, view: vk::ImageView, layout: vk::ImageLayout) -> Result<u32, Error> {
+        let index = self.images.lock().unwrap().allocate()?;
+        let image_info = [vk::DescriptorImageInfo::builder().image_view(view).image_layout(layout).build()];
+        self.write(0, index, vk::DescriptorType::SAMPLED_IMAGE, &image_info);
+        Ok(index)
+    }
+
+    /// Index of the shared sampler matching the description. Samplers live as long as the device.
+    pub fn sampler_index(&self, desc: &SamplerDesc) -> Result<u32, Error> {
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some(index) = samplers.get(desc) {
+            return Ok(*index);
+        }
+        let index = samplers.len() as u32;
+        if index >= self.max_samplers {
+            return Err(anyhow!("Bindless table is full ({} samplers)", self.max_samplers));
+        }
+        let sampler = self.ctx.sampler(desc)?;
+        let image_info = [vk::DescriptorImageInfo::builder().sampler(*sampler.ptr()).build()];
+        self.write(1, index, vk::DescriptorType::SAMPLER, &image_info);
+        samplers.insert(*desc, index);
+        Ok(index)
+    }
+
+    fn write(&self, binding: u32, index: u32, descriptor_type: vk::DescriptorType, image_info: &[vk::DescriptorImageInfo]) {
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(binding)
+            .dst_array_element(index)
+            .descriptor_type(descriptor_type)
+            .image_info(image_info)
+            .build();
+        unsafe { self.ctx.device().update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]); }
+    }
+}
+
+impl Drop for BindlessTable {
+    fn drop(&mut self) {
+        unsafe {
+            self.ctx.device().destroy_descriptor_pool(self.pool, None);
+            self.ctx.device().destroy_descriptor_set_layout(self.layout, None);
+        }
+    }
+}
@@ -1,14 +1,16 @@
+use crate::core::gfx::bindless::BINDLESS_SET;
 use crate::core::gfx::device::{DeviceCtx};
 use crate::core::gfx::resources::buffer::BufferMemory;
 use crate::core::gfx::resources::descriptor_sets::DescriptorSets;
 use crate::core::gfx::resources::mesh::Mesh;
-use crate::core::gfx::resources::pipeline::Pipeline;
+use crate::core::gfx::resources::buffer::Buffer;
+use crate::core::gfx::resources::pipeline::PipelineObject;
 use anyhow::{anyhow, Error};
 use std::collections::HashMap;
 use std::thread;
 use types::rwslock::RwSLock;
 use vulkanalia::vk;
-use vulkanalia::vk::{CommandBufferBeginInfo, CommandBufferResetFlags, CommandBufferUsageFlags, DeviceV1_0, Handle, HasBuilder};
+use vulkanalia::vk::{CommandBufferBeginInfo, CommandBufferResetFlags, CommandBufferUsageFlags, DeviceV1_0, DeviceV1_3, ExtExtendedDynamicStateExtension, Handle, HasBuilder};
 use crate::core::gfx::queues::QueueFlag;
 
 pub struct CommandPool {
@@ -130,24 +132,41 @@ impl CommandBuffer {
         self.command_buffer.as_ref().ok_or(anyhow!("Invalid command buffer"))
     }
 
-    pub fn bind_pipeline(&self, program: &Pipeline) {
+    pub fn bind_pipeline(&self, program: &impl PipelineObject) {
         unsafe {
             self.ctx.device().cmd_bind_pipeline(
                 self.command_buffer.unwrap(),
-                vk::PipelineBindPoint::GRAPHICS,
+                program.bind_point(),
                 *program.ptr_pipeline(),
             );
         }
     }
 
-    pub fn bind_descriptors(&self, pipeline: &Pipeline, descriptors: &DescriptorSets) {
+    /// Bind the descriptors at index `set` of the pipeline layout.
+    /// One dynamic offset per dynamic uniform / storage buffer of the set, in binding order
+    pub fn bind_descriptors(&self, pipeline: &impl PipelineObject, set: u32, descriptors: &DescriptorSets, dynamic_offsets: &[u32]) {
         unsafe {
             self.ctx.device().cmd_bind_descriptor_sets(
                 self.command_buffer.unwrap(),
-                vk::PipelineBindPoint::GRAPHICS,
-                *pipeline.ptr_pipeline_layout(),
-                0,
+                pipeline.bind_point(),
+                *pipeline.layout().ptr(),
+                set,
                 &[*descriptors.ptr().unwrap()],
+                dynamic_offsets,
+            );
+        }
+    }
+
+    /// Bind the device's bindless table, for pipelines created with `PipelineConfig::bindless`
+    pub fn bind_bindless(&self, pipeline: &impl PipelineObject) {
+        let bindless = self.ctx.bindless().expect("Bindless table is not available on this device");
+        unsafe {
+            self.ctx.device().cmd_bind_descriptor_sets(
+                self.command_buffer.unwrap(),
+                pipeline.bind_point(),
+                *pipeline.layout().ptr(),
+                BINDLESS_SET,
+                &[*bindless.ptr()],
                 &[],
             );
         }
@@ -249,9 +268,66 @@ impl CommandBuffer {
         }
     }
 
-    pub fn push_constant(&self, pipeline: &Pipeline, data: &BufferMemory, stage: vk::ShaderStageFlags) {
+    /// Requires the DEPTH_BIAS dynamic state
+    pub fn set_depth_bias(&self, constant_factor: f32, clamp: f32, slope_factor: f32) {
+        unsafe { self.ctx.device().cmd_set_depth_bias(self.command_buffer.unwrap(), constant_factor, clamp, slope_factor) }
+    }
+
+    /// Requires the STENCIL_REFERENCE dynamic state
+    pub fn set_stencil_reference(&self, faces: vk::StencilFaceFlags, reference: u32) {
+        unsafe { self.ctx.device().cmd_set_stencil_reference(self.command_buffer.unwrap(), faces, reference) }
+    }
+
+    /// Requires the LINE_WIDTH dynamic state. Widths other than 1.0 require the wideLines feature.
+    pub fn set_line_width(&self, line_width: f32) {
+        unsafe { self.ctx.device().cmd_set_line_width(self.command_buffer.unwrap(), line_width) }
+    }
+
+    /// Requires the CULL_MODE dynamic state, which is only accepted when the device supports extended dynamic states
+    pub fn set_cull_mode(&self, cull_mode: vk::CullModeFlags) {
+        if self.ctx.extended_dynamic_state_ext() {
+            unsafe { self.ctx.device().cmd_set_cull_mode_ext(self.command_buffer.unwrap(), cull_mode) }
+        } else {
+            unsafe { self.ctx.device().cmd_set_cull_mode(self.command_buffer.unwrap(), cull_mode) }
+        }
+    }
+
+    pub fn push_constant(&self, pipeline: &impl PipelineObject, data: &BufferMemory, stage: vk::ShaderStageFlags) {
         unsafe {
-            self.ctx.device().cmd_push_constants(self.command_buffer.unwrap(), *pipeline.ptr_pipeline_layout(), stage, 0, data.as_slice())
+            self.ctx.device().cmd_push_constants(self.command_buffer.unwrap(), *pipeline.layout().ptr(), stage, 0, data.as_slice())
+        }
+    }
+
+    /// Group counts, in workgroups. A compute pipeline must be bound.
+    pub fn dispatch(&self, group_count_x: u32, group_count_y: u32, group_count_z: u32) {
+        unsafe { self.ctx.device().cmd_dispatch(self.command_buffer.unwrap(), group_count_x, group_count_y, group_count_z) }
+    }
+
+    /// Group counts read from a `vk::DispatchIndirectCommand` stored at `offset` in the buffer
+    pub fn dispatch_indirect(&self, buffer: &Buffer, offset: vk::DeviceSize) {
+        unsafe { self.ctx.device().cmd_dispatch_indirect(self.command_buffer.unwrap(), *buffer.ptr().unwrap(), offset) }
+    }
+
+    /// Make the writes to a range of the buffer visible to the next accesses
+    pub fn buffer_barrier(&self, buffer: &Buffer, offset: vk::DeviceSize, size: vk::DeviceSize, src: (vk::PipelineStageFlags, vk::AccessFlags), dst: (vk::PipelineStageFlags, vk::AccessFlags)) {
+        let barrier = vk::BufferMemoryBarrier::builder()
+            .buffer(*buffer.ptr().unwrap())
+            .offset(offset)
+            .size(size)
+            .src_access_mask(src.1)
+            .dst_access_mask(dst.1)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .build();
+        unsafe {
+            self.ctx.device().cmd_pipeline_barrier(
+                self.command_buffer.unwrap(),
+                src.0,
+                dst.0,
+                vk::DependencyFlags::empty(),
+                &[] as &[vk::MemoryBarrier],
+                &[barrier],
+                &[] as &[vk::ImageMemoryBarrier])
         }
     }
 }
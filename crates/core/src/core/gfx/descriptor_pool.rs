@@ -1,43 +1,197 @@
 use crate::core::gfx::device::DeviceCtx;
 use anyhow::{anyhow, Error};
-use std::sync::RwLock;
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use tracing::info;
+use types::profiler::Profiler;
 use vulkanalia::vk;
 use vulkanalia::vk::{DeviceV1_0, HasBuilder};
+use winit::window::WindowId;
 
-const MAX_DESC_PER_TYPE: u32 = 1024u32;
-const MAX_DESC_PER_POOL: u32 = 1024u32;
+const INITIAL_SETS_PER_POOL: u32 = 256u32;
+const MAX_SETS_PER_POOL: u32 = 4096u32;
 
+// Descriptors reserved per set for each type
+const DESCRIPTORS_PER_SET: [(vk::DescriptorType, u32); 11] = [
+    (vk::DescriptorType::SAMPLER, 2),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+    (vk::DescriptorType::SAMPLED_IMAGE, 4),
+    (vk::DescriptorType::STORAGE_IMAGE, 1),
+    (vk::DescriptorType::UNIFORM_TEXEL_BUFFER, 1),
+    (vk::DescriptorType::STORAGE_TEXEL_BUFFER, 1),
+    (vk::DescriptorType::UNIFORM_BUFFER, 2),
+    (vk::DescriptorType::STORAGE_BUFFER, 2),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 1),
+];
+
+/// Descriptor set allocated from the persistent pools, to be given back with [`DescriptorPool::free`]
+#[derive(Clone, Debug)]
+pub struct DescriptorAllocation {
+    pub set: vk::DescriptorSet,
+    pool: vk::DescriptorPool,
+    descriptor_counts: Vec<vk::DescriptorPoolSize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DescriptorPoolStats {
+    pub pools: usize,
+    pub allocated_sets: u32,
+    pub capacity_sets: u32,
+    /// Descriptors in use, per type
+    pub descriptors: HashMap<vk::DescriptorType, u32>,
+    pub transient_pools: usize,
+    pub transient_sets: u32,
+}
+
+impl DescriptorPoolStats {
+    /// Publish the statistics as profiler counters
+    pub fn report(&self) {
+        let profiler = Profiler::get();
+        profiler.set_counter("Descriptor pools", self.pools as u64);
+        profiler.set_counter("Descriptor sets", self.allocated_sets as u64);
+        profiler.set_counter("Descriptor sets capacity", self.capacity_sets as u64);
+        profiler.set_counter("Transient descriptor pools", self.transient_pools as u64);
+        profiler.set_counter("Transient descriptor sets", self.transient_sets as u64);
+        for (descriptor_type, count) in &self.descriptors {
+            profiler.set_counter(format!("Descriptors {:?}", descriptor_type).as_str(), *count as u64);
+        }
+    }
+}
+
+struct PoolBlock {
+    pool: vk::DescriptorPool,
+    max_sets: u32,
+    allocated_sets: u32,
+}
+
+// Pools of growing size, a new one is created once all the others are exhausted
+struct PoolChain {
+    blocks: Vec<PoolBlock>,
+    free_sets: bool,
+}
+
+impl PoolChain {
+    fn new(free_sets: bool) -> Self {
+        Self { blocks: vec![], free_sets }
+    }
+
+    fn capacity(&self) -> u32 {
+        self.blocks.iter().map(|block| block.max_sets).sum()
+    }
+
+    fn allocated(&self) -> u32 {
+        self.blocks.iter().map(|block| block.allocated_sets).sum()
+    }
+
+    /// `descriptor_counts` are the descriptors of the layout : a new pool is sized to hold them when every existing pool is exhausted
+    fn allocate(&mut self, device: &vulkanalia::Device, layout: vk::DescriptorSetLayout, descriptor_counts: &[vk::DescriptorPoolSize]) -> Result<(vk::DescriptorSet, vk::DescriptorPool), Error> {
+        let layouts = [layout];
+        // Most recent pools are the most likely to have space left
+        for block in self.blocks.iter_mut().rev() {
+            if block.allocated_sets >= block.max_sets {
+                continue;
+            }
+            let descriptor_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(block.pool)
+                .set_layouts(&layouts)
+                .build();
+            match unsafe { device.allocate_descriptor_sets(&descriptor_info) } {
+                Ok(sets) => {
+                    block.allocated_sets += 1;
+                    return Ok((sets[0], block.pool));
+                }
+                Err(vk::ErrorCode::OUT_OF_POOL_MEMORY) | Err(vk::ErrorCode::FRAGMENTED_POOL) => {}
+                Err(err) => { return Err(anyhow!("Failed to allocate descriptor set : {}", err)) }
+            }
+        }
+
+        let max_sets = match self.blocks.last() {
+            None => { INITIAL_SETS_PER_POOL }
+            Some(block) => { (block.max_sets * 2).min(MAX_SETS_PER_POOL) }
+        };
+        let pool = create_pool(device, max_sets, self.free_sets, descriptor_counts)?;
+        let descriptor_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts)
+            .build();
+        // Don't keep a pool that can't even hold this layout
+        let set = match unsafe { device.allocate_descriptor_sets(&descriptor_info) } {
+            Ok(sets) => { sets[0] }
+            Err(err) => {
+                unsafe { device.destroy_descriptor_pool(pool, None); }
+                return Err(anyhow!("Failed to allocate descriptor set from a new pool : {}", err));
+            }
+        };
+        self.blocks.push(PoolBlock { pool, max_sets, allocated_sets: 1 });
+        info!("Created a descriptor pool of {} sets ({} pools)", max_sets, self.blocks.len());
+        Ok((set, pool))
+    }
+
+    fn free(&mut self, device: &vulkanalia::Device, set: vk::DescriptorSet, pool: vk::DescriptorPool) -> Result<(), Error> {
+        let block = self.blocks.iter_mut().find(|block| block.pool == pool).ok_or(anyhow!("Descriptor set doesn't belong to this pool"))?;
+        unsafe { device.free_descriptor_sets(pool, &[set]) }?;
+        block.allocated_sets -= 1;
+        Ok(())
+    }
+
+    fn reset(&mut self, device: &vulkanalia::Device) -> Result<(), Error> {
+        for block in &mut self.blocks {
+            if block.allocated_sets > 0 {
+                unsafe { device.reset_descriptor_pool(block.pool, vk::DescriptorPoolResetFlags::empty()) }?;
+                block.allocated_sets = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn destroy(&mut self, device: &vulkanalia::Device) {
+        for block in self.blocks.drain(..) {
+            unsafe { device.destroy_descriptor_pool(block.pool, None); }
+        }
+    }
+}
+
+/// Each set gets the default descriptors per type, or the descriptors of the given layout when it needs more
+fn create_pool(device: &vulkanalia::Device, max_sets: u32, free_sets: bool, descriptor_counts: &[vk::DescriptorPoolSize]) -> Result<vk::DescriptorPool, Error> {
+    let mut per_set = DESCRIPTORS_PER_SET.iter().copied().collect::<HashMap<_, _>>();
+    for size in descriptor_counts {
+        let count = per_set.entry(size.type_).or_default();
+        *count = (*count).max(size.descriptor_count);
+    }
+    let pool_sizes = per_set.iter()
+        .map(|(descriptor_type, count)| vk::DescriptorPoolSize::builder().type_(*descriptor_type).descriptor_count(max_sets * count).build())
+        .collect::<Vec<_>>();
+    let flags = if free_sets { vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET } else { vk::DescriptorPoolCreateFlags::empty() };
+    Ok(unsafe {
+        device.create_descriptor_pool(&vk::DescriptorPoolCreateInfo::builder()
+            .flags(flags)
+            .max_sets(max_sets)
+            .pool_sizes(pool_sizes.as_slice())
+            .build(), None)
+    }?)
+}
+
+/// Growable descriptor allocator.
+/// Persistent sets are freed one by one, transient sets live for a frame of a window and are reset in bulk.
 pub struct DescriptorPool {
-    pool: Option<vk::DescriptorPool>,
+    persistent: Mutex<PoolChain>,
+    transient: Mutex<HashMap<(WindowId, usize), PoolChain>>,
+    descriptors: Mutex<HashMap<vk::DescriptorType, u32>>,
     device: RwLock<Option<DeviceCtx>>,
 }
 
 impl DescriptorPool {
     pub fn new(device: &vulkanalia::Device) -> Result<Self, Error> {
-        let pool_sizes = vec![
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::SAMPLER).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::SAMPLED_IMAGE).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::STORAGE_IMAGE).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::UNIFORM_TEXEL_BUFFER).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::STORAGE_TEXEL_BUFFER).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::UNIFORM_BUFFER).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::STORAGE_BUFFER).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC).descriptor_count(MAX_DESC_PER_TYPE).build(),
-            vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::INPUT_ATTACHMENT).descriptor_count(MAX_DESC_PER_TYPE).build(),
-        ];
-
-        let pool = unsafe {
-            device.create_descriptor_pool(&vk::DescriptorPoolCreateInfo::builder()
-                .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
-                .max_sets(MAX_DESC_PER_POOL)
-                .pool_sizes(pool_sizes.as_slice())
-                .build(), None)
-        }?;
+        let mut persistent = PoolChain::new(true);
+        let pool = create_pool(device, INITIAL_SETS_PER_POOL, true, &[])?;
+        persistent.blocks.push(PoolBlock { pool, max_sets: INITIAL_SETS_PER_POOL, allocated_sets: 0 });
 
         Ok(Self {
-            pool: Some(pool),
+            persistent: Mutex::new(persistent),
+            transient: Default::default(),
+            descriptors: Default::default(),
             device: Default::default(),
         })
     }
@@ -46,28 +200,84 @@ impl DescriptorPool {
         *self.device.write().unwrap() = Some(device_data);
     }
 
-    pub fn ptr(&self) -> Result<&vk::DescriptorPool, Error> {
-        self.pool.as_ref().ok_or(anyhow!("Descriptor pool is not valid"))
+    /// Allocate a set that stays valid until freed. The descriptor counts of the layout size new pools and are used for the statistics.
+    pub fn allocate(&self, layout: vk::DescriptorSetLayout, descriptor_counts: &[vk::DescriptorPoolSize]) -> Result<DescriptorAllocation, Error> {
+        let device = self.device.read().unwrap();
+        let (set, pool) = self.persistent.lock().unwrap().allocate(device.as_ref().unwrap().device(), layout, descriptor_counts)?;
+        let mut descriptors = self.descriptors.lock().unwrap();
+        for size in descriptor_counts {
+            *descriptors.entry(size.type_).or_default() += size.descriptor_count;
+        }
+        Ok(DescriptorAllocation { set, pool, descriptor_counts: descriptor_counts.to_vec() })
     }
 
-    pub fn allocate(&self, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet, Error> {
-        let layouts = vec![layout];
-        let descriptor_info = vk::DescriptorSetAllocateInfo::builder()
-            .descriptor_pool(self.pool.unwrap())
-            .set_layouts(layouts.as_slice())
-            .build();
-        Ok(unsafe { self.device.read().unwrap().as_ref().unwrap().device().allocate_descriptor_sets(&descriptor_info)?[0] })
+    pub fn free(&self, allocation: &DescriptorAllocation) -> Result<(), Error> {
+        let device = self.device.read().unwrap();
+        self.persistent.lock().unwrap().free(device.as_ref().unwrap().device(), allocation.set, allocation.pool)?;
+        let mut descriptors = self.descriptors.lock().unwrap();
+        for size in &allocation.descriptor_counts {
+            if let Some(count) = descriptors.get_mut(&size.type_) {
+                *count -= size.descriptor_count;
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocate a set for the frame being recorded. It is released when the frame is reset, and must not be freed.
+    pub fn allocate_transient(&self, layout: vk::DescriptorSetLayout, descriptor_counts: &[vk::DescriptorPoolSize]) -> Result<vk::DescriptorSet, Error> {
+        let device = self.device.read().unwrap();
+        let device = device.as_ref().unwrap();
+        let key = {
+            let instance = device.instance();
+            let engine = instance.engine();
+            (engine.current_rendering_window(), engine.current_frame())
+        };
+        let mut transient = self.transient.lock().unwrap();
+        let (set, _) = transient.entry(key).or_insert_with(|| PoolChain::new(false)).allocate(device.device(), layout, descriptor_counts)?;
+        Ok(set)
     }
 
-    pub fn free(&self, set: vk::DescriptorSet) -> Result<(), Error> {
-        let set = vec![set];
-        unsafe { self.device.read().unwrap().as_ref().unwrap().device().free_descriptor_sets(self.pool.unwrap(), set.as_slice()) }?;
+    /// Release every transient set of this window's frame. Its fence should have been signaled.
+    pub fn reset_transient(&self, window: WindowId, frame: usize) -> Result<(), Error> {
+        let device = self.device.read().unwrap();
+        if let Some(chain) = self.transient.lock().unwrap().get_mut(&(window, frame)) {
+            chain.reset(device.as_ref().unwrap().device())?;
+        }
         Ok(())
     }
+
+    /// Destroy the transient pools of a closed window. None of its frames should still be in flight.
+    pub fn release_window(&self, window: WindowId) {
+        let device = self.device.read().unwrap();
+        self.transient.lock().unwrap().retain(|(pool_window, _), chain| {
+            if *pool_window == window {
+                chain.destroy(device.as_ref().unwrap().device());
+            }
+            *pool_window != window
+        });
+    }
+
+    pub fn stats(&self) -> DescriptorPoolStats {
+        let persistent = self.persistent.lock().unwrap();
+        let transient = self.transient.lock().unwrap();
+        DescriptorPoolStats {
+            pools: persistent.blocks.len(),
+            allocated_sets: persistent.allocated(),
+            capacity_sets: persistent.capacity(),
+            descriptors: self.descriptors.lock().unwrap().clone(),
+            transient_pools: transient.values().map(|chain| chain.blocks.len()).sum(),
+            transient_sets: transient.values().map(|chain| chain.allocated()).sum(),
+        }
+    }
 }
 
 impl Drop for DescriptorPool {
     fn drop(&mut self) {
-        unsafe { self.device.read().unwrap().as_ref().unwrap().device().destroy_descriptor_pool(self.pool.unwrap(), None); }
+        let device = self.device.read().unwrap();
+        let device = device.as_ref().unwrap().device();
+        self.persistent.lock().unwrap().destroy(device);
+        for chain in self.transient.lock().unwrap().values_mut() {
+            chain.destroy(device);
+        }
     }
-}
\ No newline at end of file
+}
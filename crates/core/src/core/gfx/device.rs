@@ -6,17 +6,21 @@ use std::sync::{RwLock};
 use anyhow::{anyhow, Error};
 use tracing::{info, warn};
 use vulkanalia::{vk};
-use vulkanalia::vk::{DeviceV1_0, FenceCreateFlags, HasBuilder};
+use vulkanalia::vk::{DeviceV1_0, FenceCreateFlags, HasBuilder, InstanceV1_0};
 use winit::window::WindowId;
 use types::resource_handle::{Resource, ResourceHandle};
+use crate::core::gfx::bindless::BindlessTable;
 use crate::core::gfx::command_buffer::CommandPool;
 use crate::core::gfx::descriptor_pool::DescriptorPool;
+use crate::core::gfx::pipeline_cache::PipelineCache;
 use crate::core::gfx::frame_graph::renderer::{RenderPassObject};
-use crate::core::gfx::frame_graph::frame_graph_definition::{RenderPass, RenderPassName};
+use crate::core::gfx::frame_graph::frame_graph_definition::{ClearValues, LoadOp, RenderPass, RenderPassAttachment, RenderPassName, RenderTarget, StoreOp, SubpassAttachment};
+use crate::core::gfx::resources::image::{format_aspects, format_is_sint, format_is_uint};
+use crate::core::gfx::resources::sampler::{Sampler, SamplerDesc};
 use crate::core::gfx::instance::{GfxConfig, InstanceCtx};
 use crate::core::gfx::physical_device::PhysicalDevice;
 use crate::core::gfx::queues::{QueueFlag, Queues};
-use crate::core::gfx::surface::{SurfaceCtx};
+use crate::core::gfx::surface::{Surface};
 
 #[derive(Default)]
 pub struct Fence {
@@ -79,8 +83,18 @@ pub struct Device {
     device: vulkanalia::Device,
     allocator: MaybeUninit<vulkanalia_vma::Allocator>,
     descriptor_pool: MaybeUninit<DescriptorPool>,
+    pipeline_cache: PipelineCache,
     command_pool: HashMap<QueueFlag, Rc<CommandPool>>,
     queues: Queues,
+    dynamic_rendering: bool,
+    dynamic_rendering_khr: bool,
+    extended_dynamic_state: bool,
+    extended_dynamic_state_ext: bool,
+    load_store_op_none: bool,
+    bindless: Option<BindlessTable>,
+    sampler_anisotropy: bool,
+    features: vk::PhysicalDeviceFeatures,
+    samplers: RwLock<HashMap<SamplerDesc, Resource<Sampler>>>,
     present_passes: RwLock<HashMap<WindowId, Resource<RenderPassObject>>>,
     render_passes: RwLock<HashMap<String, Resource<RenderPassObject>>>,
     self_ref: DeviceCtx,
@@ -89,7 +103,8 @@ pub struct Device {
 }
 
 impl Device {
-    pub fn new(ctx: InstanceCtx, surface: &SurfaceCtx, config: &GfxConfig) -> Result<Resource<Self>, Error> {
+    /// Devices created without a surface can't present
+    pub fn new(ctx: InstanceCtx, surface: Option<&Surface>, config: &GfxConfig) -> Result<Resource<Self>, Error> {
         let physical_device = PhysicalDevice::new(&ctx, surface, config)?;
         let queues = Queues::search(ctx.clone(), physical_device.ptr(), surface);
 
@@ -111,37 +126,107 @@ impl Device {
                 .queue_priorities(queue_priorities));
         }
 
-        let extensions = config.required_extensions
+        let mut extensions = config.required_extensions
             .iter()
             .map(|n| n.as_ptr())
             .collect::<Vec<_>>();
 
-        let features = vk::PhysicalDeviceFeatures::builder();
+        // Optional : LoadOp::None and StoreOp::None
+        let load_store_op_none = physical_device.supports_extension(&ctx, vk::KHR_LOAD_STORE_OP_NONE_EXTENSION.name);
+        if load_store_op_none {
+            extensions.push(vk::KHR_LOAD_STORE_OP_NONE_EXTENSION.name.as_ptr());
+        }
+
+        let supported_features = unsafe { ctx.ptr().get_physical_device_features(*physical_device.ptr()) };
+        let sampler_anisotropy = supported_features.sampler_anisotropy == vk::TRUE;
+        // Optional pipeline states, pipelines using them are rejected when they are missing
+        let features = vk::PhysicalDeviceFeatures::builder()
+            .sampler_anisotropy(sampler_anisotropy)
+            .depth_bounds(supported_features.depth_bounds == vk::TRUE)
+            .wide_lines(supported_features.wide_lines == vk::TRUE)
+            .build();
+
+        let dynamic_rendering = config.dynamic_rendering && physical_device.supports_dynamic_rendering(&ctx);
+        if config.dynamic_rendering && !dynamic_rendering {
+            warn!("Dynamic rendering is not supported by this device : falling back to render passes");
+        }
+        // Vulkan 1.2 devices expose it through VK_KHR_dynamic_rendering, and can't be given the 1.3 features
+        let core_13 = physical_device.properties().api_version >= vk::make_version(1, 3, 0);
+        let dynamic_rendering_khr = dynamic_rendering && !core_13;
+        if dynamic_rendering_khr {
+            extensions.push(vk::KHR_DYNAMIC_RENDERING_EXTENSION.name.as_ptr());
+        }
+        let mut features_13 = vk::PhysicalDeviceVulkan13Features::builder()
+            .dynamic_rendering(dynamic_rendering);
+        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeaturesKHR::builder()
+            .dynamic_rendering(dynamic_rendering);
+
+        // Core since Vulkan 1.3, VK_EXT_extended_dynamic_state before
+        let extended_dynamic_state_ext = !core_13 && physical_device.supports_extended_dynamic_state(&ctx);
+        if extended_dynamic_state_ext {
+            extensions.push(vk::EXT_EXTENDED_DYNAMIC_STATE_EXTENSION.name.as_ptr());
+        }
+        let mut extended_dynamic_state_features = vk::PhysicalDeviceExtendedDynamicStateFeaturesEXT::builder()
+            .extended_dynamic_state(true);
+
+        let bindless = config.bindless && physical_device.supports_descriptor_indexing(&ctx);
+        if config.bindless && !bindless {
+            warn!("Descriptor indexing is not supported by this device : bindless table is disabled");
+        }
+        let mut features_12 = vk::PhysicalDeviceVulkan12Features::builder()
+            .descriptor_indexing(bindless)
+            .runtime_descriptor_array(bindless)
+            .descriptor_binding_partially_bound(bindless)
+            .descriptor_binding_sampled_image_update_after_bind(bindless)
+            .shader_sampled_image_array_non_uniform_indexing(bindless);
 
         let layers = if config.validation_layers {
             vec![crate::core::gfx::instance::VALIDATION_LAYER.as_ptr()]
         } else {
             Vec::new()
         };
-        let info = vk::DeviceCreateInfo::builder()
+        let mut info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(queue_info.as_slice())
             .enabled_layer_names(layers.as_slice())
             .enabled_extension_names(&extensions)
             .enabled_features(&features);
+        // Vulkan 1.1 devices can't be given the 1.2 features
+        if physical_device.properties().api_version >= vk::make_version(1, 2, 0) {
+            info = info.push_next(&mut features_12);
+        }
+        if core_13 {
+            info = info.push_next(&mut features_13);
+        } else if dynamic_rendering_khr {
+            info = info.push_next(&mut dynamic_rendering_features);
+        }
+        if extended_dynamic_state_ext {
+            info = info.push_next(&mut extended_dynamic_state_features);
+        }
 
         let device = unsafe { ctx.ptr().create_device(*physical_device.ptr(), &info, None)? };
 
         let infos = vulkanalia_vma::AllocatorOptions::new(ctx.ptr(), &device, *physical_device.ptr());
         let allocator = unsafe { vulkanalia_vma::Allocator::new(&infos) }?;
         let descriptor_pool = DescriptorPool::new(&device)?;
+        let pipeline_cache = PipelineCache::new(&device, physical_device.properties(), config.pipeline_cache_path.clone())?;
 
         let mut device = Resource::new(Self {
             physical_device,
             allocator: MaybeUninit::new(allocator),
             descriptor_pool: MaybeUninit::new(descriptor_pool),
+            pipeline_cache,
             command_pool: HashMap::new(),
             queues,
             device,
+            dynamic_rendering,
+            dynamic_rendering_khr,
+            extended_dynamic_state: core_13 || extended_dynamic_state_ext,
+            extended_dynamic_state_ext,
+            load_store_op_none,
+            bindless: None,
+            sampler_anisotropy,
+            features,
+            samplers: Default::default(),
             instance: ctx.clone(),
             present_passes: RwLock::new(HashMap::new()),
             render_passes: RwLock::new(HashMap::new()),
@@ -158,6 +243,9 @@ impl Device {
         }
         device.self_ref = device.handle();
         device.descriptor_pool().init(device.handle());
+        if bindless {
+            device.bindless = Some(BindlessTable::new(device.handle())?);
+        }
 
         for (index, flags) in unique_queue_indices {
             let pool = Rc::new(CommandPool::new(device.handle(), index)?);
@@ -181,6 +269,53 @@ impl Device {
         &self.physical_device
     }
 
+    /// The frame graph is drawn with dynamic rendering instead of render pass objects
+    pub fn dynamic_rendering(&self) -> bool {
+        self.dynamic_rendering
+    }
+
+    /// Dynamic rendering commands should use the VK_KHR_dynamic_rendering entry points
+    pub fn dynamic_rendering_khr(&self) -> bool {
+        self.dynamic_rendering_khr
+    }
+
+    /// Bindless texture table, if it was requested and is supported
+    pub fn bindless(&self) -> Option<&BindlessTable> {
+        self.bindless.as_ref()
+    }
+
+    /// Anisotropic filtering is enabled on this device
+    pub fn sampler_anisotropy(&self) -> bool {
+        self.sampler_anisotropy
+    }
+
+    /// Core features enabled on this device
+    pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.features
+    }
+
+    /// Cull mode, front face, depth and stencil states can be dynamic (Vulkan 1.3 or VK_EXT_extended_dynamic_state)
+    pub fn extended_dynamic_state(&self) -> bool {
+        self.extended_dynamic_state
+    }
+
+    /// Extended dynamic state commands should use the VK_EXT_extended_dynamic_state entry points
+    pub fn extended_dynamic_state_ext(&self) -> bool {
+        self.extended_dynamic_state_ext
+    }
+
+    /// Shared sampler matching the description, created on first use
+    pub fn sampler(&self, desc: &SamplerDesc) -> Result<ResourceHandle<Sampler>, Error> {
+        if let Some(sampler) = self.samplers.read().unwrap().get(desc) {
+            return Ok(sampler.handle());
+        }
+        let mut samplers = self.samplers.write().unwrap();
+        if !samplers.contains_key(desc) {
+            samplers.insert(*desc, Resource::new(Sampler::new(self.self_ref.clone(), desc)?));
+        }
+        Ok(samplers[desc].handle())
+    }
+
     pub fn allocator(&self) -> &vulkanalia_vma::Allocator {
         unsafe { self.allocator.assume_init_ref() }
     }
@@ -189,6 +324,10 @@ impl Device {
         unsafe { self.descriptor_pool.assume_init_ref() }
     }
 
+    pub fn pipeline_cache(&self) -> &PipelineCache {
+        &self.pipeline_cache
+    }
+
     pub fn command_pool(&self, flags: &QueueFlag) -> &CommandPool {
         self.command_pool.get(flags).expect("Command pool is not available").as_ref()
     }
@@ -214,8 +353,22 @@ impl Device {
         locks.clear();
     }
 
+    /// Check that a render pass declaration can be created on this device
+    pub fn validate_render_pass(&self, render_pass: &RenderPass) -> Result<(), Error> {
+        if render_pass.color_attachments.is_empty() && render_pass.depth_attachment.is_none() {
+            return Err(anyhow!("Render pass {} doesn't have any attachment", render_pass.name));
+        }
+        self.validate_sample_counts(render_pass)?;
+        self.validate_load_store_ops(render_pass)?;
+        Self::validate_subpasses(render_pass)?;
+        if self.dynamic_rendering && render_pass.subpasses.len() > 1 {
+            return Err(anyhow!("Render pass {} : multiple subpasses are not supported with dynamic rendering", render_pass.name));
+        }
+        Ok(())
+    }
+
     pub fn declare_render_pass(&self, render_pass: RenderPass) -> Result<RenderPassName, Error> {
-        assert!(!(render_pass.color_attachments.is_empty() && render_pass.depth_attachment.is_none()));
+        self.validate_render_pass(&render_pass)?;
         match &render_pass.name {
             RenderPassName::Present(window) => {
                 if self.present_passes.read().unwrap().contains_key(&window.id()?) {
@@ -235,6 +388,163 @@ impl Device {
     }
 
 
+    /// Declare or overwrite a render pass. The previous declaration is returned and should be kept alive until no renderer uses it anymore.
+    pub fn replace_render_pass(&self, render_pass: RenderPass) -> Result<Option<Resource<RenderPassObject>>, Error> {
+        self.validate_render_pass(&render_pass)?;
+        Ok(match &render_pass.name {
+            RenderPassName::Present(window) => {
+                self.present_passes.write().unwrap().insert(window.id()?, RenderPassObject::new(self.self_ref.clone(), &render_pass))
+            }
+            RenderPassName::Named(name) => {
+                self.render_passes.write().unwrap().insert(name.clone(), RenderPassObject::new(self.self_ref.clone(), &render_pass))
+            }
+        })
+    }
+
+    /// Same as [`Device::replace_render_pass`] for several passes : none of them is replaced if one is invalid
+    pub fn replace_render_passes(&self, render_passes: Vec<RenderPass>) -> Result<Vec<Resource<RenderPassObject>>, Error> {
+        let mut objects = vec![];
+        for render_pass in &render_passes {
+            self.validate_render_pass(render_pass)?;
+            let window = match &render_pass.name {
+                RenderPassName::Present(window) => { Some(window.id()?) }
+                RenderPassName::Named(_) => { None }
+            };
+            objects.push((window, RenderPassObject::new(self.self_ref.clone(), render_pass)));
+        }
+
+        let mut present_passes = self.present_passes.write().unwrap();
+        let mut named_passes = self.render_passes.write().unwrap();
+        let mut previous = vec![];
+        for (render_pass, (window, object)) in render_passes.iter().zip(objects) {
+            let replaced = match (&render_pass.name, window) {
+                (RenderPassName::Named(name), _) => { named_passes.insert(name.clone(), object) }
+                (RenderPassName::Present(_), Some(window)) => { present_passes.insert(window, object) }
+                (RenderPassName::Present(_), None) => { unreachable!() }
+            };
+            previous.extend(replaced);
+        }
+        Ok(previous)
+    }
+
+    fn validate_sample_counts(&self, render_pass: &RenderPass) -> Result<(), Error> {
+        let limits = &self.physical_device.properties().limits;
+        let attachments = render_pass.color_attachments.iter().map(|attachment| (attachment, limits.framebuffer_color_sample_counts))
+            .chain(render_pass.depth_attachment.iter().map(|attachment| (attachment, limits.framebuffer_depth_sample_counts)));
+
+        let mut pass_samples = None;
+        for (attachment, supported_samples) in attachments {
+            if attachment.samples.bits().count_ones() != 1 {
+                return Err(anyhow!("Render pass {} : attachments should use exactly one sample count", render_pass.name));
+            }
+            if !supported_samples.contains(attachment.samples) {
+                return Err(anyhow!("Render pass {} : {:?} samples are not supported by this device", render_pass.name, attachment.samples));
+            }
+            if *pass_samples.get_or_insert(attachment.samples) != attachment.samples {
+                return Err(anyhow!("Render pass {} : every attachment should use the same sample count", render_pass.name));
+            }
+        }
+        if let Some(depth) = &render_pass.depth_attachment {
+            if depth.is_resolved() {
+                return Err(anyhow!("Render pass {} : multisampled depth attachments cannot be resolved and should be transient", render_pass.name));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_load_store_ops(&self, render_pass: &RenderPass) -> Result<(), Error> {
+        // Window targets use the format of the swapchain, which is never an integer format
+        let attachment_format = |attachment: &RenderPassAttachment| match &attachment.source {
+            RenderTarget::Window => { None }
+            RenderTarget::Image(image) => { Some(image.format()) }
+            RenderTarget::Internal(format) => { Some(*format) }
+        };
+        for attachment in &render_pass.color_attachments {
+            let format = attachment_format(attachment);
+            let matches_format = match attachment.clear_value {
+                ClearValues::Color(_) => { !format.is_some_and(|format| format_is_sint(format) || format_is_uint(format)) }
+                ClearValues::ColorInt(_) => { format.is_some_and(format_is_sint) }
+                ClearValues::ColorUint(_) => { format.is_some_and(format_is_uint) }
+                _ => { true }
+            };
+            if !matches_format {
+                return Err(anyhow!("Render pass {} : {:?} doesn't match the attachment format {}", render_pass.name, attachment.clear_value,
+                    format.map_or("of the window".to_string(), |format| format!("{format:?}"))));
+            }
+        }
+        let depth_format = render_pass.depth_attachment.as_ref().and_then(attachment_format);
+        let attachments = render_pass.color_attachments.iter().map(|attachment| (attachment, None))
+            .chain(render_pass.depth_attachment.iter().map(|attachment| (attachment, depth_format)));
+
+        for (attachment, depth_format) in attachments {
+            let clear = attachment.clear_value;
+            match depth_format {
+                None if clear != ClearValues::DontClear && !clear.is_color() => {
+                    return Err(anyhow!("Render pass {} : color attachments can only be cleared with a color", render_pass.name));
+                }
+                Some(_) if clear.is_color() => {
+                    return Err(anyhow!("Render pass {} : depth attachments cannot be cleared with a color", render_pass.name));
+                }
+                Some(format) if (clear.clears_depth() && !format_aspects(format).contains(vk::ImageAspectFlags::DEPTH)) ||
+                    (clear.clears_stencil() && !format_aspects(format).contains(vk::ImageAspectFlags::STENCIL)) => {
+                    return Err(anyhow!("Render pass {} : {clear:?} doesn't match the aspects of {format:?}", render_pass.name));
+                }
+                _ => {}
+            }
+            if attachment.preserves_content() {
+                // The load op only applies to the aspects which are not cleared
+                let mut loaded_aspects = depth_format.map(format_aspects).unwrap_or(vk::ImageAspectFlags::COLOR);
+                if clear.is_color() {
+                    loaded_aspects.remove(vk::ImageAspectFlags::COLOR);
+                }
+                if clear.clears_depth() {
+                    loaded_aspects.remove(vk::ImageAspectFlags::DEPTH);
+                }
+                if clear.clears_stencil() {
+                    loaded_aspects.remove(vk::ImageAspectFlags::STENCIL);
+                }
+                if loaded_aspects.is_empty() {
+                    return Err(anyhow!("Render pass {} : cleared attachments cannot be loaded", render_pass.name));
+                }
+                if attachment.transient {
+                    return Err(anyhow!("Render pass {} : transient attachments cannot be loaded", render_pass.name));
+                }
+                if attachment.is_resolved() {
+                    return Err(anyhow!("Render pass {} : resolved attachments cannot be loaded", render_pass.name));
+                }
+                // Swapchain images are undefined after the swapchain is created or resized
+                if matches!(attachment.source, RenderTarget::Window) {
+                    return Err(anyhow!("Render pass {} : window attachments cannot be loaded", render_pass.name));
+                }
+            }
+            if !self.load_store_op_none && (attachment.load_op == LoadOp::None || attachment.store_op == StoreOp::None) {
+                return Err(anyhow!("Render pass {} : load and store ops None are not supported by this device", render_pass.name));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_subpasses(render_pass: &RenderPass) -> Result<(), Error> {
+        let is_valid = |attachment: &SubpassAttachment| match attachment {
+            SubpassAttachment::Color(index) => { *index < render_pass.color_attachments.len() }
+            SubpassAttachment::Depth => { render_pass.depth_attachment.is_some() }
+        };
+        for (index, subpass) in render_pass.subpasses.iter().enumerate() {
+            if !subpass.color_attachments.iter().all(|attachment| is_valid(&SubpassAttachment::Color(*attachment))) ||
+                !subpass.input_attachments.iter().all(is_valid) ||
+                (subpass.depth_attachment && !is_valid(&SubpassAttachment::Depth)) {
+                return Err(anyhow!("Render pass {} : subpass {index} references a missing attachment", render_pass.name));
+            }
+        }
+        let subpass_count = render_pass.effective_subpasses().len();
+        for dependency in &render_pass.subpass_dependencies {
+            if dependency.src_subpass > dependency.dst_subpass || dependency.dst_subpass >= subpass_count {
+                return Err(anyhow!("Render pass {} : invalid subpass dependency {} -> {}", render_pass.name, dependency.src_subpass, dependency.dst_subpass));
+            }
+        }
+        Ok(())
+    }
+
     pub fn find_render_pass(&self, render_pass: &RenderPassName) -> Result<ResourceHandle<RenderPassObject>, Error> {
         Ok(match &render_pass {
             RenderPassName::Present(window) => {
@@ -260,6 +570,21 @@ impl Device {
     pub fn free_resources_for_window(&self, window: WindowId, frame: usize) {
         let mut per_image = self.pending_kill_resources.write().unwrap();
         per_image[frame].entry(window).or_default().clear();
+        self.descriptor_pool().reset_transient(window, frame).unwrap();
+    }
+
+    /// Release everything kept for a closed window, once the device is idle
+    pub fn release_window(&self, window: WindowId) {
+        for per_window in self.pending_kill_resources.write().unwrap().iter_mut() {
+            per_window.remove(&window);
+        }
+        self.descriptor_pool().release_window(window);
+    }
+
+    /// Publish the descriptor pool and pipeline cache statistics as profiler counters
+    pub fn report_stats(&self) {
+        self.descriptor_pool().stats().report();
+        self.pipeline_cache.stats().report();
     }
 }
 
@@ -268,9 +593,15 @@ impl Drop for Device {
         unsafe {
             self.render_passes.write().unwrap().clear();
             self.present_passes.write().unwrap().clear();
+            self.samplers.write().unwrap().clear();
             self.pending_kill_resources.write().unwrap().clear();
+            self.bindless = None;
             self.command_pool.clear();
             self.descriptor_pool.assume_init_read();
+            if let Err(err) = self.pipeline_cache.save(&self.device) {
+                warn!("{}", err);
+            }
+            self.pipeline_cache.destroy(&self.device);
             self.allocator.assume_init_read();
             self.device.destroy_device(None);
         }
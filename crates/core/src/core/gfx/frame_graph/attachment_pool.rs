@@ -0,0 +1,411 @@
+use crate::core::gfx::command_buffer::CommandBuffer;
+use crate::core::gfx::device::{DeviceCtx, Fence};
+use crate::core::gfx::frame_graph::frame_graph_definition::{AttachmentSize, RenderPassAttachment, RenderTarget, RendererStage, SubpassAttachment};
+use crate::core::gfx::queues::QueueFlag;
+use crate::core::gfx::resources::image::{Image, ImageCreateOptions};
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use tracing::info;
+use types::resource_handle::{Resource, ResourceHandle};
+use vulkanalia::vk;
+use vulkanalia::vk::HasBuilder;
+use vulkanalia_vma::{Alloc, AllocationOptions, MemoryUsage};
+
+/// Memory used by the internal attachments of a renderer (all frames in flight included)
+#[derive(Copy, Clone, Default, Debug, Serialize)]
+pub struct AttachmentMemoryStats {
+    /// Number of internal attachments declared by the passes of the renderer
+    pub attachments: usize,
+    /// Number of memory blocks shared between these attachments
+    pub memory_blocks: usize,
+    /// Memory that would be required if each attachment had its own allocation
+    pub unaliased_size: vk::DeviceSize,
+    /// Memory actually allocated once attachments with disjoint lifetimes are aliased
+    pub aliased_size: vk::DeviceSize,
+}
+
+struct AttachmentRequest {
+    create_infos: ImageCreateOptions,
+    size: AttachmentSize,
+    // Pass that writes this attachment
+    producer: usize,
+    // Last pass reading it (the parent stage, or the producer itself for transient attachments)
+    consumer: usize,
+    transient: bool,
+    // Content kept from one frame to the next : the memory can't be shared
+    persistent: bool,
+    // Images of the present pass are indexed by target image instead of frame in flight
+    image_count: usize,
+}
+
+struct MemoryBlock {
+    allocations: Vec<vulkanalia_vma::Allocation>,
+    ctx: DeviceCtx,
+}
+
+impl Drop for MemoryBlock {
+    fn drop(&mut self) {
+        for allocation in &self.allocations {
+            unsafe { self.ctx.allocator().free_memory(*allocation) };
+        }
+    }
+}
+
+/// Owns the images of every internal attachment of a renderer.
+/// The lifetime of each attachment is deduced from the stage tree : an attachment is written by its pass then read by the parent stage.
+/// A stage is only submitted once all of its dependencies are finished, so two attachments can share the same memory
+/// when the last reader of the first one is a dependency (direct or not) of the writer of the second one.
+/// Attachments loading the content of the previous frame never share their memory.
+pub struct AttachmentPool {
+    // Parent of each stage, indexed in pre-order
+    parents: Vec<Option<usize>>,
+    node_attachments: Vec<Vec<usize>>,
+    // Multisampled images resolved into the color attachments of each stage
+    node_multisampled_attachments: Vec<Vec<usize>>,
+    requests: Vec<AttachmentRequest>,
+    // Images should be destroyed before the memory they are bound to
+    images: Vec<Vec<Resource<Image>>>,
+    blocks: Vec<MemoryBlock>,
+    extents: Vec<vk::Extent2D>,
+    image_count: usize,
+    present_image_count: usize,
+    output_res: vk::Extent2D,
+    resolution_scale: f32,
+    stats: AttachmentMemoryStats,
+    ctx: DeviceCtx,
+    self_ctx: ResourceHandle<AttachmentPool>,
+}
+
+impl AttachmentPool {
+    pub fn new(ctx: DeviceCtx, stage: &RendererStage, image_count: usize, present_image_count: usize, output_res: vk::Extent2D) -> Result<Resource<Self>, Error> {
+        let mut pool = Resource::new(Self {
+            parents: vec![],
+            node_attachments: vec![],
+            node_multisampled_attachments: vec![],
+            requests: vec![],
+            images: vec![],
+            blocks: vec![],
+            extents: vec![],
+            image_count,
+            present_image_count,
+            output_res,
+            resolution_scale: 1.0,
+            stats: Default::default(),
+            ctx,
+            self_ctx: Default::default(),
+        });
+        pool.self_ctx = pool.handle();
+        pool.collect_requests(stage, None)?;
+        pool.allocate()?;
+        Ok(pool)
+    }
+
+    /// Recreate every attachment for the given output resolution. Attachments should not be in use anymore.
+    pub fn resize(&mut self, output_res: vk::Extent2D) -> Result<(), Error> {
+        if output_res.width == self.output_res.width && output_res.height == self.output_res.height {
+            return Ok(());
+        }
+        self.output_res = output_res;
+        self.allocate()
+    }
+
+    /// Scale applied to the output resolution before resolving attachment sizes (dynamic resolution).
+    /// Attachments should not be in use anymore.
+    pub fn set_resolution_scale(&mut self, resolution_scale: f32) -> Result<(), Error> {
+        if resolution_scale == self.resolution_scale {
+            return Ok(());
+        }
+        self.resolution_scale = resolution_scale;
+        self.allocate()
+    }
+
+    pub fn resolution_scale(&self) -> f32 {
+        self.resolution_scale
+    }
+
+    /// Output resolution scaled by the current resolution scale
+    pub fn render_res(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: ((self.output_res.width as f32 * self.resolution_scale) as u32).max(1),
+            height: ((self.output_res.height as f32 * self.resolution_scale) as u32).max(1),
+        }
+    }
+
+    /// Attachments written by the stage at the given index (pre-order index in the stage tree)
+    pub fn node_attachments(&self, node: usize) -> Vec<AttachmentInstance> {
+        self.instances(&self.node_attachments[node])
+    }
+
+    /// Multisampled images drawn by the stage at the given index, in the order of its resolved color attachments
+    pub fn node_multisampled_attachments(&self, node: usize) -> Vec<AttachmentInstance> {
+        self.instances(&self.node_multisampled_attachments[node])
+    }
+
+    fn instances(&self, requests: &[usize]) -> Vec<AttachmentInstance> {
+        requests.iter().map(|request| AttachmentInstance {
+            pool: self.self_ctx.clone(),
+            request: *request,
+        }).collect()
+    }
+
+    pub fn stats(&self) -> &AttachmentMemoryStats {
+        &self.stats
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.image_count
+    }
+
+    fn collect_requests(&mut self, stage: &RendererStage, parent: Option<usize>) -> Result<(), Error> {
+        let node = self.parents.len();
+        self.parents.push(parent);
+        self.node_attachments.push(vec![]);
+        self.node_multisampled_attachments.push(vec![]);
+
+        let render_pass = self.ctx.find_render_pass(&stage.name)?;
+        match parent {
+            Some(parent) => {
+                for (index, attachment) in render_pass.base().color_attachments.iter().enumerate() {
+                    self.add_request(node, parent, attachment, false, render_pass.base().is_input_attachment(SubpassAttachment::Color(index)))?;
+                }
+                if let Some(attachment) = &render_pass.base().depth_attachment {
+                    self.add_request(node, parent, attachment, true, render_pass.base().is_input_attachment(SubpassAttachment::Depth))?;
+                }
+            }
+            None => {
+                // The present pass targets are external, only its multisampled images are owned by the pool
+                for (index, (attachment, format)) in render_pass.base().color_attachments.iter().zip(render_pass.color_formats()).enumerate() {
+                    if attachment.is_resolved() {
+                        self.add_multisampled_request(node, attachment, *format, render_pass.base().is_input_attachment(SubpassAttachment::Color(index)));
+                    }
+                }
+            }
+        }
+
+        for dependency in &stage.dependencies {
+            self.collect_requests(dependency, Some(node))?;
+        }
+        Ok(())
+    }
+
+    fn add_request(&mut self, node: usize, parent: usize, attachment: &RenderPassAttachment, is_depth: bool, is_input: bool) -> Result<(), Error> {
+        let format = match &attachment.source {
+            RenderTarget::Internal(format) => { *format }
+            _ => return Err(anyhow!("Only internal formats are allowed for children targets"))
+        };
+
+        if attachment.is_resolved() {
+            self.add_multisampled_request(node, attachment, format, is_input);
+        }
+
+        let mut usage = if is_depth { vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT } else { vk::ImageUsageFlags::COLOR_ATTACHMENT };
+        // Subpasses read the multisampled image of resolved attachments
+        if is_input && !attachment.is_resolved() {
+            usage |= vk::ImageUsageFlags::INPUT_ATTACHMENT;
+        }
+        self.node_attachments[node].push(self.requests.len());
+        self.requests.push(AttachmentRequest {
+            create_infos: ImageCreateOptions {
+                image_type: vk::ImageType::_2D,
+                format,
+                usage: usage | if attachment.transient { vk::ImageUsageFlags::TRANSIENT_ATTACHMENT } else { vk::ImageUsageFlags::SAMPLED },
+                width: 0,
+                height: 0,
+                depth: 1,
+                mips_levels: 1,
+                array_layers: 1,
+                cube: false,
+                is_depth,
+                samples: if attachment.is_resolved() { vk::SampleCountFlags::_1 } else { attachment.samples },
+            },
+            size: attachment.size.clone(),
+            producer: node,
+            consumer: if attachment.transient { node } else { parent },
+            transient: attachment.transient,
+            persistent: attachment.preserves_content(),
+            image_count: self.image_count,
+        });
+        Ok(())
+    }
+
+    fn add_multisampled_request(&mut self, node: usize, attachment: &RenderPassAttachment, format: vk::Format, is_input: bool) {
+        self.node_multisampled_attachments[node].push(self.requests.len());
+        self.requests.push(AttachmentRequest {
+            create_infos: ImageCreateOptions {
+                image_type: vk::ImageType::_2D,
+                format,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT |
+                    if is_input { vk::ImageUsageFlags::INPUT_ATTACHMENT } else { vk::ImageUsageFlags::empty() },
+                width: 0,
+                height: 0,
+                depth: 1,
+                mips_levels: 1,
+                array_layers: 1,
+                cube: false,
+                is_depth: false,
+                samples: attachment.samples,
+            },
+            size: attachment.size.clone(),
+            producer: node,
+            consumer: node,
+            transient: true,
+            persistent: false,
+            image_count: if node == 0 { self.present_image_count } else { self.image_count },
+        });
+    }
+
+    fn is_strict_descendant(&self, node: usize, ancestor: usize) -> bool {
+        let mut current = self.parents[node];
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            current = self.parents[parent];
+        }
+        false
+    }
+
+    fn lifetimes_are_disjoint(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.requests[a], &self.requests[b]);
+        if a.persistent || b.persistent {
+            return false;
+        }
+        self.is_strict_descendant(a.consumer, b.producer) || self.is_strict_descendant(b.consumer, a.producer)
+    }
+
+    /// Attachments loading the previous frame start their first pass in the layout the last frame left them in.
+    /// Freshly allocated images are moved to that layout once, their content stays undefined.
+    fn initialize_persistent_layouts(&mut self) -> Result<(), Error> {
+        if !self.requests.iter().any(|request| request.persistent) {
+            return Ok(());
+        }
+        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Graphic)?;
+        command_buffer.begin_one_time()?;
+        for (request, images) in self.requests.iter().zip(&mut self.images) {
+            if request.persistent {
+                for image in images {
+                    image.set_image_layout(command_buffer.ptr()?, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
+                }
+            }
+        }
+        command_buffer.end()?;
+
+        let command_buffers = vec![*command_buffer.ptr()?];
+        let submit_infos = vec![vk::SubmitInfo::builder()
+            .command_buffers(command_buffers.as_slice())
+            .build()];
+        let fence = Fence::new(self.ctx.clone());
+        self.ctx.queues().submit(&QueueFlag::Graphic, submit_infos.as_slice(), Some(fence.handle()));
+        fence.wait();
+        Ok(())
+    }
+
+    fn supports_lazy_allocation(&self, memory_type_bits: u32) -> bool {
+        let properties = self.ctx.allocator().get_memory_properties();
+        (0..properties.memory_type_count).any(|i| {
+            memory_type_bits & (1 << i) != 0 && properties.memory_types[i as usize].property_flags.contains(vk::MemoryPropertyFlags::LAZILY_ALLOCATED)
+        })
+    }
+
+    fn allocate(&mut self) -> Result<(), Error> {
+        self.images.clear();
+        self.blocks.clear();
+        self.extents.clear();
+
+        let render_res = self.render_res();
+        let mut requirements = vec![];
+        for request in &self.requests {
+            // Only the multisampled images of the present pass are produced by the root stage : they match the output
+            let extent = if request.producer == 0 { self.output_res } else { request.size.resolve(render_res) };
+            self.extents.push(extent);
+            let mut create_infos = request.create_infos.clone();
+            create_infos.width = extent.width;
+            create_infos.height = extent.height;
+            let mut images = vec![];
+            for _ in 0..request.image_count {
+                images.push(Image::new_unbound(self.ctx.clone(), create_infos.clone())?);
+            }
+            requirements.push(images[0].memory_requirements()?);
+            self.images.push(images);
+        }
+
+        // Greedily put each attachment in the first block whose users are never alive at the same time
+        let mut blocks: Vec<(vk::MemoryRequirements, Vec<usize>)> = vec![];
+        for (index, request) in self.requests.iter().enumerate() {
+            let requirement = requirements[index];
+            let compatible_block = blocks.iter().position(|(block_requirements, users)| {
+                block_requirements.memory_type_bits & requirement.memory_type_bits != 0 &&
+                    users.iter().all(|user| self.requests[*user].image_count == request.image_count) &&
+                    users.iter().all(|user| self.requests[*user].transient == request.transient && self.lifetimes_are_disjoint(index, *user))
+            });
+            match compatible_block {
+                None => { blocks.push((requirement, vec![index])) }
+                Some(block) => {
+                    let (block_requirements, users) = &mut blocks[block];
+                    block_requirements.size = block_requirements.size.max(requirement.size);
+                    block_requirements.alignment = block_requirements.alignment.max(requirement.alignment);
+                    block_requirements.memory_type_bits &= requirement.memory_type_bits;
+                    users.push(index);
+                }
+            }
+        }
+
+        let mut stats = AttachmentMemoryStats {
+            attachments: self.requests.len(),
+            memory_blocks: blocks.len(),
+            unaliased_size: requirements.iter().zip(&self.requests).map(|(r, request)| r.size * request.image_count as vk::DeviceSize).sum(),
+            aliased_size: 0,
+        };
+
+        for (block_requirements, users) in blocks {
+            let transient = self.requests[users[0]].transient;
+            let image_count = self.requests[users[0]].image_count;
+            let options = AllocationOptions {
+                usage: MemoryUsage::Unknown,
+                required_flags: if transient && self.supports_lazy_allocation(block_requirements.memory_type_bits) {
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED
+                } else {
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL
+                },
+                ..Default::default()
+            };
+
+            let mut block = MemoryBlock { allocations: vec![], ctx: self.ctx.clone() };
+            for frame in 0..image_count {
+                let allocation = unsafe { self.ctx.allocator().allocate_memory(block_requirements, &options) }?;
+                block.allocations.push(allocation);
+                for user in &users {
+                    self.images[*user][frame].bind_memory(allocation)?;
+                }
+            }
+            stats.aliased_size += block_requirements.size * image_count as vk::DeviceSize;
+            self.blocks.push(block);
+        }
+        self.initialize_persistent_layouts()?;
+
+        info!("Allocated {} frame graph attachments in {} memory blocks : {} bytes instead of {} bytes without aliasing",
+            stats.attachments, stats.memory_blocks, stats.aliased_size, stats.unaliased_size);
+        self.stats = stats;
+        Ok(())
+    }
+}
+
+/// Reference to the images of an internal attachment (one per frame in flight)
+pub struct AttachmentInstance {
+    pool: ResourceHandle<AttachmentPool>,
+    request: usize,
+}
+
+impl AttachmentInstance {
+    pub fn image(&self, index: usize) -> &Image {
+        &self.pool.images[self.request][index]
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.pool.requests[self.request].image_count
+    }
+
+    pub fn res(&self) -> vk::Extent2D {
+        self.pool.extents[self.request]
+    }
+}
@@ -1,5 +1,7 @@
 use crate::core::gfx::resources::image::Image;
 use crate::core::window::WindowCtx;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 use types::resource_handle::ResourceHandle;
 use vulkanalia::vk;
 
@@ -9,12 +11,100 @@ pub enum RenderPassName {
     Named(String)
 }
 
-#[derive(Copy, Clone, Default)]
+impl Display for RenderPassName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderPassName::Present(_) => { f.write_str("present") }
+            RenderPassName::Named(name) => { f.write_str(name) }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
 pub enum ClearValues {
     #[default]
     DontClear,
+    /// Float and normalized color formats
     Color(glam::Vec4),
-    DepthStencil(glam::Vec2),
+    /// Signed integer color formats
+    ColorInt(glam::IVec4),
+    /// Unsigned integer color formats
+    ColorUint(glam::UVec4),
+    /// Only clear the depth aspect, the stencil aspect follows the load op of the attachment
+    Depth(f32),
+    /// Only clear the stencil aspect, the depth aspect follows the load op of the attachment
+    Stencil(u32),
+    DepthStencil(f32, u32),
+}
+
+impl ClearValues {
+    pub fn is_color(&self) -> bool {
+        matches!(self, ClearValues::Color(_) | ClearValues::ColorInt(_) | ClearValues::ColorUint(_))
+    }
+
+    pub fn clears_depth(&self) -> bool {
+        matches!(self, ClearValues::Depth(_) | ClearValues::DepthStencil(_, _))
+    }
+
+    pub fn clears_stencil(&self) -> bool {
+        matches!(self, ClearValues::Stencil(_) | ClearValues::DepthStencil(_, _))
+    }
+
+    pub fn vk_value(&self) -> vk::ClearValue {
+        match *self {
+            ClearValues::DontClear => { vk::ClearValue::default() }
+            ClearValues::Color(color) => { vk::ClearValue { color: vk::ClearColorValue { float32: color.to_array() } } }
+            ClearValues::ColorInt(color) => { vk::ClearValue { color: vk::ClearColorValue { int32: color.to_array() } } }
+            ClearValues::ColorUint(color) => { vk::ClearValue { color: vk::ClearColorValue { uint32: color.to_array() } } }
+            ClearValues::Depth(depth) => { vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth, stencil: 0 } } }
+            ClearValues::Stencil(stencil) => { vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 0.0, stencil } } }
+            ClearValues::DepthStencil(depth, stencil) => { vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth, stencil } } }
+        }
+    }
+}
+
+/// Content of an attachment at the beginning of a pass when it is not cleared
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub enum LoadOp {
+    /// The previous content is discarded
+    #[default]
+    DontCare,
+    /// Keep the content of the previous frame. Loaded attachments never share their memory with other attachments.
+    /// Not allowed on window targets.
+    Load,
+    /// The content is kept but the pass is not allowed to read it. Requires VK_KHR_load_store_op_none. Not allowed on window targets.
+    None,
+}
+
+impl LoadOp {
+    pub fn vk_op(&self) -> vk::AttachmentLoadOp {
+        match self {
+            LoadOp::DontCare => { vk::AttachmentLoadOp::DONT_CARE }
+            LoadOp::Load => { vk::AttachmentLoadOp::LOAD }
+            LoadOp::None => { vk::AttachmentLoadOp::NONE_KHR }
+        }
+    }
+}
+
+/// Content of an attachment at the end of a pass
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub enum StoreOp {
+    #[default]
+    Store,
+    /// The content written by the pass is discarded
+    DontCare,
+    /// The content is left untouched if the pass didn't write it. Requires VK_KHR_load_store_op_none.
+    None,
+}
+
+impl StoreOp {
+    pub fn vk_op(&self) -> vk::AttachmentStoreOp {
+        match self {
+            StoreOp::Store => { vk::AttachmentStoreOp::STORE }
+            StoreOp::DontCare => { vk::AttachmentStoreOp::DONT_CARE }
+            StoreOp::None => { vk::AttachmentStoreOp::NONE }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -24,17 +114,60 @@ pub enum RenderTarget {
     Internal(vk::Format),
 }
 
+/// Resolution of an internal attachment
+#[derive(Clone)]
+pub enum AttachmentSize {
+    /// Scale of the render resolution (ex : 0.5 for half-res SSAO)
+    Relative(f32),
+    /// Always the same size whatever the output resolution (ex : shadow maps)
+    Fixed(vk::Extent2D),
+    /// Computed from the render resolution
+    Custom(Arc<dyn Fn(vk::Extent2D) -> vk::Extent2D>),
+}
+
+impl Default for AttachmentSize {
+    fn default() -> Self {
+        Self::Relative(1.0)
+    }
+}
+
+impl AttachmentSize {
+    pub fn resolve(&self, render_res: vk::Extent2D) -> vk::Extent2D {
+        let res = match self {
+            AttachmentSize::Relative(scale) => {
+                vk::Extent2D {
+                    width: (render_res.width as f32 * scale) as u32,
+                    height: (render_res.height as f32 * scale) as u32,
+                }
+            }
+            AttachmentSize::Fixed(res) => { *res }
+            AttachmentSize::Custom(callback) => { callback(render_res) }
+        };
+        vk::Extent2D { width: res.width.max(1), height: res.height.max(1) }
+    }
+}
+
 #[derive(Clone)]
 pub struct RenderPassAttachment {
     pub clear_value: ClearValues,
+    pub load_op: LoadOp,
+    pub store_op: StoreOp,
     pub source: RenderTarget,
+    pub transient: bool,
+    pub size: AttachmentSize,
+    pub samples: vk::SampleCountFlags,
 }
 
 impl RenderPassAttachment {
     pub fn new(source: RenderTarget) -> Self {
         Self {
             clear_value: Default::default(),
+            load_op: Default::default(),
+            store_op: Default::default(),
             source,
+            transient: false,
+            size: Default::default(),
+            samples: vk::SampleCountFlags::_1,
         }
     }
     
@@ -42,12 +175,125 @@ impl RenderPassAttachment {
         self.clear_value = clear_value;
         self
     }
+
+    /// Ignored for the aspects being cleared
+    pub fn load_op(mut self, load_op: LoadOp) -> Self {
+        self.load_op = load_op;
+        self
+    }
+
+    /// Ignored for transient attachments, which are never stored
+    pub fn store_op(mut self, store_op: StoreOp) -> Self {
+        self.store_op = store_op;
+        self
+    }
+
+    /// The content of this attachment is never read after the pass (ex : depth buffer only used for depth testing).
+    /// Only allowed on internal targets. Transient attachments are lazily allocated when the device supports it.
+    pub fn transient(mut self) -> Self {
+        self.transient = true;
+        self
+    }
+
+    /// Resolution of this attachment. Only allowed on internal targets.
+    /// The render pass is drawn with the smallest extent of its attachments.
+    pub fn size(mut self, size: AttachmentSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Number of samples per pixel. Every attachment of a pass must use the same sample count.
+    /// Multisampled attachments are automatically resolved into their target, unless they are transient.
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// The pass draws into a multisampled image which is then resolved into this attachment's target
+    pub fn is_resolved(&self) -> bool {
+        self.samples != vk::SampleCountFlags::_1 && !self.transient
+    }
+
+    /// The content of the previous frame is kept : the image starts the pass in the layout it was left in
+    pub fn preserves_content(&self) -> bool {
+        self.load_op != LoadOp::DontCare
+    }
+
+    pub fn vk_load_op(&self, cleared: bool) -> vk::AttachmentLoadOp {
+        if cleared { vk::AttachmentLoadOp::CLEAR } else { self.load_op.vk_op() }
+    }
+
+    pub fn vk_store_op(&self) -> vk::AttachmentStoreOp {
+        if self.transient { vk::AttachmentStoreOp::DONT_CARE } else { self.store_op.vk_op() }
+    }
+}
+
+/// Attachment of a render pass referenced by one of its subpasses
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SubpassAttachment {
+    /// Index in the color attachments of the render pass
+    Color(usize),
+    Depth,
+}
+
+#[derive(Clone, Default)]
+pub struct Subpass {
+    /// Indices of the render pass color attachments written by this subpass
+    pub color_attachments: Vec<usize>,
+    /// Attachments written by a previous subpass and read in this one with subpassLoad()
+    pub input_attachments: Vec<SubpassAttachment>,
+    pub depth_attachment: bool,
+}
+
+impl Subpass {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn color_attachment(mut self, index: usize) -> Self {
+        self.color_attachments.push(index);
+        self
+    }
+    pub fn input_attachment(mut self, attachment: SubpassAttachment) -> Self {
+        self.input_attachments.push(attachment);
+        self
+    }
+    pub fn depth_attachment(mut self) -> Self {
+        self.depth_attachment = true;
+        self
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct SubpassDependency {
+    pub src_subpass: usize,
+    pub dst_subpass: usize,
+    pub src_stage_mask: vk::PipelineStageFlags,
+    pub dst_stage_mask: vk::PipelineStageFlags,
+    pub src_access_mask: vk::AccessFlags,
+    pub dst_access_mask: vk::AccessFlags,
+}
+
+impl SubpassDependency {
+    /// The fragment shaders of dst_subpass read the attachments written by src_subpass
+    pub fn input_attachment(src_subpass: usize, dst_subpass: usize) -> Self {
+        Self {
+            src_subpass,
+            dst_subpass,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::INPUT_ATTACHMENT_READ,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct RenderPass {
     pub color_attachments: Vec<RenderPassAttachment>,
     pub depth_attachment: Option<RenderPassAttachment>,
+    /// When empty, the pass has a single subpass writing every attachment
+    pub subpasses: Vec<Subpass>,
+    pub subpass_dependencies: Vec<SubpassDependency>,
     pub name: RenderPassName
 }
 
@@ -57,6 +303,8 @@ impl RenderPass {
         Self {
             color_attachments: vec![],
             depth_attachment: None,
+            subpasses: vec![],
+            subpass_dependencies: vec![],
             name,
         }
     }
@@ -68,15 +316,55 @@ impl RenderPass {
         self.depth_attachment = Some(attachment);
         self
     }
+    pub fn subpass(mut self, subpass: Subpass) -> Self {
+        self.subpasses.push(subpass);
+        self
+    }
+    pub fn subpass_dependency(mut self, dependency: SubpassDependency) -> Self {
+        self.subpass_dependencies.push(dependency);
+        self
+    }
+
+    /// Declared subpasses, or the implicit one writing every attachment
+    pub fn effective_subpasses(&self) -> Vec<Subpass> {
+        if !self.subpasses.is_empty() {
+            return self.subpasses.clone();
+        }
+        vec![Subpass {
+            color_attachments: (0..self.color_attachments.len()).collect(),
+            input_attachments: vec![],
+            depth_attachment: self.depth_attachment.is_some(),
+        }]
+    }
+
+    pub fn is_input_attachment(&self, attachment: SubpassAttachment) -> bool {
+        self.subpasses.iter().any(|subpass| subpass.input_attachments.contains(&attachment))
+    }
 }
 
 pub struct Renderer {
     pub present_stage: RendererStage,
+    /// Renderers without UI stage don't instantiate ImGui
+    pub ui: Option<UiStage>,
     pub name: String
 }
 
+/// ImGui is drawn at the end of the last subpass of the given pass.
+/// It can be the present pass, or an overlay pass depending on the scene passes.
+pub struct UiStage {
+    pub pass: RenderPassName,
+}
+
+pub type ClearCallback = Box<dyn FnMut(&mut [ClearValues])>;
+
 pub struct RendererStage {
     pub render_callback: Box<dyn FnMut()>,
+    /// Draw the subpasses following the first one, in order
+    pub subpass_callbacks: Vec<Box<dyn FnMut()>>,
+    /// Called before the pass begins with the declared clear values of its attachments (colors then depth).
+    /// Values can be changed for the current frame, but an attachment can't start or stop being cleared.
+    /// Overrides changing the kind of clear are ignored.
+    pub clear_callback: Option<ClearCallback>,
     pub name: RenderPassName,
     pub dependencies: Vec<RendererStage>
 }
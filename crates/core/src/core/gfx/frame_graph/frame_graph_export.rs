@@ -0,0 +1,131 @@
+use crate::core::gfx::frame_graph::attachment_pool::AttachmentMemoryStats;
+use imgui::sys::{igBegin, igEnd, igSeparator, igTextUnformatted, igTreeNode_Str, igTreePop};
+use serde::Serialize;
+use std::ffi::CString;
+use std::fmt::Write;
+
+/// Snapshot of an instantiated renderer, as submitted by the render passes
+#[derive(Serialize, Clone, Debug)]
+pub struct FrameGraphDescription {
+    pub renderer: String,
+    /// Passes in pre-order, the present pass first
+    pub passes: Vec<PassDescription>,
+    pub attachment_memory: AttachmentMemoryStats,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PassDescription {
+    pub id: usize,
+    pub name: String,
+    pub target: String,
+    pub extent: [u32; 2],
+    pub queue: String,
+    pub subpasses: usize,
+    pub samples: u32,
+    pub attachments: Vec<AttachmentDescription>,
+    pub wait_semaphores: Vec<String>,
+    pub barriers: Vec<String>,
+    /// Ids of the passes this one waits for
+    pub dependencies: Vec<usize>,
+    /// Duration of the last completed submission
+    pub gpu_time_ms: Option<f64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AttachmentDescription {
+    pub kind: String,
+    pub format: String,
+    pub extent: [u32; 2],
+    pub samples: u32,
+    pub transient: bool,
+    pub resolved: bool,
+    pub clear: String,
+    pub load_op: String,
+    pub store_op: String,
+}
+
+impl FrameGraphDescription {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Graphviz representation : one node per pass, edges go from a dependency to the pass waiting for it
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape(&self.renderer)).unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for pass in &self.passes {
+            let label = pass.lines().join("\\l");
+            writeln!(dot, "    pass{} [label=\"{}\\l\"];", pass.id, escape(&label).replace("\\\\l", "\\l")).unwrap();
+        }
+        for pass in &self.passes {
+            for dependency in &pass.dependencies {
+                writeln!(dot, "    pass{} -> pass{};", dependency, pass.id).unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    /// Live view, to be called while an ImGui frame is being recorded
+    pub fn draw_imgui(&self) {
+        let title = CString::new(format!("Frame graph : {}", self.renderer)).unwrap();
+        if unsafe { igBegin(title.as_ptr(), std::ptr::null_mut(), 0) } {
+            text(&format!("Attachments : {} in {} blocks, {} KB ({} KB without aliasing)",
+                          self.attachment_memory.attachments, self.attachment_memory.memory_blocks,
+                          self.attachment_memory.aliased_size / 1024, self.attachment_memory.unaliased_size / 1024));
+            unsafe { igSeparator() };
+            if !self.passes.is_empty() {
+                self.draw_pass_node(0);
+            }
+        }
+        unsafe { igEnd() };
+    }
+
+    fn draw_pass_node(&self, id: usize) {
+        let pass = &self.passes[id];
+        let time = pass.gpu_time_ms.map(|time| format!("{time:.3} ms")).unwrap_or("-".to_string());
+        let label = CString::new(format!("{} ({time})##{}", pass.name, pass.id)).unwrap();
+        if unsafe { igTreeNode_Str(label.as_ptr()) } {
+            for line in pass.lines().iter().skip(1) {
+                text(line);
+            }
+            for dependency in &pass.dependencies {
+                self.draw_pass_node(*dependency);
+            }
+            unsafe { igTreePop() };
+        }
+    }
+}
+
+impl PassDescription {
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            self.name.clone(),
+            format!("target : {} {}x{}, {} subpass(es), {} sample(s), {} queue", self.target, self.extent[0], self.extent[1], self.subpasses, self.samples, self.queue),
+        ];
+        for attachment in &self.attachments {
+            lines.push(format!("{} : {} {}x{} x{}{}{} clear={} load={} store={}", attachment.kind, attachment.format, attachment.extent[0], attachment.extent[1], attachment.samples,
+                               if attachment.transient { " transient" } else { "" }, if attachment.resolved { " resolved" } else { "" }, attachment.clear, attachment.load_op, attachment.store_op));
+        }
+        for semaphore in &self.wait_semaphores {
+            lines.push(format!("wait : {semaphore}"));
+        }
+        for barrier in &self.barriers {
+            lines.push(format!("barrier : {barrier}"));
+        }
+        if let Some(time) = self.gpu_time_ms {
+            lines.push(format!("gpu : {time:.3} ms"));
+        }
+        lines
+    }
+}
+
+fn text(text: &str) {
+    let range = text.as_bytes().as_ptr_range();
+    unsafe { igTextUnformatted(range.start as *const imgui::sys::cty::c_char, range.end as *const imgui::sys::cty::c_char) };
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
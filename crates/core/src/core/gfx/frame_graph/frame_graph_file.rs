@@ -0,0 +1,634 @@
+use crate::core::gfx::frame_graph::frame_graph_definition::{AttachmentSize, ClearValues, LoadOp, RenderPass, RenderPassAttachment, RenderPassName, RenderTarget, Renderer, RendererStage, StoreOp, Subpass, SubpassAttachment, SubpassDependency, UiStage};
+use crate::core::gfx::device::DeviceCtx;
+use crate::core::gfx::frame_graph::renderer::RenderPassObject;
+use crate::core::window::WindowCtx;
+use anyhow::{anyhow, Error};
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::SystemTime;
+use types::resource_handle::Resource;
+use vulkanalia::vk;
+
+/// Rust callbacks that can be referenced by name from a frame graph file
+#[derive(Default)]
+pub struct FrameGraphBindings {
+    render_callbacks: HashMap<String, Rc<RefCell<dyn FnMut()>>>,
+    size_callbacks: HashMap<String, Arc<dyn Fn(vk::Extent2D) -> vk::Extent2D>>,
+}
+
+impl FrameGraphBindings {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Callback drawing a stage or a subpass
+    pub fn render_callback(mut self, name: &str, callback: impl FnMut() + 'static) -> Self {
+        self.render_callbacks.insert(name.to_string(), Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Callback computing the size of an attachment from the render resolution
+    pub fn size_callback(mut self, name: &str, callback: impl Fn(vk::Extent2D) -> vk::Extent2D + 'static) -> Self {
+        self.size_callbacks.insert(name.to_string(), Arc::new(callback));
+        self
+    }
+
+    fn render_callback_instance(&self, name: &CallbackName) -> Box<dyn FnMut()> {
+        let callback = self.render_callbacks[&name.0].clone();
+        Box::new(move || (callback.borrow_mut())())
+    }
+}
+
+/// Renderer described in a json file :
+/// ```json
+/// {
+///     "name": "main",
+///     "ui": "present",
+///     "present": {
+///         "callback": "draw_ui",
+///         "pass": { "color_attachments": [{ "target": "window", "clear": { "color": [0.0, 0.0, 0.0, 1.0] } }] },
+///         "dependencies": [{
+///             "name": "forward",
+///             "callback": "draw_scene",
+///             "pass": {
+///                 "color_attachments": [{ "target": "R16G16B16A16_SFLOAT", "size": { "relative": 0.5 }, "samples": 4 }],
+///                 "depth_attachment": { "target": "D32_SFLOAT", "clear": { "depth": 1.0 }, "transient": true }
+///             }
+///         }]
+///     }
+/// }
+/// ```
+/// Clears are `color`, `color_int`, `color_uint`, `depth`, `stencil` or `depth_stencil` ([depth, stencil]).
+/// `load_op` is `dont_care` (default), `load` or `none` (not allowed on the window), `store_op` is `store` (default), `dont_care` or `none`.
+/// `ui` is optional and names the pass drawing ImGui ("present" for the present pass).
+/// The file is reloaded by the window whenever it is modified.
+pub struct FrameGraphFile {
+    path: PathBuf,
+    bindings: FrameGraphBindings,
+    last_modified: Option<SystemTime>,
+    // Names of the passes declared by this file, which it is allowed to replace
+    declared_passes: HashSet<String>,
+    // Declarations replaced since the current renderer was created, still used by it
+    retired_passes: Vec<Resource<RenderPassObject>>,
+}
+
+impl FrameGraphFile {
+    pub fn new(path: impl Into<PathBuf>, bindings: FrameGraphBindings) -> Self {
+        Self {
+            path: path.into(),
+            bindings,
+            last_modified: None,
+            declared_passes: HashSet::new(),
+            retired_passes: vec![],
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// The file changed since it was last loaded
+    pub fn is_modified(&self) -> bool {
+        let modified = std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        modified.is_some() && modified != self.last_modified
+    }
+
+    /// Parse the file and declare its render passes. Nothing is declared if the file is invalid.
+    /// Passes declared by a frame graph file are overwritten on reload : their names can't be used by passes declared elsewhere.
+    /// The replaced declarations are kept alive until [`FrameGraphFile::release_retired_passes`] is called.
+    pub fn load(&mut self, window: WindowCtx) -> Result<Renderer, Error> {
+        self.last_modified = std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        let source = std::fs::read_to_string(&self.path).map_err(|err| anyhow!("Failed to read {} : {err}", self.path.display()))?;
+        let file = self.parse(&source).map_err(|err| anyhow!("{}:{}:{} : {err}", self.path.display(), err.line(), err.column()))?;
+        let spans = JsonSpan::parse(&source);
+        let present_span = spans.as_ref().and_then(|spans| spans.get("present"));
+
+        let device = window.engine().instance().device();
+        let mut ctx = LoadContext {
+            file: self,
+            source: &source,
+            device: device.clone(),
+            render_passes: vec![],
+        };
+        let present_stage = ctx.build_stage(file.present.stage(), RenderPassName::Present(window.clone()), present_span)?;
+
+        let ui = match &file.ui {
+            None => { None }
+            Some(pass) => {
+                let pass = ctx.render_passes.iter().find(|(render_pass, _)| render_pass.name.to_string() == *pass)
+                    .ok_or_else(|| anyhow!("{} : ui pass {pass} is not part of the renderer", ctx.location(spans.as_ref().and_then(|spans| spans.get("ui")))))?;
+                Some(UiStage { pass: pass.0.name.clone() })
+            }
+        };
+
+        // Every pass is validated before any of them is replaced, so an invalid file leaves the current graph untouched
+        for (render_pass, offset) in &ctx.render_passes {
+            device.validate_render_pass(render_pass).map_err(|err| anyhow!("{} : {err}", ctx.location_at(*offset)))?;
+        }
+        let render_passes = ctx.render_passes.into_iter().map(|(render_pass, _)| render_pass).collect::<Vec<_>>();
+        let names = render_passes.iter().filter_map(|render_pass| match &render_pass.name {
+            RenderPassName::Named(name) => { Some(name.clone()) }
+            RenderPassName::Present(_) => { None }
+        }).collect::<Vec<_>>();
+        let replaced = device.replace_render_passes(render_passes)?;
+        self.declared_passes.extend(names);
+        self.retired_passes.extend(replaced);
+
+        Ok(Renderer {
+            present_stage,
+            ui,
+            name: file.name,
+        })
+    }
+
+    /// Free the declarations replaced by the previous loads, once the renderer using them has been replaced
+    pub fn release_retired_passes(&mut self) {
+        self.retired_passes.clear();
+    }
+
+    fn parse(&self, source: &str) -> Result<RendererFile, serde_json::Error> {
+        KNOWN_BINDINGS.with(|known| {
+            *known.borrow_mut() = KnownBindings {
+                render_callbacks: self.bindings.render_callbacks.keys().cloned().collect(),
+                size_callbacks: self.bindings.size_callbacks.keys().cloned().collect(),
+            }
+        });
+        let file = serde_json::from_str(source);
+        KNOWN_BINDINGS.with(|known| *known.borrow_mut() = Default::default());
+        file
+    }
+}
+
+// State of a load, stages are built depth first in the same order as the attachment pool numbers them
+struct LoadContext<'a> {
+    file: &'a FrameGraphFile,
+    source: &'a str,
+    device: DeviceCtx,
+    // Declared passes, with the offset of their declaration
+    render_passes: Vec<(RenderPass, Option<usize>)>,
+}
+
+impl LoadContext<'_> {
+    fn build_stage(&mut self, stage: StageRef, name: RenderPassName, span: Option<&JsonSpan>) -> Result<RendererStage, Error> {
+        let name_span = span.and_then(|span| span.get("name")).or(span);
+        if let RenderPassName::Named(pass_name) = &name {
+            if self.render_passes.iter().any(|(render_pass, _)| render_pass.name.to_string() == *pass_name) {
+                return Err(anyhow!("{} : render pass {name} is declared more than once", self.location(name_span)));
+            }
+            if !self.file.declared_passes.contains(pass_name) && self.device.find_render_pass(&name).is_ok() {
+                return Err(anyhow!("{} : render pass {name} is already declared outside of this file", self.location(name_span)));
+            }
+        }
+        let subpass_count = stage.pass.subpasses.len().max(1);
+        if stage.subpass_callbacks.len() + 1 != subpass_count {
+            return Err(anyhow!("{} : render pass {name} has {subpass_count} subpasses, expected {} subpass callbacks", self.location(span.and_then(|span| span.get("subpass_callbacks")).or(name_span)), subpass_count - 1));
+        }
+
+        let is_present = matches!(name, RenderPassName::Present(_));
+        let pass_span = span.and_then(|span| span.get("pass"));
+        let mut render_pass = RenderPass::new(name.clone());
+        for (index, attachment) in stage.pass.color_attachments.iter().enumerate() {
+            let attachment_span = pass_span.and_then(|span| span.get("color_attachments")).and_then(|span| span.index(index));
+            render_pass = render_pass.color_attachment(self.build_attachment(attachment, is_present, &name)
+                .map_err(|err| anyhow!("{} : {err}", self.location(attachment_span)))?);
+        }
+        if let Some(attachment) = &stage.pass.depth_attachment {
+            let attachment_span = pass_span.and_then(|span| span.get("depth_attachment"));
+            render_pass = render_pass.depth_attachment(self.build_attachment(attachment, is_present, &name)
+                .map_err(|err| anyhow!("{} : {err}", self.location(attachment_span)))?);
+        }
+        for (index, subpass) in stage.pass.subpasses.iter().enumerate() {
+            render_pass = render_pass.subpass(Subpass {
+                color_attachments: subpass.color_attachments.clone(),
+                input_attachments: subpass.input_attachments.iter().map(|input| match input {
+                    InputAttachmentFile::Color(index) => { SubpassAttachment::Color(*index) }
+                    InputAttachmentFile::Depth => { SubpassAttachment::Depth }
+                }).collect(),
+                depth_attachment: subpass.depth_attachment,
+            });
+            if !subpass.input_attachments.is_empty() {
+                for previous in 0..index {
+                    render_pass = render_pass.subpass_dependency(SubpassDependency::input_attachment(previous, index));
+                }
+            }
+        }
+        self.render_passes.push((render_pass, name_span.map(|span| span.offset())));
+
+        let mut stage_dependencies = vec![];
+        for (index, dependency) in stage.dependencies.iter().enumerate() {
+            let dependency_span = span.and_then(|span| span.get("dependencies")).and_then(|span| span.index(index));
+            stage_dependencies.push(self.build_stage(dependency.stage(), RenderPassName::Named(dependency.name.clone()), dependency_span)?);
+        }
+
+        Ok(RendererStage {
+            render_callback: self.file.bindings.render_callback_instance(stage.callback),
+            subpass_callbacks: stage.subpass_callbacks.iter().map(|callback| self.file.bindings.render_callback_instance(callback)).collect(),
+            clear_callback: None,
+            name,
+            dependencies: stage_dependencies,
+        })
+    }
+
+    fn build_attachment(&self, attachment: &AttachmentFile, is_present: bool, pass: &RenderPassName) -> Result<RenderPassAttachment, Error> {
+        let source = match attachment.target {
+            TargetFile::Window if !is_present => { return Err(anyhow!("render pass {pass} cannot target the window, only the present stage can")) }
+            TargetFile::Window => { RenderTarget::Window }
+            TargetFile::Format(format) => { RenderTarget::Internal(format) }
+        };
+        let mut result = RenderPassAttachment::new(source)
+            .clear(match attachment.clear {
+                None => { ClearValues::DontClear }
+                Some(ClearFile::Color(color)) => { ClearValues::Color(glam::Vec4::from_array(color)) }
+                Some(ClearFile::ColorInt(color)) => { ClearValues::ColorInt(glam::IVec4::from_array(color)) }
+                Some(ClearFile::ColorUint(color)) => { ClearValues::ColorUint(glam::UVec4::from_array(color)) }
+                Some(ClearFile::Depth(depth)) => { ClearValues::Depth(depth) }
+                Some(ClearFile::Stencil(stencil)) => { ClearValues::Stencil(stencil) }
+                Some(ClearFile::DepthStencil((depth, stencil))) => { ClearValues::DepthStencil(depth, stencil) }
+            })
+            .load_op(match attachment.load_op {
+                LoadOpFile::DontCare => { LoadOp::DontCare }
+                LoadOpFile::Load => { LoadOp::Load }
+                LoadOpFile::None => { LoadOp::None }
+            })
+            .store_op(match attachment.store_op {
+                StoreOpFile::Store => { StoreOp::Store }
+                StoreOpFile::DontCare => { StoreOp::DontCare }
+                StoreOpFile::None => { StoreOp::None }
+            })
+            .samples(attachment.samples.0);
+        if attachment.transient {
+            result = result.transient();
+        }
+        if let Some(size) = &attachment.size {
+            result = result.size(match size {
+                SizeFile::Relative(scale) => { AttachmentSize::Relative(*scale) }
+                SizeFile::Fixed([width, height]) => { AttachmentSize::Fixed(vk::Extent2D { width: *width, height: *height }) }
+                SizeFile::Custom(callback) => { AttachmentSize::Custom(self.file.bindings.size_callbacks[&callback.0].clone()) }
+            });
+        }
+        Ok(result)
+    }
+
+    // `path:line` of a value of the file
+    fn location(&self, span: Option<&JsonSpan>) -> String {
+        self.location_at(span.map(|span| span.offset()))
+    }
+
+    fn location_at(&self, offset: Option<usize>) -> String {
+        match offset {
+            None => { self.file.path.display().to_string() }
+            Some(offset) => { format!("{}:{}", self.file.path.display(), self.source[..offset].matches('\n').count() + 1) }
+        }
+    }
+}
+
+// Fields shared by the present stage and its dependencies
+struct StageRef<'a> {
+    callback: &'a CallbackName,
+    subpass_callbacks: &'a [CallbackName],
+    pass: &'a PassFile,
+    dependencies: &'a [StageFile],
+}
+
+/// Offsets of the values of a json document, used to report errors at their position in the file.
+/// Only used on documents already accepted by serde_json.
+enum JsonSpan {
+    Object(usize, Vec<(String, JsonSpan)>),
+    Array(usize, Vec<JsonSpan>),
+    Value(usize),
+}
+
+impl JsonSpan {
+    fn parse(source: &str) -> Option<Self> {
+        let mut offset = 0;
+        Self::parse_value(source.as_bytes(), &mut offset)
+    }
+
+    fn offset(&self) -> usize {
+        match self {
+            JsonSpan::Object(offset, _) | JsonSpan::Array(offset, _) | JsonSpan::Value(offset) => { *offset }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&JsonSpan> {
+        match self {
+            JsonSpan::Object(_, members) => { members.iter().find(|(name, _)| name == key).map(|(_, value)| value) }
+            _ => { None }
+        }
+    }
+
+    fn index(&self, index: usize) -> Option<&JsonSpan> {
+        match self {
+            JsonSpan::Array(_, elements) => { elements.get(index) }
+            _ => { None }
+        }
+    }
+
+    fn parse_value(source: &[u8], offset: &mut usize) -> Option<Self> {
+        Self::skip_whitespace(source, offset);
+        let start = *offset;
+        match *source.get(start)? {
+            b'{' => {
+                *offset += 1;
+                let mut members = vec![];
+                loop {
+                    Self::skip_whitespace(source, offset);
+                    if *source.get(*offset)? == b'}' {
+                        *offset += 1;
+                        return Some(JsonSpan::Object(start, members));
+                    }
+                    let key = Self::parse_string(source, offset)?;
+                    Self::skip_whitespace(source, offset);
+                    *offset += 1;
+                    members.push((key, Self::parse_value(source, offset)?));
+                    Self::skip_whitespace(source, offset);
+                    if *source.get(*offset)? == b',' {
+                        *offset += 1;
+                    }
+                }
+            }
+            b'[' => {
+                *offset += 1;
+                let mut elements = vec![];
+                loop {
+                    Self::skip_whitespace(source, offset);
+                    if *source.get(*offset)? == b']' {
+                        *offset += 1;
+                        return Some(JsonSpan::Array(start, elements));
+                    }
+                    elements.push(Self::parse_value(source, offset)?);
+                    Self::skip_whitespace(source, offset);
+                    if *source.get(*offset)? == b',' {
+                        *offset += 1;
+                    }
+                }
+            }
+            b'"' => {
+                Self::parse_string(source, offset)?;
+                Some(JsonSpan::Value(start))
+            }
+            _ => {
+                while !matches!(source.get(*offset), None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n')) {
+                    *offset += 1;
+                }
+                Some(JsonSpan::Value(start))
+            }
+        }
+    }
+
+    // Escape sequences are kept as is
+    fn parse_string(source: &[u8], offset: &mut usize) -> Option<String> {
+        let start = *offset + 1;
+        *offset = start;
+        loop {
+            match *source.get(*offset)? {
+                b'\\' => { *offset += 2 }
+                b'"' => {
+                    *offset += 1;
+                    return String::from_utf8(source[start..*offset - 1].to_vec()).ok();
+                }
+                _ => { *offset += 1 }
+            }
+        }
+    }
+
+    fn skip_whitespace(source: &[u8], offset: &mut usize) {
+        while source.get(*offset).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            *offset += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+struct KnownBindings {
+    render_callbacks: HashSet<String>,
+    size_callbacks: HashSet<String>,
+}
+
+thread_local! {
+    // Bindings available while parsing, so unknown names are reported with their position in the file
+    static KNOWN_BINDINGS: RefCell<KnownBindings> = Default::default();
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RendererFile {
+    name: String,
+    #[serde(default)]
+    ui: Option<String>,
+    present: PresentStageFile,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PresentStageFile {
+    callback: CallbackName,
+    #[serde(default)]
+    subpass_callbacks: Vec<CallbackName>,
+    pass: PassFile,
+    #[serde(default)]
+    dependencies: Vec<StageFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageFile {
+    name: String,
+    callback: CallbackName,
+    #[serde(default)]
+    subpass_callbacks: Vec<CallbackName>,
+    pass: PassFile,
+    #[serde(default)]
+    dependencies: Vec<StageFile>,
+}
+
+impl PresentStageFile {
+    fn stage(&self) -> StageRef<'_> {
+        StageRef { callback: &self.callback, subpass_callbacks: &self.subpass_callbacks, pass: &self.pass, dependencies: &self.dependencies }
+    }
+}
+
+impl StageFile {
+    fn stage(&self) -> StageRef<'_> {
+        StageRef { callback: &self.callback, subpass_callbacks: &self.subpass_callbacks, pass: &self.pass, dependencies: &self.dependencies }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PassFile {
+    #[serde(default)]
+    color_attachments: Vec<AttachmentFile>,
+    #[serde(default)]
+    depth_attachment: Option<AttachmentFile>,
+    #[serde(default)]
+    subpasses: Vec<SubpassFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AttachmentFile {
+    target: TargetFile,
+    #[serde(default)]
+    clear: Option<ClearFile>,
+    #[serde(default)]
+    load_op: LoadOpFile,
+    #[serde(default)]
+    store_op: StoreOpFile,
+    #[serde(default)]
+    transient: bool,
+    #[serde(default)]
+    size: Option<SizeFile>,
+    #[serde(default)]
+    samples: SamplesFile,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubpassFile {
+    #[serde(default)]
+    color_attachments: Vec<usize>,
+    #[serde(default)]
+    input_attachments: Vec<InputAttachmentFile>,
+    #[serde(default)]
+    depth_attachment: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InputAttachmentFile {
+    Color(usize),
+    Depth,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ClearFile {
+    Color([f32; 4]),
+    ColorInt([i32; 4]),
+    ColorUint([u32; 4]),
+    Depth(f32),
+    Stencil(u32),
+    DepthStencil((f32, u32)),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum LoadOpFile {
+    #[default]
+    DontCare,
+    Load,
+    None,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum StoreOpFile {
+    #[default]
+    Store,
+    DontCare,
+    None,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SizeFile {
+    Relative(f32),
+    Fixed([u32; 2]),
+    Custom(SizeCallbackName),
+}
+
+/// "window" or the name of a vk::Format
+enum TargetFile {
+    Window,
+    Format(vk::Format),
+}
+
+impl<'de> Deserialize<'de> for TargetFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if name == "window" {
+            return Ok(TargetFile::Window);
+        }
+        // Core formats are contiguous
+        (0..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw())
+            .map(vk::Format::from_raw)
+            .find(|format| format!("{format:?}") == name)
+            .map(TargetFile::Format)
+            .ok_or(D::Error::custom(format!("unknown format `{name}`")))
+    }
+}
+
+struct SamplesFile(vk::SampleCountFlags);
+
+impl Default for SamplesFile {
+    fn default() -> Self {
+        Self(vk::SampleCountFlags::_1)
+    }
+}
+
+impl<'de> Deserialize<'de> for SamplesFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let samples = u32::deserialize(deserializer)?;
+        match vk::SampleCountFlags::from_bits(samples) {
+            Some(flags) if samples.count_ones() == 1 => { Ok(Self(flags)) }
+            _ => { Err(D::Error::custom(format!("invalid sample count {samples}"))) }
+        }
+    }
+}
+
+struct CallbackName(String);
+
+impl<'de> Deserialize<'de> for CallbackName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if !KNOWN_BINDINGS.with(|known| known.borrow().render_callbacks.contains(&name)) {
+            return Err(D::Error::custom(format!("unknown render callback `{name}`")));
+        }
+        Ok(Self(name))
+    }
+}
+
+struct SizeCallbackName(String);
+
+impl<'de> Deserialize<'de> for SizeCallbackName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if !KNOWN_BINDINGS.with(|known| known.borrow().size_callbacks.contains(&name)) {
+            return Err(D::Error::custom(format!("unknown size callback `{name}`")));
+        }
+        Ok(Self(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::gfx::frame_graph::frame_graph_file::JsonSpan;
+
+    #[test]
+    fn json_spans_locate_nested_values() {
+        let source = r#"{
+            "name": "forward",
+            "present": {
+                "callback": "draw \"ui\"",
+                "pass": { "color_attachments": [{ "target": "window" }, { "target": "R8G8B8A8_UNORM" }] },
+                "dependencies": [{ "name": "forward", "pass": { "depth_attachment": { "target": "D32_SFLOAT" } } }]
+            }
+        }"#;
+        let line = |span: Option<&JsonSpan>| source[..span.unwrap().offset()].matches('\n').count() + 1;
+        let spans = JsonSpan::parse(source).unwrap();
+        let present = spans.get("present");
+        assert_eq!(line(present), 3);
+        let attachment = present.and_then(|span| span.get("pass")).and_then(|span| span.get("color_attachments")).and_then(|span| span.index(1));
+        assert_eq!(&source[attachment.unwrap().offset()..][..14], "{ \"target\": \"R");
+        // The renderer name on the first line isn't mistaken for the name of the dependency
+        let dependency = present.and_then(|span| span.get("dependencies")).and_then(|span| span.index(0));
+        assert_eq!(line(dependency.and_then(|span| span.get("name"))), 6);
+        assert!(present.and_then(|span| span.get("ui")).is_none());
+    }
+}
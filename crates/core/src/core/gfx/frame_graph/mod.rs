@@ -1,2 +1,5 @@
 pub mod renderer;
-pub mod frame_graph_definition;
\ No newline at end of file
+pub mod frame_graph_definition;
+pub mod attachment_pool;
+pub mod frame_graph_file;
+pub mod frame_graph_export;
@@ -1,14 +1,20 @@
 use crate::core::gfx::command_buffer::{CommandBuffer, Scissors, Viewport};
 use crate::core::gfx::device::DeviceCtx;
-use crate::core::gfx::frame_graph::frame_graph_definition::{ClearValues, RenderPass, RenderPassName, RenderTarget, Renderer, RendererStage};
+use crate::core::gfx::frame_graph::attachment_pool::{AttachmentInstance, AttachmentMemoryStats, AttachmentPool};
+use crate::core::gfx::frame_graph::frame_graph_export::{AttachmentDescription, FrameGraphDescription, PassDescription};
+use crate::core::gfx::frame_graph::frame_graph_definition::{RenderPass, RenderPassAttachment, RenderPassName, RenderTarget, Renderer, RendererStage, SubpassAttachment};
 use crate::core::gfx::queues::QueueFlag;
-use crate::core::gfx::resources::image::{Image, ImageCreateOptions};
+use crate::core::gfx::resources::image::{format_aspects, Image};
+use crate::core::gfx::resources::pipeline::AttachmentFormats;
 use crate::core::gfx::swapchain::{FrameData, SwapchainCtx};
 use types::resource_handle::{Resource, ResourceHandle};
 use vulkanalia::vk;
-use vulkanalia::vk::{DeviceV1_0, Extent2D, HasBuilder};
+use std::time::Duration;
+use vulkanalia::vk::{DeviceV1_0, DeviceV1_3, Extent2D, Handle, HasBuilder, KhrDynamicRenderingExtension};
 use types::profiler::Profiler;
+use tracing::warn;
 use crate::core::gfx::ui::imgui::{ImGui, UiPtr};
+use anyhow::{anyhow, Error};
 
 pub enum FrameGraphTargetInstance {
     Swapchain(SwapchainCtx),
@@ -16,91 +22,159 @@ pub enum FrameGraphTargetInstance {
     Internal(Vec<AttachmentInstance>),
 }
 
-pub struct AttachmentInstance {
-    images: Vec<Resource<Image>>,
-}
-
-impl AttachmentInstance {
-    pub fn new(ctx: &DeviceCtx, format: vk::Format, is_depth: bool, image_count: u32, res: vk::Extent2D) -> Self {
-        let mut images = vec![];
-
-        for _ in 0..image_count {
-            images.push(Image::new(ctx.clone(), ImageCreateOptions {
-                image_type: vk::ImageType::_2D,
-                format,
-                usage: if is_depth { vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT } else { vk::ImageUsageFlags::COLOR_ATTACHMENT } | vk::ImageUsageFlags::SAMPLED,
-                width: res.width,
-                height: res.height,
-                depth: 1,
-                mips_levels: 1,
-                is_depth,
-            }).unwrap());
-        }
-
-        Self {
-            images,
+impl FrameGraphTargetInstance {
+    fn draw_res(&self) -> Extent2D {
+        match self {
+            FrameGraphTargetInstance::Swapchain(swapchain) => { Extent2D { width: swapchain.window().width().unwrap(), height: swapchain.window().height().unwrap() } }
+            FrameGraphTargetInstance::Image(image) => { image[0].res() }
+            FrameGraphTargetInstance::Internal(attachments) => {
+                // The framebuffer can't be larger than any of its attachments
+                attachments.iter().fold(attachments[0].res(), |res, attachment| Extent2D {
+                    width: res.width.min(attachment.res().width),
+                    height: res.height.min(attachment.res().height),
+                })
+            }
         }
     }
 }
 
-
 pub struct RendererInstance {
+    name: String,
     present_pass: Resource<RenderPassInstance>,
-    imgui: Resource<ImGui>,
+    attachments: Resource<AttachmentPool>,
+    imgui: Option<Resource<ImGui>>,
+    // Instance drawing the UI
+    ui_pass: Option<ResourceHandle<RenderPassInstance>>,
+    frame_graph_view: bool,
 }
 
 impl RendererInstance {
-    pub fn new(ctx: DeviceCtx, base: Renderer, target: FrameGraphTargetInstance) -> Resource<Self> {
-        let render_pass_object = ctx.find_render_pass(&base.present_stage.name).unwrap();
+    pub fn new(ctx: DeviceCtx, base: Renderer, target: FrameGraphTargetInstance) -> Result<Resource<Self>, Error> {
+        let render_pass_object = ctx.find_render_pass(&base.present_stage.name)?;
 
         let render_res = match &target {
-            FrameGraphTargetInstance::Swapchain(swapchain) => {
-                Extent2D {
-                    width: swapchain.window().width().unwrap(),
-                    height: swapchain.window().height().unwrap(),
-                }
-            }
-            FrameGraphTargetInstance::Image(images) => { images[0].res() }
             FrameGraphTargetInstance::Internal(_) => { panic!("Invalild target") }
+            target => { target.draw_res() }
         };
 
-        let imgui = ImGui::new(ctx.clone(), render_res, &render_pass_object).unwrap();
+        let image_count = match &target {
+            FrameGraphTargetInstance::Swapchain(swapchain) => { swapchain.get_swapchain_images().len() - 1 }
+            FrameGraphTargetInstance::Image(images) => { images.len() }
+            FrameGraphTargetInstance::Internal(_) => { panic!("Invalild target") }
+        };
+        let present_image_count = match &target {
+            FrameGraphTargetInstance::Swapchain(swapchain) => { swapchain.get_swapchain_images().len() }
+            _ => { image_count }
+        };
+        let attachments = AttachmentPool::new(ctx.clone(), &base.present_stage, image_count, present_image_count, render_res)?;
 
-        if let FrameGraphTargetInstance::Swapchain(swapchain) = &target {
-            imgui.set_target_window_for_inputs(swapchain.window().clone());
-        }
+        let input_window = match &target {
+            FrameGraphTargetInstance::Swapchain(swapchain) => { Some(swapchain.window().clone()) }
+            _ => { None }
+        };
 
         let mut renderer = Resource::new(Self {
+            name: base.name,
             present_pass: Default::default(),
-            imgui,
+            attachments,
+            imgui: None,
+            ui_pass: None,
+            frame_graph_view: false,
         });
-        renderer.present_pass = render_pass_object.instantiate(base.present_stage, target, renderer.handle());
-
+        renderer.present_pass = render_pass_object.instantiate(base.present_stage, target, renderer.handle(), &mut 0);
+
+        if let Some(ui) = base.ui {
+            let ui_object = ctx.find_render_pass(&ui.pass)?;
+            let ui_pass = renderer.present_pass.find_instance(&ui_object).ok_or_else(|| anyhow!("UI pass {} is not part of renderer {}", ui.pass, renderer.name))?;
+            let imgui = ImGui::new(ctx.clone(), ui_pass.draw_res(), &ui_object)?;
+            if let Some(window) = input_window {
+                imgui.set_target_window_for_inputs(window);
+            }
+            renderer.imgui = Some(imgui);
+            renderer.ui_pass = Some(ui_pass);
+        }
 
-        renderer
+        Ok(renderer)
     }
 
     pub fn resize(&mut self) {
+        self.attachments.resize(self.present_pass.target.draw_res()).unwrap();
         self.present_pass.resize();
     }
 
+    /// Dynamic resolution : relative and custom sized attachments are resolved against the output resolution multiplied by this scale.
+    /// Changing the scale waits for the device to be idle and recreates every internal attachment.
+    pub fn set_resolution_scale(&mut self, resolution_scale: f32) {
+        if self.attachments.resolution_scale() == resolution_scale {
+            return;
+        }
+        self.present_pass.ctx.wait_idle();
+        self.attachments.set_resolution_scale(resolution_scale).unwrap();
+        self.present_pass.resize();
+    }
+
+    pub fn resolution_scale(&self) -> f32 {
+        self.attachments.resolution_scale()
+    }
+
+    pub fn attachment_memory_stats(&self) -> &AttachmentMemoryStats {
+        self.attachments.stats()
+    }
+
     pub fn draw(&mut self, data: &FrameData, target_index: usize) {
         self.present_pass.draw(data, target_index);
     }
 
-    pub fn ui<'a>(&self) -> UiPtr<'a> {
-        self.imgui.ui()
+    /// None if the renderer has no UI stage
+    pub fn ui<'a>(&self) -> Option<UiPtr<'a>> {
+        self.imgui.as_ref().map(|imgui| imgui.ui())
     }
 
     pub fn present_pass(&self) -> &RenderPassInstance {
         &self.present_pass
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Passes, attachments, synchronization and last GPU timings of this renderer
+    pub fn describe(&self) -> FrameGraphDescription {
+        let mut passes = vec![];
+        self.present_pass.describe(&mut passes);
+        FrameGraphDescription {
+            renderer: self.name.clone(),
+            passes,
+            attachment_memory: *self.attachments.stats(),
+        }
+    }
+
+    pub fn export_dot(&self) -> String {
+        self.describe().to_dot()
+    }
+
+    pub fn export_json(&self) -> String {
+        self.describe().to_json()
+    }
+
+    /// Draw a live view of the frame graph in the ImGui frame of this renderer. Requires a UI stage.
+    pub fn set_frame_graph_view_visible(&mut self, visible: bool) {
+        self.frame_graph_view = visible;
+    }
+
+    pub fn frame_graph_view_visible(&self) -> bool {
+        self.frame_graph_view
+    }
 }
 
 pub struct RenderPassObject {
     ctx: DeviceCtx,
     base: RenderPass,
     render_pass: vk::RenderPass,
+    color_formats: Vec<vk::Format>,
+    depth_format: Option<vk::Format>,
+    sample_count: vk::SampleCountFlags,
+    subpass_count: usize,
     _instances: Vec<Resource<RenderPassInstance>>,
     self_ctx: ResourceHandle<RenderPassObject>,
 }
@@ -109,8 +183,11 @@ impl RenderPassObject {
     pub fn new(ctx: DeviceCtx, base: &RenderPass) -> Resource<Self> {
         let mut attachment_descriptions = Vec::<vk::AttachmentDescription>::new();
         let mut color_attachment_references = Vec::<vk::AttachmentReference>::new();
-        let mut _depth_attachment_reference = vk::AttachmentReference::default();
-        let mut clear_values = Vec::new();
+        let mut resolve_attachment_references = Vec::<vk::AttachmentReference>::new();
+        let mut depth_attachment_reference = None;
+        let mut color_formats = vec![];
+        let mut depth_format = None;
+        let mut sample_count = vk::SampleCountFlags::_1;
 
         // add color color_attachments
         for attachment in &base.color_attachments
@@ -126,37 +203,42 @@ impl RenderPassObject {
                 RenderTarget::Image(image) => { (false, image.format()) }
                 RenderTarget::Internal(format) => { (false, *format) }
             };
+            assert!(!attachment.transient || !present_pass, "Window attachments cannot be transient");
+            color_formats.push(format);
+            sample_count = attachment.samples;
 
             let attachment_index: u32 = attachment_descriptions.len() as u32;
 
+            // When resolved, the target is only written by the resolve operation
+            let resolved = attachment.is_resolved();
             attachment_descriptions.push(vk::AttachmentDescription::builder()
                 .format(format)
-                .samples(vk::SampleCountFlags::_1)
-                .load_op(match attachment.clear_value {
-                    ClearValues::DontClear => { vk::AttachmentLoadOp::DONT_CARE }
-                    _ => { vk::AttachmentLoadOp::CLEAR }
-                }
-                )
-                .store_op(vk::AttachmentStoreOp::STORE)
+                .samples(if resolved { vk::SampleCountFlags::_1 } else { attachment.samples })
+                .load_op(if resolved { vk::AttachmentLoadOp::DONT_CARE } else { attachment.vk_load_op(attachment.clear_value.is_color()) })
+                .store_op(attachment.vk_store_op())
                 .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
                 .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(if present_pass { vk::ImageLayout::PRESENT_SRC_KHR } else { vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL })
+                .initial_layout(initial_layout(attachment, present_pass, false))
+                .final_layout(final_layout(attachment, present_pass, false))
                 .build());
 
-            color_attachment_references.push(vk::AttachmentReference {
+            let reference = vk::AttachmentReference {
                 attachment: attachment_index,
                 layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            });
-
-            clear_values.push(attachment.clear_value);
+            };
+            if resolved {
+                resolve_attachment_references.push(reference);
+                // Will point to the multisampled image, added after every target
+                color_attachment_references.push(vk::AttachmentReference::default());
+            } else {
+                resolve_attachment_references.push(vk::AttachmentReference {
+                    attachment: vk::ATTACHMENT_UNUSED,
+                    layout: vk::ImageLayout::UNDEFINED,
+                });
+                color_attachment_references.push(reference);
+            }
         }
 
-        let mut subpass = vk::SubpassDescription::builder()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(color_attachment_references.as_slice())
-            .build();
-
         // add depth attachment
         match &base.depth_attachment {
             None => {}
@@ -167,51 +249,171 @@ impl RenderPassObject {
                     RenderTarget::Image(image) => { image.format() }
                     RenderTarget::Internal(format) => { *format }
                 };
+                depth_format = Some(format);
+                sample_count = attachment.samples;
+                let aspects = format_aspects(format);
                 attachment_descriptions.push(vk::AttachmentDescription::builder()
                     .format(format)
-                    .samples(vk::SampleCountFlags::_1)
-                    .load_op(match attachment.clear_value {
-                        ClearValues::DontClear => { vk::AttachmentLoadOp::DONT_CARE }
-                        _ => { vk::AttachmentLoadOp::CLEAR }
-                    })
-                    .store_op(vk::AttachmentStoreOp::STORE)
-                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                    .initial_layout(vk::ImageLayout::UNDEFINED)
-                    .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .samples(attachment.samples)
+                    .load_op(if aspects.contains(vk::ImageAspectFlags::DEPTH) { attachment.vk_load_op(attachment.clear_value.clears_depth()) } else { vk::AttachmentLoadOp::DONT_CARE })
+                    .store_op(if aspects.contains(vk::ImageAspectFlags::DEPTH) { attachment.vk_store_op() } else { vk::AttachmentStoreOp::DONT_CARE })
+                    .stencil_load_op(if aspects.contains(vk::ImageAspectFlags::STENCIL) { attachment.vk_load_op(attachment.clear_value.clears_stencil()) } else { vk::AttachmentLoadOp::DONT_CARE })
+                    .stencil_store_op(if aspects.contains(vk::ImageAspectFlags::STENCIL) { attachment.vk_store_op() } else { vk::AttachmentStoreOp::DONT_CARE })
+                    .initial_layout(initial_layout(attachment, false, true))
+                    .final_layout(final_layout(attachment, false, true))
                     .build());
 
-                _depth_attachment_reference = vk::AttachmentReference::builder()
+                depth_attachment_reference = Some(vk::AttachmentReference::builder()
                     .attachment(attachment_index)
                     .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-                    .build();
-                subpass.depth_stencil_attachment = &_depth_attachment_reference;
-                clear_values.push(attachment.clear_value);
+                    .build());
             }
         };
 
-        let dependencies = vec![
-            vk::SubpassDependency::builder()
-                .src_subpass(vk::SUBPASS_EXTERNAL)                                                             // Producer of the dependency
-                .dst_subpass(0)                                                                            // Consumer is our single subpass that will wait for the execution dependency
-                .src_stage_mask(vk::PipelineStageFlags::BOTTOM_OF_PIPE)                                        // Match our pWaitDstStageMask when we vkQueueSubmit
-                .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)                               // is a loadOp stage for color color_attachments
-                .src_access_mask(vk::AccessFlags::MEMORY_READ)                                                 // semaphore wait already does memory dependency for us
-                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE) // is a loadOp CLEAR access mask for color color_attachments
-                .dependency_flags(vk::DependencyFlags::BY_REGION)
-                .build(),
-            vk::SubpassDependency::builder()
-                .src_subpass(0)                                                                            // Producer of the dependency is our single subpass
-                .dst_subpass(vk::SUBPASS_EXTERNAL)                                                             // Consumer are all commands outside of the render pass
-                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)                               // is a storeOp stage for color color_attachments
-                .dst_stage_mask(vk::PipelineStageFlags::BOTTOM_OF_PIPE)                                        // Do not block any subsequent work
-                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE) // is a storeOp `STORE` access mask for color color_attachments
-                .dst_access_mask(vk::AccessFlags::MEMORY_READ)
+        // add multisampled images, in the same order as the framebuffer views
+        for (index, attachment) in base.color_attachments.iter().enumerate() {
+            if !attachment.is_resolved() {
+                continue;
+            }
+            color_attachment_references[index] = vk::AttachmentReference {
+                attachment: attachment_descriptions.len() as u32,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            };
+            attachment_descriptions.push(vk::AttachmentDescription::builder()
+                .format(color_formats[index])
+                .samples(attachment.samples)
+                .load_op(if attachment.clear_value.is_color() { vk::AttachmentLoadOp::CLEAR } else { vk::AttachmentLoadOp::DONT_CARE })
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .build());
+        }
+
+        let subpass_declarations = base.effective_subpasses();
+        let attachment_index = |attachment: &SubpassAttachment| match attachment {
+            SubpassAttachment::Color(index) => { color_attachment_references[*index].attachment }
+            SubpassAttachment::Depth => { depth_attachment_reference.expect("This render pass doesn't have a depth attachment").attachment }
+        };
+
+        // References are stored until the render pass is created
+        let mut subpass_color_references = vec![];
+        let mut subpass_resolve_references = vec![];
+        let mut subpass_input_references = vec![];
+        let mut subpass_used_attachments = vec![];
+        for subpass in &subpass_declarations {
+            subpass_color_references.push(subpass.color_attachments.iter().map(|index| color_attachment_references[*index]).collect::<Vec<_>>());
+            subpass_resolve_references.push(subpass.color_attachments.iter().map(|index| resolve_attachment_references[*index]).collect::<Vec<_>>());
+            subpass_input_references.push(subpass.input_attachments.iter().map(|attachment| vk::AttachmentReference {
+                attachment: attachment_index(attachment),
+                layout: match attachment {
+                    SubpassAttachment::Color(_) => { vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL }
+                    SubpassAttachment::Depth => { vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL }
+                },
+            }).collect::<Vec<_>>());
+
+            let mut used_attachments = subpass.color_attachments.iter().map(|index| SubpassAttachment::Color(*index))
+                .chain(subpass.input_attachments.iter().copied())
+                .map(|attachment| attachment_index(&attachment))
+                .collect::<Vec<_>>();
+            if subpass.depth_attachment {
+                used_attachments.push(attachment_index(&SubpassAttachment::Depth));
+            }
+            subpass_used_attachments.push(used_attachments);
+        }
+
+        // Attachments used before and after a subpass must be preserved by it
+        let mut subpass_preserve_references = vec![];
+        for (index, used_attachments) in subpass_used_attachments.iter().enumerate() {
+            let used_before = subpass_used_attachments[..index].iter().flatten().collect::<Vec<_>>();
+            let used_after = subpass_used_attachments[index + 1..].iter().flatten().collect::<Vec<_>>();
+            let mut preserved = used_before.into_iter().filter(|attachment| used_after.contains(attachment) && !used_attachments.contains(attachment)).copied().collect::<Vec<_>>();
+            preserved.sort_unstable();
+            preserved.dedup();
+            subpass_preserve_references.push(preserved);
+        }
+
+        let mut subpasses = vec![];
+        for (index, subpass) in subpass_declarations.iter().enumerate() {
+            let mut description = vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(subpass_color_references[index].as_slice())
+                .input_attachments(subpass_input_references[index].as_slice())
+                .preserve_attachments(subpass_preserve_references[index].as_slice());
+            if subpass.color_attachments.iter().any(|index| base.color_attachments[*index].is_resolved()) {
+                description = description.resolve_attachments(subpass_resolve_references[index].as_slice());
+            }
+            if subpass.depth_attachment {
+                description = description.depth_stencil_attachment(depth_attachment_reference.as_ref().expect("This render pass doesn't have a depth attachment"));
+            }
+            subpasses.push(description.build());
+        }
+
+        // Attachments memory may be aliased with attachments of previous passes : the depth load op should also wait for them
+        let (depth_stages, depth_access) = if base.depth_attachment.is_some() {
+            (vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS, vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        } else {
+            (vk::PipelineStageFlags::empty(), vk::AccessFlags::empty())
+        };
+
+        // Only the first and the last subpass using an attachment depend on the commands outside of the render pass
+        let depth_attachment_index = depth_attachment_reference.map(|reference| reference.attachment);
+        let attachment_scope = |attachment: u32| if Some(attachment) == depth_attachment_index {
+            (depth_stages, depth_access)
+        } else {
+            (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        };
+        let mut dependencies = vec![];
+        for (subpass, used_attachments) in subpass_used_attachments.iter().enumerate() {
+            let (mut load_stages, mut load_access) = (vk::PipelineStageFlags::empty(), vk::AccessFlags::empty());
+            let (mut store_stages, mut store_access) = (vk::PipelineStageFlags::empty(), vk::AccessFlags::empty());
+            for attachment in used_attachments {
+                let (stages, access) = attachment_scope(*attachment);
+                if !subpass_used_attachments[..subpass].iter().flatten().any(|other| other == attachment) {
+                    load_stages |= stages;
+                    load_access |= access;
+                }
+                if !subpass_used_attachments[subpass + 1..].iter().flatten().any(|other| other == attachment) {
+                    store_stages |= stages;
+                    store_access |= access;
+                }
+            }
+            if !load_stages.is_empty() {
+                dependencies.push(vk::SubpassDependency::builder()
+                    .src_subpass(vk::SUBPASS_EXTERNAL)                                                         // Producer of the dependency
+                    .dst_subpass(subpass as u32)                                                               // Attachments are loaded by the first subpass using them
+                    .src_stage_mask(vk::PipelineStageFlags::BOTTOM_OF_PIPE)                                    // Match our pWaitDstStageMask when we vkQueueSubmit
+                    .dst_stage_mask(load_stages)                                                               // is a loadOp stage for the attachments
+                    .src_access_mask(vk::AccessFlags::MEMORY_READ)                                             // semaphore wait already does memory dependency for us
+                    .dst_access_mask(load_access)                                                              // is a loadOp CLEAR access mask for the attachments
+                    .dependency_flags(vk::DependencyFlags::BY_REGION)
+                    .build());
+            }
+            if !store_stages.is_empty() {
+                dependencies.push(vk::SubpassDependency::builder()
+                    .src_subpass(subpass as u32)                                                               // Attachments are stored by the last subpass using them
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)                                                         // Consumer are all commands outside of the render pass
+                    .src_stage_mask(store_stages)                                                              // is a storeOp stage for the attachments
+                    .dst_stage_mask(vk::PipelineStageFlags::BOTTOM_OF_PIPE)                                    // Do not block any subsequent work
+                    .src_access_mask(store_access)                                                             // is a storeOp `STORE` access mask for the attachments
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ)
+                    .dependency_flags(vk::DependencyFlags::BY_REGION)
+                    .build());
+            }
+        }
+        for dependency in &base.subpass_dependencies {
+            dependencies.push(vk::SubpassDependency::builder()
+                .src_subpass(dependency.src_subpass as u32)
+                .dst_subpass(dependency.dst_subpass as u32)
+                .src_stage_mask(dependency.src_stage_mask)
+                .dst_stage_mask(dependency.dst_stage_mask)
+                .src_access_mask(dependency.src_access_mask)
+                .dst_access_mask(dependency.dst_access_mask)
                 .dependency_flags(vk::DependencyFlags::BY_REGION)
-                .build(),
-        ];
+                .build());
+        }
 
-        let subpasses = vec![subpass];
         let render_pass_infos = vk::RenderPassCreateInfo::builder()
             .attachments(attachment_descriptions.as_slice())
             .subpasses(subpasses.as_slice())
@@ -219,12 +421,21 @@ impl RenderPassObject {
             .build();
 
 
-        let render_pass = unsafe { ctx.device().create_render_pass(&render_pass_infos, None) }.unwrap();
+        // Dynamic rendering only needs the declaration of the attachments
+        let render_pass = if ctx.dynamic_rendering() {
+            vk::RenderPass::null()
+        } else {
+            unsafe { ctx.device().create_render_pass(&render_pass_infos, None) }.unwrap()
+        };
 
         let mut pass = Resource::new(Self {
             ctx,
             base: base.clone(),
             render_pass,
+            color_formats,
+            depth_format,
+            sample_count,
+            subpass_count: subpass_declarations.len(),
             _instances: vec![],
             self_ctx: Default::default(),
         });
@@ -241,48 +452,78 @@ impl RenderPassObject {
         &self.render_pass
     }
 
-    fn instantiate(&self, mut stage: RendererStage, target: FrameGraphTargetInstance, renderer: ResourceHandle<RendererInstance>) -> Resource<RenderPassInstance> {
+    pub fn color_formats(&self) -> &[vk::Format] {
+        &self.color_formats
+    }
+
+    pub fn depth_format(&self) -> Option<vk::Format> {
+        self.depth_format
+    }
+
+    /// Sample count of the subpass, used by the pipelines drawing in this pass
+    pub fn sample_count(&self) -> vk::SampleCountFlags {
+        self.sample_count
+    }
+
+    pub fn subpass_count(&self) -> usize {
+        self.subpass_count
+    }
+
+    /// Attachments written by the given subpass, used to create the pipelines drawing in it
+    pub fn attachment_formats(&self, subpass: u32) -> AttachmentFormats {
+        let subpasses = self.base.effective_subpasses();
+        let subpass_declaration = &subpasses[subpass as usize];
+        AttachmentFormats {
+            color_formats: subpass_declaration.color_attachments.iter().map(|index| self.color_formats[*index]).collect(),
+            depth_format: if subpass_declaration.depth_attachment { self.depth_format } else { None },
+            sample_count: self.sample_count,
+            render_pass: if self.render_pass.is_null() { None } else { Some((self.render_pass, subpass)) },
+        }
+    }
+
+    fn instantiate(&self, mut stage: RendererStage, target: FrameGraphTargetInstance, renderer: ResourceHandle<RendererInstance>, last_node: &mut usize) -> Resource<RenderPassInstance> {
         let mut children = vec![];
 
-        let draw_res = match &target {
-            FrameGraphTargetInstance::Swapchain(swapchain) => { Extent2D { width: swapchain.window().width().unwrap(), height: swapchain.window().height().unwrap() } }
-            FrameGraphTargetInstance::Image(image) => { image[0].res() }
-            FrameGraphTargetInstance::Internal(attachment) => { attachment[0].images[0].res() }
-        };
+        assert_eq!(stage.subpass_callbacks.len() + 1, self.subpass_count, "Render pass {} expects one callback per subpass", self.base.name);
+        let draw_res = target.draw_res();
+        let multisampled_attachments = renderer.attachments.node_multisampled_attachments(*last_node);
 
-        let (framebuffer_count, image_count) = match &target {
-            FrameGraphTargetInstance::Swapchain(swapchain) => { (swapchain.get_swapchain_images().len(), swapchain.get_swapchain_images().len() - 1) }
-            FrameGraphTargetInstance::Image(images) => { (images.len(), images.len()) }
-            FrameGraphTargetInstance::Internal(attachments) => { (attachments[0].images.len(), attachments[0].images.len()) }
+        let framebuffer_count = match &target {
+            FrameGraphTargetInstance::Swapchain(swapchain) => { swapchain.get_swapchain_images().len() }
+            FrameGraphTargetInstance::Image(images) => { images.len() }
+            FrameGraphTargetInstance::Internal(attachments) => { attachments[0].image_count() }
         };
 
         for stage in stage.dependencies {
             let child = self.ctx.find_render_pass(&stage.name).unwrap();
 
-            let mut attachments = vec![];
+            // Nodes are numbered in the same order as when the attachment pool was built
+            *last_node += 1;
+            let attachments = renderer.attachments.node_attachments(*last_node);
 
-            for color in &child.base.color_attachments {
-                let format = match color.source {
-                    RenderTarget::Internal(format) => { format }
-                    _ => panic!("Only internal formats are allowed for children targets")
-                };
-                attachments.push(AttachmentInstance::new(&self.ctx, format, false, image_count as u32, draw_res));
-            }
-            if let Some(depth) = &child.base.depth_attachment {
-                let format = match depth.source {
-                    RenderTarget::Internal(format) => { format }
-                    _ => panic!("Only internal formats are allowed for children targets")
-                };
-                attachments.push(AttachmentInstance::new(&self.ctx, format, true, image_count as u32, draw_res));
-            }
-
-            children.push(child.instantiate(stage, FrameGraphTargetInstance::Internal(attachments), renderer.clone()));
+            children.push(child.instantiate(stage, FrameGraphTargetInstance::Internal(attachments), renderer.clone(), last_node));
         }
         stage.dependencies = vec![];
 
+        // Two timestamps per framebuffer : beginning and end of the command buffer
+        let timestamp_valid_bits = self.ctx.queues().find_queue(&QueueFlag::Graphic).map_or(0, |queue| queue.timestamp_valid_bits());
+        let timestamps = if timestamp_valid_bits > 0 {
+            let create_info = vk::QueryPoolCreateInfo::builder()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(2 * framebuffer_count as u32);
+            Some(unsafe { self.ctx.device().create_query_pool(&create_info, None) }.unwrap())
+        } else {
+            None
+        };
+
         let mut instance = Resource::new(RenderPassInstance {
             framebuffers: vec![],
+            timestamps,
+            timestamps_written: vec![false; framebuffer_count],
+            timestamp_mask: if timestamp_valid_bits >= 64 { u64::MAX } else { (1u64 << timestamp_valid_bits) - 1 },
+            gpu_time: None,
             children,
+            multisampled_attachments,
             ctx: self.ctx.clone(),
             object: self.self_ctx.clone(),
             current_draw_res: draw_res,
@@ -303,14 +544,22 @@ impl RenderPassObject {
 
 impl Drop for RenderPassObject {
     fn drop(&mut self) {
-        unsafe { self.ctx.device().destroy_render_pass(self.render_pass, None) };
+        if !self.render_pass.is_null() {
+            unsafe { self.ctx.device().destroy_render_pass(self.render_pass, None) };
+        }
     }
 }
 
 
 pub struct RenderPassInstance {
     framebuffers: Vec<Framebuffer>,
+    timestamps: Option<vk::QueryPool>,
+    timestamps_written: Vec<bool>,
+    // Timestamps only have `timestampValidBits` meaningful bits and may wrap around
+    timestamp_mask: u64,
+    gpu_time: Option<Duration>,
     children: Vec<Resource<RenderPassInstance>>,
+    multisampled_attachments: Vec<AttachmentInstance>,
     object: ResourceHandle<RenderPassObject>,
     ctx: DeviceCtx,
     current_draw_res: Extent2D,
@@ -322,12 +571,7 @@ pub struct RenderPassInstance {
 
 impl RenderPassInstance {
     pub fn resize(&mut self) {
-        let draw_res = match &self.target {
-            FrameGraphTargetInstance::Swapchain(swapchain) => { Extent2D { width: swapchain.window().width().unwrap(), height: swapchain.window().height().unwrap() } }
-            FrameGraphTargetInstance::Image(image) => { image[0].res() }
-            FrameGraphTargetInstance::Internal(attachment) => { attachment[0].images[0].res() }
-        };
-        self.current_draw_res = draw_res;
+        self.current_draw_res = self.target.draw_res();
         for child in &mut self.children {
             child.resize();
         }
@@ -343,16 +587,306 @@ impl RenderPassInstance {
         &self.object
     }
 
+    /// Extent of the framebuffers, viewport and scissor of this pass
+    pub fn draw_res(&self) -> Extent2D {
+        self.current_draw_res
+    }
+
+    /// View of an attachment of this pass, as seen by the subpasses reading it as an input attachment.
+    /// Multisampled attachments return the image before resolve.
+    pub fn attachment_view(&self, attachment: SubpassAttachment, image_index: usize) -> Option<vk::ImageView> {
+        let color_attachments = &self.object.base.color_attachments;
+        if let SubpassAttachment::Color(index) = attachment {
+            if color_attachments[index].is_resolved() {
+                let multisampled_index = color_attachments[..index].iter().filter(|attachment| attachment.is_resolved()).count();
+                return self.multisampled_attachments[multisampled_index].image(image_index).view().ok().copied();
+            }
+        }
+        match (&self.target, attachment) {
+            (FrameGraphTargetInstance::Swapchain(_), _) => { None }
+            (FrameGraphTargetInstance::Image(images), SubpassAttachment::Color(0)) => { images[image_index].view().ok().copied() }
+            (FrameGraphTargetInstance::Image(_), _) => { None }
+            (FrameGraphTargetInstance::Internal(attachments), SubpassAttachment::Color(index)) => { attachments[index].image(image_index).view().ok().copied() }
+            (FrameGraphTargetInstance::Internal(attachments), SubpassAttachment::Depth) => { attachments[color_attachments.len()].image(image_index).view().ok().copied() }
+        }
+    }
+
+    /// Images and views in the order of the attachment descriptions : targets, then multisampled images
+    fn attachment_images(&self, image_index: usize) -> Vec<(vk::Image, vk::ImageView)> {
+        let mut images = vec![];
+        match &self.target {
+            FrameGraphTargetInstance::Swapchain(swapchain) => {
+                images.push((swapchain.get_swapchain_image_handles()[image_index], swapchain.get_swapchain_images()[image_index]))
+            }
+            FrameGraphTargetInstance::Image(target_images) => {
+                images.push((*target_images[image_index].image().unwrap(), *target_images[image_index].view().unwrap()))
+            }
+            FrameGraphTargetInstance::Internal(attachments) => {
+                for attachment in attachments {
+                    let image = attachment.image(image_index);
+                    images.push((*image.image().unwrap(), *image.view().unwrap()))
+                }
+            }
+        }
+        for attachment in &self.multisampled_attachments {
+            let image = attachment.image(image_index);
+            images.push((*image.image().unwrap(), *image.view().unwrap()))
+        }
+        images
+    }
+
+    fn begin_rendering(&self, command_buffer: vk::CommandBuffer, image_index: usize, clear_values: &[vk::ClearValue]) {
+        let base = &self.object.base;
+        let images = self.attachment_images(image_index);
+        let mut multisampled_images = images[images.len() - self.multisampled_attachments.len()..].iter();
+        let mut barriers = vec![];
+
+        let mut color_attachments = vec![];
+        for (index, attachment) in base.color_attachments.iter().enumerate() {
+            let (image, view) = images[index];
+            let present_pass = matches!(self.target, FrameGraphTargetInstance::Swapchain(_));
+            barriers.push(layout_barrier(image, vk::ImageAspectFlags::COLOR, initial_layout(attachment, present_pass, false), vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL));
+            let info = vk::RenderingAttachmentInfo::builder()
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(attachment.vk_load_op(attachment.clear_value.is_color()))
+                .clear_value(clear_values[index]);
+            color_attachments.push(if attachment.is_resolved() {
+                let (multisampled_image, multisampled_view) = multisampled_images.next().unwrap();
+                barriers.push(layout_barrier(*multisampled_image, vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL));
+                info.image_view(*multisampled_view)
+                    .load_op(if attachment.clear_value.is_color() { vk::AttachmentLoadOp::CLEAR } else { vk::AttachmentLoadOp::DONT_CARE })
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                    .resolve_image_view(view)
+                    .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .build()
+            } else {
+                info.image_view(view)
+                    .store_op(attachment.vk_store_op())
+                    .build()
+            });
+        }
+
+        // Depth and stencil aspects of the same image
+        let mut depth_attachment = None;
+        let mut stencil_attachment = None;
+        if let (Some(attachment), Some(format)) = (&base.depth_attachment, self.object.depth_format) {
+            let index = base.color_attachments.len();
+            let (image, view) = images[index];
+            let aspects = format_aspects(format);
+            barriers.push(layout_barrier(image, aspects, initial_layout(attachment, false, true), vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL));
+            let info = |cleared: bool| vk::RenderingAttachmentInfo::builder()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .load_op(attachment.vk_load_op(cleared))
+                .store_op(attachment.vk_store_op())
+                .clear_value(clear_values[index])
+                .build();
+            if aspects.contains(vk::ImageAspectFlags::DEPTH) {
+                depth_attachment = Some(info(attachment.clear_value.clears_depth()));
+            }
+            if aspects.contains(vk::ImageAspectFlags::STENCIL) {
+                stencil_attachment = Some(info(attachment.clear_value.clears_stencil()));
+            }
+        }
+
+        unsafe {
+            let memory_barriers: [vk::MemoryBarrier; 0] = [];
+            let buffer_memory_barriers: [vk::BufferMemoryBarrier; 0] = [];
+            // Chains with the semaphores waited before this pass, whatever their stage
+            self.ctx.device().cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &buffer_memory_barriers,
+                barriers.as_slice());
+        }
+
+        let mut rendering_info = vk::RenderingInfo::builder()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.current_draw_res,
+            })
+            .layer_count(1)
+            .color_attachments(color_attachments.as_slice());
+        if let Some(depth_attachment) = &depth_attachment {
+            rendering_info = rendering_info.depth_attachment(depth_attachment);
+        }
+        if let Some(stencil_attachment) = &stencil_attachment {
+            rendering_info = rendering_info.stencil_attachment(stencil_attachment);
+        }
+        if self.ctx.dynamic_rendering_khr() {
+            unsafe { self.ctx.device().cmd_begin_rendering_khr(command_buffer, &rendering_info); }
+        } else {
+            unsafe { self.ctx.device().cmd_begin_rendering(command_buffer, &rendering_info); }
+        }
+    }
+
+    fn end_rendering(&self, command_buffer: vk::CommandBuffer, image_index: usize) {
+        if self.ctx.dynamic_rendering_khr() {
+            unsafe { self.ctx.device().cmd_end_rendering_khr(command_buffer); }
+        } else {
+            unsafe { self.ctx.device().cmd_end_rendering(command_buffer); }
+        }
+
+        // Match the final layouts of the render pass backend
+        let base = &self.object.base;
+        let images = self.attachment_images(image_index);
+        let mut barriers = vec![];
+        for (index, attachment) in base.color_attachments.iter().enumerate() {
+            if attachment.transient {
+                continue;
+            }
+            let present_pass = matches!(self.target, FrameGraphTargetInstance::Swapchain(_));
+            barriers.push(layout_barrier(images[index].0, vk::ImageAspectFlags::COLOR, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, final_layout(attachment, present_pass, false)));
+        }
+        if let (Some(attachment), Some(format)) = (&base.depth_attachment, self.object.depth_format) {
+            if !attachment.transient {
+                barriers.push(layout_barrier(images[base.color_attachments.len()].0, format_aspects(format), vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, final_layout(attachment, false, true)));
+            }
+        }
+
+        unsafe {
+            let memory_barriers: [vk::MemoryBarrier; 0] = [];
+            let buffer_memory_barriers: [vk::BufferMemoryBarrier; 0] = [];
+            self.ctx.device().cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &buffer_memory_barriers,
+                barriers.as_slice());
+        }
+    }
+
+    pub fn children(&self) -> &[Resource<RenderPassInstance>] {
+        &self.children
+    }
+
     pub fn render_finished_semaphore(&self, image_index: usize) -> vk::Semaphore {
         self.framebuffers[image_index].render_finished_semaphore
     }
 
+    /// First instance of the given pass in this subtree, in pre-order
+    fn find_instance(&self, object: &ResourceHandle<RenderPassObject>) -> Option<ResourceHandle<RenderPassInstance>> {
+        if self.object == *object {
+            return Some(self.self_ctx.clone());
+        }
+        self.children.iter().find_map(|child| child.find_instance(object))
+    }
+
+    /// GPU duration of the last completed submission of this pass. None if the device doesn't support timestamps.
+    pub fn gpu_time(&self) -> Option<Duration> {
+        self.gpu_time
+    }
+
+    /// Append this pass and its children in pre-order, and return the id of this pass
+    fn describe(&self, passes: &mut Vec<PassDescription>) -> usize {
+        let base = &self.object.base;
+        let id = passes.len();
+        let present_pass = matches!(self.target, FrameGraphTargetInstance::Swapchain(_));
+        let attachment_res = |index: usize| {
+            match &self.target {
+                FrameGraphTargetInstance::Internal(attachments) => { attachments[index].res() }
+                _ => { self.current_draw_res }
+            }
+        };
+        let dynamic_rendering = self.object.render_pass.is_null();
+
+        let mut attachments = vec![];
+        let mut barriers = vec![];
+        let mut describe_attachment = |kind: String, format: vk::Format, index: usize, attachment: &RenderPassAttachment, depth: bool| {
+            let attachment_layout = if depth { vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL } else { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL };
+            let (initial_layout, final_layout) = (initial_layout(attachment, present_pass, depth), final_layout(attachment, present_pass, depth));
+            if dynamic_rendering {
+                barriers.push(format!("{kind} : {:?} -> {:?} before rendering", initial_layout, attachment_layout));
+                if !attachment.transient {
+                    barriers.push(format!("{kind} : {:?} -> {:?} after rendering", attachment_layout, final_layout));
+                }
+            } else {
+                barriers.push(format!("{kind} : {:?} -> {:?} (render pass)", initial_layout, final_layout));
+            }
+            let res = attachment_res(index);
+            attachments.push(AttachmentDescription {
+                kind,
+                format: format!("{format:?}"),
+                extent: [res.width, res.height],
+                samples: attachment.samples.bits(),
+                transient: attachment.transient,
+                resolved: attachment.is_resolved(),
+                clear: format!("{:?}", attachment.clear_value),
+                load_op: format!("{:?}", attachment.load_op),
+                store_op: format!("{:?}", attachment.store_op),
+            });
+        };
+        for (index, attachment) in base.color_attachments.iter().enumerate() {
+            describe_attachment(format!("color {index}"), self.object.color_formats[index], index, attachment, false);
+        }
+        if let (Some(attachment), Some(format)) = (&base.depth_attachment, self.object.depth_format) {
+            describe_attachment("depth".to_string(), format, base.color_attachments.len(), attachment, true);
+        }
+        for dependency in &base.subpass_dependencies {
+            barriers.push(format!("subpass {} -> {} : {:?} -> {:?}", dependency.src_subpass, dependency.dst_subpass, dependency.src_stage_mask, dependency.dst_stage_mask));
+        }
+
+        let mut wait_semaphores = vec![];
+        if let FrameGraphTargetInstance::Swapchain(_) = &self.target {
+            wait_semaphores.push(format!("image available ({:?})", vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT));
+        }
+        for child in &self.children {
+            wait_semaphores.push(format!("{} finished ({:?})", child.object.base.name, vk::PipelineStageFlags::ALL_GRAPHICS));
+        }
+
+        passes.push(PassDescription {
+            id,
+            name: base.name.to_string(),
+            target: match &self.target {
+                FrameGraphTargetInstance::Swapchain(_) => { "window" }
+                FrameGraphTargetInstance::Image(_) => { "image" }
+                FrameGraphTargetInstance::Internal(_) => { "internal" }
+            }.to_string(),
+            extent: [self.current_draw_res.width, self.current_draw_res.height],
+            queue: "graphic".to_string(),
+            subpasses: self.object.subpass_count,
+            samples: self.object.sample_count.bits(),
+            attachments,
+            wait_semaphores,
+            barriers,
+            dependencies: vec![],
+            gpu_time_ms: self.gpu_time.map(|time| time.as_secs_f64() * 1000.0),
+        });
+        let dependencies = self.children.iter().map(|child| child.describe(passes)).collect();
+        passes[id].dependencies = dependencies;
+        id
+    }
+
+    /// Read back the timestamps of the previous submission of this framebuffer without waiting for it
+    fn read_gpu_time(&mut self, target_index: usize) {
+        let Some(timestamps) = self.timestamps else { return; };
+        if !self.timestamps_written[target_index] {
+            return;
+        }
+        let mut ticks = [0u64; 2];
+        let data = unsafe { std::slice::from_raw_parts_mut(ticks.as_mut_ptr() as *mut u8, size_of_val(&ticks)) };
+        let result = unsafe { self.ctx.device().get_query_pool_results(timestamps, 2 * target_index as u32, 2, data, size_of::<u64>() as vk::DeviceSize, vk::QueryResultFlags::_64) };
+        if let Ok(vk::SuccessCode::SUCCESS) = result {
+            let period = self.ctx.physical_device().properties().limits.timestamp_period as f64;
+            let elapsed = (ticks[1] & self.timestamp_mask).wrapping_sub(ticks[0] & self.timestamp_mask) & self.timestamp_mask;
+            self.gpu_time = Some(Duration::from_nanos((elapsed as f64 * period) as u64));
+        }
+    }
+
     fn draw(&mut self, data: &FrameData, target_index: usize) {
         let record = Profiler::get().record(format!("Draw render pass").as_str());
         for child in &mut *self.children {
             child.draw(data, data.frame_index);
         }
 
+        self.read_gpu_time(target_index);
+
         let device = &self.ctx;
 
         let framebuffer = &self.framebuffers[target_index];
@@ -360,51 +894,50 @@ impl RenderPassInstance {
 
         // Begin buffer
         framebuffer.command_buffer.begin().unwrap();
+        if let Some(timestamps) = self.timestamps {
+            unsafe {
+                device.device().cmd_reset_query_pool(*framebuffer.command_buffer.ptr().unwrap(), timestamps, 2 * target_index as u32, 2);
+                device.device().cmd_write_timestamp(*framebuffer.command_buffer.ptr().unwrap(), vk::PipelineStageFlags::TOP_OF_PIPE, timestamps, 2 * target_index as u32);
+            }
+        }
 
 
-        let mut clear_values = Vec::new();
-
-        for attachment in &self.object.base.color_attachments {
-            clear_values.push(match attachment.clear_value {
-                ClearValues::DontClear => { vk::ClearValue::default() }
-                ClearValues::Color(color) => {
-                    vk::ClearValue {
-                        color: vk::ClearColorValue {
-                            float32: [color.x, color.y, color.z, color.w]
-                        }
-                    }
+        let base = &self.object.base;
+        let declared_clears = base.color_attachments.iter().chain(&base.depth_attachment).map(|attachment| attachment.clear_value).collect::<Vec<_>>();
+        let mut clears = declared_clears.clone();
+        if let Some(clear_callback) = &mut self.stage.clear_callback {
+            clear_callback(clears.as_mut_slice());
+            for (declared, clear) in declared_clears.iter().zip(clears.iter_mut()) {
+                if std::mem::discriminant(declared) != std::mem::discriminant(clear) {
+                    warn!("Render pass {} : clear override {:?} doesn't keep the declared kind of clear {:?}, using the declared value", base.name, clear, declared);
+                    *clear = *declared;
                 }
-                _ => { panic!("Not a color attachment") }
-            });
+            }
         }
-        if let Some(attachment) = &self.object.base.depth_attachment {
-            clear_values.push(match attachment.clear_value {
-                ClearValues::DontClear => { vk::ClearValue::default() }
-                ClearValues::DepthStencil(depth_stencil) => {
-                    vk::ClearValue {
-                        depth_stencil: vk::ClearDepthStencilValue {
-                            depth: depth_stencil.x,
-                            stencil: depth_stencil.y as u32,
-                        }
-                    }
-                }
-                _ => { panic!("Not a depth attachment") }
-            });
+        let mut clear_values = clears.iter().map(|clear| clear.vk_value()).collect::<Vec<_>>();
+        for (index, attachment) in base.color_attachments.iter().enumerate() {
+            if attachment.is_resolved() {
+                clear_values.push(clears[index].vk_value());
+            }
         }
 
         // begin pass
-        let begin_infos = vk::RenderPassBeginInfo::builder()
-            .render_pass(self.object.render_pass)
-            .framebuffer(framebuffer.vk_framebuffer)
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.current_draw_res,
-            })
-            .clear_values(clear_values.as_slice())
-            .build();
-
+        match framebuffer.vk_framebuffer {
+            Some(vk_framebuffer) => {
+                let begin_infos = vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.object.render_pass)
+                    .framebuffer(vk_framebuffer)
+                    .render_area(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent: self.current_draw_res,
+                    })
+                    .clear_values(clear_values.as_slice())
+                    .build();
 
-        unsafe { device.device().cmd_begin_render_pass(*framebuffer.command_buffer.ptr().unwrap(), &begin_infos, vk::SubpassContents::INLINE); }
+                unsafe { device.device().cmd_begin_render_pass(*framebuffer.command_buffer.ptr().unwrap(), &begin_infos, vk::SubpassContents::INLINE); }
+            }
+            None => { self.begin_rendering(*framebuffer.command_buffer.ptr().unwrap(), target_index, clear_values.as_slice()); }
+        }
 
         framebuffer.command_buffer.set_viewport(&Viewport {
             min_x: 0.0,
@@ -425,23 +958,38 @@ impl RenderPassInstance {
         // Draw content
         let callback = Profiler::get().record("Render callback");
         (self.stage.render_callback)();
+        match framebuffer.vk_framebuffer {
+            Some(_) => {
+                for subpass_callback in &mut self.stage.subpass_callbacks {
+                    unsafe { device.device().cmd_next_subpass(*framebuffer.command_buffer.ptr().unwrap(), vk::SubpassContents::INLINE); }
+                    subpass_callback();
+                }
+            }
+            // Dynamic rendering has no subpasses : passes declaring more than one are rejected by Device::validate_render_pass
+            None => { assert!(self.stage.subpass_callbacks.is_empty(), "Subpasses are not supported with dynamic rendering"); }
+        }
         callback.end();
 
-        let callback = Profiler::get().record("Draw ImGui");
-        // Todo : have a flag for final passes
-        match &self.target {
-            FrameGraphTargetInstance::Swapchain(_) => {
-                self.renderer.imgui.submit_frame(&framebuffer.command_buffer, self.current_draw_res).unwrap();
-            }
-            FrameGraphTargetInstance::Image(_) => {
-                self.renderer.imgui.submit_frame(&framebuffer.command_buffer, self.current_draw_res).unwrap();
+        if let (Some(imgui), Some(ui_pass)) = (&self.renderer.imgui, &self.renderer.ui_pass) {
+            if *ui_pass == self.self_ctx {
+                let callback = Profiler::get().record("Draw ImGui");
+                if self.renderer.frame_graph_view {
+                    let _ui = imgui.ui();
+                    self.renderer.describe().draw_imgui();
+                }
+                imgui.submit_frame(&framebuffer.command_buffer, self.current_draw_res).unwrap();
+                callback.end();
             }
-            FrameGraphTargetInstance::Internal(_) => {}
         }
-        callback.end();
 
         // End pass
-        unsafe { device.device().cmd_end_render_pass(*framebuffer.command_buffer.ptr().unwrap()); }
+        match framebuffer.vk_framebuffer {
+            Some(_) => { unsafe { device.device().cmd_end_render_pass(*framebuffer.command_buffer.ptr().unwrap()); } }
+            None => { self.end_rendering(*framebuffer.command_buffer.ptr().unwrap(), target_index); }
+        }
+        if let Some(timestamps) = self.timestamps {
+            unsafe { device.device().cmd_write_timestamp(*framebuffer.command_buffer.ptr().unwrap(), vk::PipelineStageFlags::BOTTOM_OF_PIPE, timestamps, 2 * target_index as u32 + 1); }
+        }
         framebuffer.command_buffer.end().unwrap();
 
         // Submit buffer
@@ -454,12 +1002,14 @@ impl RenderPassInstance {
             signal_fence = Some(swapchain.get_in_flight_fence(data.frame_index));
         }
 
+        let mut wait_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT; wait_semaphores.len()];
+
+        // Children attachments are sampled, and their memory can be reused by this pass
         for child in &self.children {
             wait_semaphores.push(child.framebuffers[data.frame_index].render_finished_semaphore);
+            wait_stages.push(vk::PipelineStageFlags::ALL_GRAPHICS);
         }
 
-        let wait_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT; wait_semaphores.len()];
-
         let command_buffers = vec![*framebuffer.command_buffer.ptr().unwrap()];
         let signal_semaphores = vec![framebuffer.render_finished_semaphore];
 
@@ -473,13 +1023,65 @@ impl RenderPassInstance {
         let submit_infos = vec![submit_infos];
         submit.end();
         self.ctx.queues().submit(&QueueFlag::Graphic, submit_infos.as_slice(), signal_fence);
+        self.timestamps_written[target_index] = true;
         record.end();
     }
 }
 
+impl Drop for RenderPassInstance {
+    fn drop(&mut self) {
+        if let Some(timestamps) = self.timestamps {
+            unsafe { self.ctx.device().destroy_query_pool(timestamps, None) };
+        }
+    }
+}
+
+
+/// Layout of an attachment at the end of its pass
+fn final_layout(attachment: &RenderPassAttachment, present_pass: bool, is_depth: bool) -> vk::ImageLayout {
+    match (present_pass, attachment.transient, is_depth) {
+        (true, _, _) => { vk::ImageLayout::PRESENT_SRC_KHR }
+        (false, true, false) => { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL }
+        (false, true, true) => { vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL }
+        (false, false, _) => { vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL }
+    }
+}
+
+/// Attachments preserving their content start the pass in the layout the previous frame left them in.
+/// The attachment pool moves newly allocated images to that layout.
+fn initial_layout(attachment: &RenderPassAttachment, present_pass: bool, is_depth: bool) -> vk::ImageLayout {
+    if attachment.preserves_content() { final_layout(attachment, present_pass, is_depth) } else { vk::ImageLayout::UNDEFINED }
+}
+
+fn layout_barrier(image: vk::Image, aspect_mask: vk::ImageAspectFlags, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> vk::ImageMemoryBarrier {
+    let is_attachment_layout = |layout: vk::ImageLayout| layout == vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL || layout == vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL;
+    let (src_access_mask, dst_access_mask) = if is_attachment_layout(new_layout) {
+        (vk::AccessFlags::empty(), vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+    } else {
+        // Visibility for the next passes is given by the semaphores
+        (vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE, vk::AccessFlags::empty())
+    };
+    vk::ImageMemoryBarrier::builder()
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange::builder()
+            .aspect_mask(aspect_mask)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build())
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)
+        .build()
+}
 
 pub struct Framebuffer {
-    vk_framebuffer: vk::Framebuffer,
+    // None with dynamic rendering
+    vk_framebuffer: Option<vk::Framebuffer>,
     command_buffer: CommandBuffer,
     render_finished_semaphore: vk::Semaphore,
     ctx: DeviceCtx,
@@ -487,32 +1089,22 @@ pub struct Framebuffer {
 
 impl Framebuffer {
     pub fn new(render_pass: ResourceHandle<RenderPassInstance>, image_index: u32) -> Self {
-        let mut source_views = vec![];
-        match &render_pass.target {
-            FrameGraphTargetInstance::Swapchain(swapchain) => {
-                source_views.push(swapchain.get_swapchain_images()[image_index as usize])
-            }
-            FrameGraphTargetInstance::Image(images) => {
-                source_views.push(*images[image_index as usize].view().unwrap())
-            }
-            FrameGraphTargetInstance::Internal(attachments) => {
-                for attachment in attachments {
-                    source_views.push(*attachment.images[image_index as usize].view().unwrap())
-                }
-            }
-        }
-
-        let create_info = vk::FramebufferCreateInfo::builder()
-            .render_pass(render_pass.object.render_pass)
-            .attachments(source_views.as_slice())
-            .width(render_pass.current_draw_res.width)
-            .height(render_pass.current_draw_res.height)
-            .layers(1);
-
+        let vk_framebuffer = if render_pass.object.render_pass.is_null() {
+            None
+        } else {
+            let source_views = render_pass.attachment_images(image_index as usize).iter().map(|(_, view)| *view).collect::<Vec<_>>();
+            let create_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(render_pass.object.render_pass)
+                .attachments(source_views.as_slice())
+                .width(render_pass.current_draw_res.width)
+                .height(render_pass.current_draw_res.height)
+                .layers(1);
+            Some(unsafe { render_pass.ctx.device().create_framebuffer(&create_info, None) }.unwrap())
+        };
 
         let semaphore_info = vk::SemaphoreCreateInfo::builder();
         Self {
-            vk_framebuffer: unsafe { render_pass.ctx.device().create_framebuffer(&create_info, None) }.unwrap(),
+            vk_framebuffer,
             command_buffer: CommandBuffer::new(render_pass.ctx.clone(), &QueueFlag::Graphic).unwrap(),
             render_finished_semaphore: unsafe { render_pass.ctx.device().create_semaphore(&semaphore_info, None).unwrap() },
             ctx: render_pass.ctx.clone(),
@@ -522,7 +1114,9 @@ impl Framebuffer {
 
 impl Drop for Framebuffer {
     fn drop(&mut self) {
-        unsafe { self.ctx.device().destroy_framebuffer(self.vk_framebuffer, None) };
+        if let Some(vk_framebuffer) = self.vk_framebuffer {
+            unsafe { self.ctx.device().destroy_framebuffer(vk_framebuffer, None) };
+        }
         unsafe { self.ctx.device().destroy_semaphore(self.render_finished_semaphore, None) };
     }
 }
\ No newline at end of file
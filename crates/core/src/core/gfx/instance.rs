@@ -1,6 +1,7 @@
 use std::any::TypeId;
 use std::collections::HashSet;
 use std::ffi::{c_void};
+use std::path::PathBuf;
 use anyhow::{Error};
 use tracing::{debug, error, trace, warn};
 use vulkanalia::{vk, Entry};
@@ -17,6 +18,9 @@ pub(crate) const VALIDATION_LAYER: vk::ExtensionName = vk::ExtensionName::from_b
 pub struct GfxConfig {
     pub validation_layers: bool,
     pub required_extensions: Vec<vk::ExtensionName>,
+    pub dynamic_rendering: bool,
+    pub bindless: bool,
+    pub pipeline_cache_path: Option<PathBuf>,
 }
 
 pub struct Instance {
@@ -36,10 +40,16 @@ impl Instance {
             let loader = LibloadingLoader::new(LIBRARY)?;
             Entry::new(loader).map_err(|b| anyhow::anyhow!("{}", b))?
         };
-        // Required extensions
-        let mut extensions = vec![
-            vk::KHR_SURFACE_EXTENSION.name.as_ptr(),
-            vk::KHR_WIN32_SURFACE_EXTENSION.name.as_ptr()];
+        // Surface extensions are only needed by windows : headless devices can run on drivers without them
+        let available_extensions = unsafe { entry.enumerate_instance_extension_properties(None)? }
+            .iter()
+            .map(|extension| extension.extension_name)
+            .collect::<HashSet<_>>();
+        let mut extensions = [vk::KHR_SURFACE_EXTENSION.name, vk::KHR_WIN32_SURFACE_EXTENSION.name]
+            .iter()
+            .filter(|extension| available_extensions.contains(*extension))
+            .map(|extension| extension.as_ptr())
+            .collect::<Vec<_>>();
         if config.validation_layers {
             extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name.as_ptr());
         }
@@ -203,15 +213,37 @@ impl Instance {
             return self.device.handle();
         }
         let device = Device::new(self.self_ctx.clone(),
-                                 &ctx.surface(),
+                                 Some(&ctx.surface()),
                                  &GfxConfig {
                                      validation_layers: true,
                                      required_extensions: vec![vk::KHR_SWAPCHAIN_EXTENSION.name],
+                                     dynamic_rendering: self.engine.params().rendering.dynamic_rendering,
+                                     bindless: self.engine.params().rendering.bindless,
+                                     pipeline_cache_path: self.engine.params().rendering.pipeline_cache_path.clone(),
                                  }).unwrap();
         let ctx = device.handle();
         self.device = device;
         ctx
     }
+
+    /// Create the device without any window, for offscreen and compute work
+    pub fn create_headless_device(&mut self) -> Result<DeviceCtx, Error> {
+        if self.device.is_valid() {
+            return Ok(self.device.handle());
+        }
+        let device = Device::new(self.self_ctx.clone(),
+                                 None,
+                                 &GfxConfig {
+                                     validation_layers: true,
+                                     required_extensions: vec![],
+                                     dynamic_rendering: self.engine.params().rendering.dynamic_rendering,
+                                     bindless: self.engine.params().rendering.bindless,
+                                     pipeline_cache_path: None,
+                                 })?;
+        let ctx = device.handle();
+        self.device = device;
+        Ok(ctx)
+    }
 }
 
 impl Drop for Instance {
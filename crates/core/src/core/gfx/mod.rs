@@ -4,6 +4,8 @@ pub mod surface;
 pub mod command_buffer;
 pub mod resources;
 pub mod descriptor_pool;
+pub mod pipeline_cache;
+pub mod bindless;
 pub mod swapchain;
 pub mod frame_graph;
 pub mod physical_device;
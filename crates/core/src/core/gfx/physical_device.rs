@@ -2,13 +2,14 @@ use std::collections::HashSet;
 use anyhow::{anyhow, Error};
 use tracing::{info, warn};
 use vulkanalia::vk;
-use vulkanalia::vk::{InstanceV1_0, KhrSurfaceExtension};
+use vulkanalia::vk::{HasBuilder, InstanceV1_0, InstanceV1_1, KhrSurfaceExtension};
 use crate::core::gfx::instance::{GfxConfig, InstanceCtx};
 use crate::core::gfx::queues::{QueueFlag, Queues};
 use crate::core::gfx::surface::Surface;
 
 pub struct PhysicalDevice {
     physical_device: vk::PhysicalDevice,
+    properties: vk::PhysicalDeviceProperties,
 }
 
 #[derive(Clone, Debug)]
@@ -30,16 +31,21 @@ impl SwapchainSupport {
 }
 
 impl PhysicalDevice {
-    pub fn new(ctx: &InstanceCtx, surface: &Surface, config: &GfxConfig) -> Result<Self, Error> {
+    /// Present and swapchain support are only required when a surface is given.
+    /// Windows require a discrete GPU, headless devices accept any device type but prefer discrete GPUs.
+    pub fn new(ctx: &InstanceCtx, surface: Option<&Surface>, config: &GfxConfig) -> Result<Self, Error> {
+        let mut selected: Option<Self> = None;
         unsafe {
             for physical_device in ctx.ptr().enumerate_physical_devices()? {
                 let properties = ctx.ptr().get_physical_device_properties(physical_device);
                 match Self::check_physical_device(ctx, surface, physical_device, config) {
                     Ok(_) => {
-                        info!("Selected physical device (`{}`).", properties.device_name);
-                        return Ok(Self {
-                            physical_device
-                        });
+                        if selected.as_ref().is_none_or(|selected| device_type_rank(properties.device_type) < device_type_rank(selected.properties.device_type)) {
+                            selected = Some(Self {
+                                physical_device,
+                                properties,
+                            });
+                        }
                     }
                     Err(err) => {
                         warn!("Skipping physical device (`{}`): {}", properties.device_name, err);
@@ -47,16 +53,98 @@ impl PhysicalDevice {
                 }
             }
         }
-        Err(anyhow!("Failed to find suitable physical device."))
+        let selected = selected.ok_or(anyhow!("Failed to find suitable physical device."))?;
+        info!("Selected physical device (`{}`).", selected.properties.device_name);
+        Ok(selected)
     }
 
     pub fn ptr(&self) -> &vk::PhysicalDevice {
         &self.physical_device
     }
 
-    unsafe fn check_physical_device(ctx: &InstanceCtx, surface: &Surface, physical_device: vk::PhysicalDevice, config: &GfxConfig) -> Result<(), Error> {
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    /// Dynamic rendering is core since Vulkan 1.3, and provided by VK_KHR_dynamic_rendering on Vulkan 1.2
+    pub fn supports_dynamic_rendering(&self, ctx: &InstanceCtx) -> bool {
+        if self.properties.api_version >= vk::make_version(1, 3, 0) {
+            let mut features_13 = vk::PhysicalDeviceVulkan13Features::default();
+            {
+                let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut features_13);
+                unsafe { ctx.ptr().get_physical_device_features2(self.physical_device, &mut features) };
+            }
+            features_13.dynamic_rendering == vk::TRUE
+        } else if self.properties.api_version >= vk::make_version(1, 2, 0) && self.supports_extension(ctx, vk::KHR_DYNAMIC_RENDERING_EXTENSION.name) {
+            let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeaturesKHR::default();
+            {
+                let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut dynamic_rendering_features);
+                unsafe { ctx.ptr().get_physical_device_features2(self.physical_device, &mut features) };
+            }
+            dynamic_rendering_features.dynamic_rendering == vk::TRUE
+        } else {
+            false
+        }
+    }
+
+    /// Only queried before Vulkan 1.3, where VK_EXT_extended_dynamic_state became core
+    pub fn supports_extended_dynamic_state(&self, ctx: &InstanceCtx) -> bool {
+        if self.properties.api_version < vk::make_version(1, 1, 0) || !self.supports_extension(ctx, vk::EXT_EXTENDED_DYNAMIC_STATE_EXTENSION.name) {
+            return false;
+        }
+        let mut extended_dynamic_state = vk::PhysicalDeviceExtendedDynamicStateFeaturesEXT::default();
+        {
+            let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut extended_dynamic_state);
+            unsafe { ctx.ptr().get_physical_device_features2(self.physical_device, &mut features) };
+        }
+        extended_dynamic_state.extended_dynamic_state == vk::TRUE
+    }
+
+    /// Descriptor indexing features required by the bindless table
+    pub fn supports_descriptor_indexing(&self, ctx: &InstanceCtx) -> bool {
+        if self.properties.api_version < vk::make_version(1, 2, 0) {
+            return false;
+        }
+        let mut features_12 = vk::PhysicalDeviceVulkan12Features::default();
+        {
+            let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut features_12);
+            unsafe { ctx.ptr().get_physical_device_features2(self.physical_device, &mut features) };
+        }
+        features_12.descriptor_indexing == vk::TRUE
+            && features_12.runtime_descriptor_array == vk::TRUE
+            && features_12.descriptor_binding_partially_bound == vk::TRUE
+            && features_12.descriptor_binding_sampled_image_update_after_bind == vk::TRUE
+            && features_12.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+    }
+
+    /// Limits of update after bind descriptors, core since Vulkan 1.2
+    pub fn descriptor_indexing_properties(&self, ctx: &InstanceCtx) -> vk::PhysicalDeviceDescriptorIndexingProperties {
+        let mut descriptor_indexing = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+        if self.properties.api_version >= vk::make_version(1, 2, 0) {
+            let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut descriptor_indexing);
+            unsafe { ctx.ptr().get_physical_device_properties2(self.physical_device, &mut properties) };
+        }
+        descriptor_indexing
+    }
+
+    pub fn format_properties(&self, ctx: &InstanceCtx, format: vk::Format) -> vk::FormatProperties {
+        unsafe { ctx.ptr().get_physical_device_format_properties(self.physical_device, format) }
+    }
+
+    /// Whether images of this format with optimal tiling support all the given features
+    pub fn supports_format(&self, ctx: &InstanceCtx, format: vk::Format, features: vk::FormatFeatureFlags) -> bool {
+        self.format_properties(ctx, format).optimal_tiling_features.contains(features)
+    }
+
+    pub fn supports_extension(&self, ctx: &InstanceCtx, extension: vk::ExtensionName) -> bool {
+        unsafe { ctx.ptr().enumerate_device_extension_properties(self.physical_device, None) }
+            .map(|extensions| extensions.iter().any(|properties| properties.extension_name == extension))
+            .unwrap_or(false)
+    }
+
+    unsafe fn check_physical_device(ctx: &InstanceCtx, surface: Option<&Surface>, physical_device: vk::PhysicalDevice, config: &GfxConfig) -> Result<(), Error> {
         let properties = ctx.ptr().get_physical_device_properties(physical_device);
-        if properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU {
+        if surface.is_some() && properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU {
             return Err(anyhow!("Only discrete GPUs are supported."));
         }
         let _features = ctx.ptr().get_physical_device_features(physical_device);
@@ -74,7 +162,7 @@ impl PhysicalDevice {
             return Err(anyhow!("There is no available graphic queue on this device"));
         }
 
-        if queues.find_queue(&QueueFlag::Present).is_none() {
+        if surface.is_some() && queues.find_queue(&QueueFlag::Present).is_none() {
             return Err(anyhow!("There is no available present queue on this device"));
         }
 
@@ -82,9 +170,11 @@ impl PhysicalDevice {
             return Err(anyhow!("There is no available compute queue on this device"));
         }
 
-        let swapchain_support = SwapchainSupport::get(ctx.ptr(), surface.ptr(), physical_device)?;
-        if swapchain_support.formats.is_empty() || swapchain_support.present_modes.is_empty() {
-            return Err(anyhow!("Insufficient swapchain support."));
+        if let Some(surface) = surface {
+            let swapchain_support = SwapchainSupport::get(ctx.ptr(), surface.ptr(), physical_device)?;
+            if swapchain_support.formats.is_empty() || swapchain_support.present_modes.is_empty() {
+                return Err(anyhow!("Insufficient swapchain support."));
+            }
         }
 
         if config.required_extensions.iter().all(|e| extensions.contains(e)) {
@@ -93,4 +183,15 @@ impl PhysicalDevice {
             Err(anyhow!("Missing required device extensions."))
         }
     }
-}
\ No newline at end of file
+}
+
+// Lower is preferred
+fn device_type_rank(device_type: vk::PhysicalDeviceType) -> u32 {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => { 0 }
+        vk::PhysicalDeviceType::INTEGRATED_GPU => { 1 }
+        vk::PhysicalDeviceType::VIRTUAL_GPU => { 2 }
+        vk::PhysicalDeviceType::CPU => { 3 }
+        _ => { 4 }
+    }
+}
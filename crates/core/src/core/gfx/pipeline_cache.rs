@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Error};
+use std::fs;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{info, warn};
+use types::profiler::Profiler;
+use vulkanalia::vk;
+use vulkanalia::vk::{DeviceV1_0, HasBuilder};
+
+#[derive(Clone, Debug, Default)]
+pub struct PipelineCacheStats {
+    /// Pipelines found in the cache. Only counted when the device reports creation feedback.
+    pub hits: u64,
+    pub misses: u64,
+    /// Time spent creating pipelines, in nanoseconds
+    pub creation_time: u64,
+    /// Bytes loaded from the cache file on startup
+    pub loaded_size: usize,
+}
+
+impl PipelineCacheStats {
+    /// Publish the statistics as profiler counters
+    pub fn report(&self) {
+        let profiler = Profiler::get();
+        profiler.set_counter("Pipeline cache hits", self.hits);
+        profiler.set_counter("Pipeline cache misses", self.misses);
+        profiler.set_counter("Pipeline creation time (us)", self.creation_time / 1000);
+    }
+}
+
+/// Pipeline cache shared by every pipeline of the device, optionally persisted to a file
+pub struct PipelineCache {
+    cache: vk::PipelineCache,
+    path: Option<PathBuf>,
+    creation_feedback: bool,
+    loaded_size: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    creation_time: AtomicU64,
+}
+
+impl PipelineCache {
+    /// Load the cache file when it was written by the same device and driver, otherwise start from an empty cache
+    pub fn new(device: &vulkanalia::Device, properties: &vk::PhysicalDeviceProperties, path: Option<PathBuf>) -> Result<Self, Error> {
+        let data = match &path {
+            None => { vec![] }
+            Some(path) => {
+                match fs::read(path) {
+                    Ok(data) => {
+                        match validate_header(&data, properties) {
+                            Ok(()) => { data }
+                            Err(err) => {
+                                warn!("Ignoring pipeline cache {} : {}", path.display(), err);
+                                vec![]
+                            }
+                        }
+                    }
+                    Err(_) => { vec![] }
+                }
+            }
+        };
+
+        let cache = match unsafe { device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::builder().initial_data(&data), None) } {
+            Ok(cache) => { cache }
+            // Some drivers still reject data they don't like : start again from scratch
+            Err(_) if !data.is_empty() => {
+                warn!("Pipeline cache data was rejected by the driver");
+                unsafe { device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::builder(), None) }?
+            }
+            Err(err) => { return Err(anyhow!("Failed to create pipeline cache : {}", err)) }
+        };
+        if !data.is_empty() {
+            info!("Loaded pipeline cache ({} bytes)", data.len());
+        }
+
+        Ok(Self {
+            cache,
+            path,
+            // Core since Vulkan 1.3
+            creation_feedback: properties.api_version >= vk::make_version(1, 3, 0),
+            loaded_size: data.len(),
+            hits: Default::default(),
+            misses: Default::default(),
+            creation_time: Default::default(),
+        })
+    }
+
+    pub fn ptr(&self) -> vk::PipelineCache {
+        self.cache
+    }
+
+    /// Whether `vk::PipelineCreationFeedbackCreateInfo` can be chained to pipeline create infos
+    pub fn creation_feedback(&self) -> bool {
+        self.creation_feedback
+    }
+
+    /// Count the result of a pipeline creation
+    pub fn record(&self, feedback: &vk::PipelineCreationFeedback) {
+        if !feedback.flags.contains(vk::PipelineCreationFeedbackFlags::VALID) {
+            return;
+        }
+        if feedback.flags.contains(vk::PipelineCreationFeedbackFlags::APPLICATION_PIPELINE_CACHE_HIT) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        self.creation_time.fetch_add(feedback.duration, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> PipelineCacheStats {
+        PipelineCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            creation_time: self.creation_time.load(Ordering::Relaxed),
+            loaded_size: self.loaded_size,
+        }
+    }
+
+    /// Write the cache content to its file, if any
+    pub fn save(&self, device: &vulkanalia::Device) -> Result<(), Error> {
+        let stats = self.stats();
+        if self.creation_feedback {
+            info!("Pipeline cache : {} hits, {} misses, {} ms spent creating pipelines", stats.hits, stats.misses, stats.creation_time / 1_000_000);
+        }
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = unsafe { device.get_pipeline_cache_data(self.cache) }?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &data).map_err(|err| anyhow!("Failed to write pipeline cache {} : {}", path.display(), err))?;
+        info!("Saved pipeline cache ({} bytes)", data.len());
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, device: &vulkanalia::Device) {
+        unsafe { device.destroy_pipeline_cache(self.cache, None); }
+        self.cache = vk::PipelineCache::default();
+    }
+}
+
+// The data starts with a vk::PipelineCacheHeaderVersionOne, written in little endian
+fn validate_header(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> Result<(), Error> {
+    const HEADER_SIZE: usize = 4 * size_of::<u32>() + vk::UUID_SIZE;
+    if data.len() < HEADER_SIZE {
+        return Err(anyhow!("file is too small"));
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let header_size = read_u32(0) as usize;
+    if header_size < HEADER_SIZE || header_size > data.len() {
+        return Err(anyhow!("invalid header size {}", header_size));
+    }
+    if read_u32(4) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        return Err(anyhow!("unsupported header version {}", read_u32(4)));
+    }
+    if read_u32(8) != properties.vendor_id {
+        return Err(anyhow!("written for vendor {:#x}, expected {:#x}", read_u32(8), properties.vendor_id));
+    }
+    if read_u32(12) != properties.device_id {
+        return Err(anyhow!("written for device {:#x}, expected {:#x}", read_u32(12), properties.device_id));
+    }
+    if data[16..16 + vk::UUID_SIZE] != properties.pipeline_cache_uuid[..] {
+        return Err(anyhow!("written by another driver version"));
+    }
+    Ok(())
+}
@@ -27,7 +27,8 @@ impl Queues {
         &self.preferred
     }
 
-    pub fn search(instance: InstanceCtx, physical_device: &vk::PhysicalDevice, surface: &Surface) -> Self {
+    /// Without a surface, no queue is considered able to present
+    pub fn search(instance: InstanceCtx, physical_device: &vk::PhysicalDevice, surface: Option<&Surface>) -> Self {
         let properties = unsafe {
             instance.ptr().get_physical_device_queue_family_properties(*physical_device)
         };
@@ -37,12 +38,14 @@ impl Queues {
 
         for (index, prop) in properties.iter().enumerate() {
             let mut support_present = false;
-            unsafe {
-                if instance.ptr().get_physical_device_surface_support_khr(
-                    *physical_device,
-                    index as u32,
-                    *surface.ptr()).unwrap() {
-                    support_present = true;
+            if let Some(surface) = surface {
+                unsafe {
+                    if instance.ptr().get_physical_device_surface_support_khr(
+                        *physical_device,
+                        index as u32,
+                        *surface.ptr()).unwrap() {
+                        support_present = true;
+                    }
                 }
             }
             let queue = Arc::new(Queue {
@@ -50,6 +53,7 @@ impl Queues {
                 flags: prop.queue_flags,
                 queue: Mutex::new(Default::default()),
                 support_present,
+                timestamp_valid_bits: prop.timestamp_valid_bits,
             });
             queue_map.insert(index, queue.clone());
             queues.push(queue);
@@ -193,6 +197,7 @@ pub struct Queue {
     flags: vk::QueueFlags,
     queue: Mutex<vk::Queue>,
     support_present: bool,
+    timestamp_valid_bits: u32,
 }
 
 impl Queue {
@@ -204,6 +209,11 @@ impl Queue {
     pub fn flags(&self) -> &vk::QueueFlags {
         &self.flags
     }
+
+    /// Meaningful bits of the timestamps written on this queue, 0 if timestamps are not supported
+    pub fn timestamp_valid_bits(&self) -> u32 {
+        self.timestamp_valid_bits
+    }
     
     pub fn ptr(&self) -> &Mutex<vk::Queue> {
         &self.queue
@@ -1,17 +1,22 @@
-use crate::core::gfx::device::{DeviceCtx};
+use crate::core::gfx::command_buffer::CommandBuffer;
+use crate::core::gfx::device::{DeviceCtx, Fence};
+use crate::core::gfx::queues::QueueFlag;
 use anyhow::{anyhow, Error};
+use bytemuck::Pod;
+use std::cell::RefCell;
+use std::ops::Range;
 use std::ptr::slice_from_raw_parts;
 use vulkanalia::vk;
-use vulkanalia::vk::{HasBuilder};
-use vulkanalia_vma::{Alloc, AllocationCreateFlags};
+use vulkanalia::vk::{DeviceV1_0, HasBuilder};
+use vulkanalia_vma::{Alloc, AllocationCreateFlags, MemoryUsage};
 use types::resource_handle::Resource;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum BufferAccess
 {
-    // Choose best configuration
+    // Choose best configuration : GpuOnly for Immutable and Static buffers, CpuToGpu for Dynamic and Immediate buffers
     Default,
-    // Data will be cached on GPU
+    // Device local memory, written through a staging buffer
     GpuOnly,
     // frequent transfer from CPU to GPU
     CpuToGpu,
@@ -27,26 +32,41 @@ pub enum BufferType
     Immutable,
     // Pretty never updated. Updating data would cause some freezes. Low memory footprint
     Static,
-    // Data is stored internally, then automatically submitted. Can lead to a memory overhead depending on the get size.
+    // Data is stored in a CPU copy, then automatically submitted to each frame's copy. Can lead to a memory overhead depending on the get size.
     Dynamic,
-    // Data need to be submitted every frames
+    // Data need to be submitted every frames, only the current frame's copy is written
     Immediate,
 }
 
 struct BufferResource {
     buffer: vk::Buffer,
     allocation: vulkanalia_vma::Allocation,
+    memory_flags: vk::MemoryPropertyFlags,
+    // Persistent mapping of host visible memory, null otherwise
+    mapped: *mut u8,
     ctx: DeviceCtx,
 }
 
 impl BufferResource {
     pub fn new(buffer: vk::Buffer, allocation: vulkanalia_vma::Allocation, ctx: DeviceCtx) -> Self {
+        let allocation_info = ctx.allocator().get_allocation_info(allocation);
+        let memory_flags = ctx.allocator().get_memory_properties().memory_types[allocation_info.memoryType as usize].property_flags;
         Self {
             buffer,
             allocation,
+            memory_flags,
+            mapped: allocation_info.pMappedData as *mut u8,
             ctx,
         }
     }
+
+    fn is_host_visible(&self) -> bool {
+        self.memory_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+    }
+
+    fn is_coherent(&self) -> bool {
+        self.memory_flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+    }
 }
 
 impl Drop for BufferResource {
@@ -67,6 +87,10 @@ pub struct Buffer {
     elements: usize,
     stride: usize,
     create_infos: BufferCreateInfo,
+    // CPU copy of Dynamic buffers
+    shadow: Vec<u8>,
+    // Range of each frame's copy that differs from the CPU copy
+    outdated: RefCell<Vec<Option<Range<usize>>>>,
     ctx: DeviceCtx,
 }
 
@@ -78,6 +102,8 @@ impl Buffer {
             elements,
             stride,
             create_infos,
+            shadow: vec![],
+            outdated: RefCell::new(vec![]),
             ctx,
         };
         buffer.create()?;
@@ -90,6 +116,7 @@ impl Buffer {
         Ok(buffer)
     }
 
+    /// The content of Dynamic buffers is preserved, other buffers should be written again.
     pub fn resize(&mut self, new_element_count: usize) -> Result<(), Error> {
         if let BufferType::Immutable = self.create_infos.buffer_type {
             return Err(anyhow!("Cannot resize an immutable buffer"));
@@ -101,52 +128,210 @@ impl Buffer {
         self.create()
     }
 
+    /// Host visible memory is written directly, device local memory is written through a staging buffer and a transfer
     pub fn set_data(&mut self, start_offset: usize, data: &BufferMemory) -> Result<(), Error> {
-        if start_offset + data.get_size() > self.size() {
-            return Err(anyhow!("buffer is to small : size={}, expected={}", self.size(), start_offset + data.get_size()));
+        self.write_bytes(start_offset, &data.as_slice()[..data.get_size()])
+    }
+
+    /// Write a slice of T at the given offset in bytes
+    pub fn write_slice<T: Copy>(&mut self, start_offset: usize, data: &[T]) -> Result<(), Error> {
+        let bytes = unsafe { slice_from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)).as_ref().unwrap() };
+        self.write_bytes(start_offset, bytes)
+    }
+
+    /// Write the element at the given index, using the stride of the buffer
+    pub fn write_at<T: Copy>(&mut self, index: usize, value: &T) -> Result<(), Error> {
+        if size_of::<T>() > self.stride {
+            return Err(anyhow!("Element is larger than the buffer stride : size={}, stride={}", size_of::<T>(), self.stride));
         }
-        unsafe {
-            let resource = match self.create_infos.buffer_type {
-                BufferType::Immutable | BufferType::Static => {
-                    self.resource[0].allocation
-                }
-                BufferType::Immediate | BufferType::Dynamic => {
-                    let frame = self.ctx.instance().engine().current_frame();
-                    self.resource[frame].allocation
+        self.write_slice(index * self.stride, std::slice::from_ref(value))
+    }
+
+    /// Dynamic buffers update their CPU copy and the current frame's copy, the other frames are updated when they are used.
+    /// Other buffer types only update the current frame's copy.
+    fn write_bytes(&mut self, start_offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        if start_offset + bytes.len() > self.size() {
+            return Err(anyhow!("buffer is to small : size={}, expected={}", self.size(), start_offset + bytes.len()));
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        // Bring the current frame up to date before writing over it
+        let resource = self.current_resource()?;
+        self.write_resource(resource, start_offset, bytes)?;
+        if let BufferType::Dynamic = self.create_infos.buffer_type {
+            self.shadow[start_offset..start_offset + bytes.len()].copy_from_slice(bytes);
+            let frame = self.ctx.instance().engine().current_frame();
+            for (copy, outdated) in self.outdated.get_mut().iter_mut().enumerate() {
+                if copy != frame {
+                    *outdated = Some(match outdated.take() {
+                        None => { start_offset..start_offset + bytes.len() }
+                        Some(range) => { range.start.min(start_offset)..range.end.max(start_offset + bytes.len()) }
+                    });
                 }
-            };
+            }
+        }
+        Ok(())
+    }
 
-            let mapped_memory = self.ctx.allocator().map_memory(resource)?;
-            data.get_ptr(0).copy_to(mapped_memory.add(start_offset), data.get_size());
-            self.ctx.allocator().unmap_memory(resource);
+    fn write_resource(&self, resource: &BufferResource, start_offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        if resource.mapped.is_null() {
+            return self.upload_staged(resource.buffer, start_offset, bytes);
+        }
+        unsafe { bytes.as_ptr().copy_to_nonoverlapping(resource.mapped.add(start_offset), bytes.len()); }
+        if !resource.is_coherent() {
+            unsafe { self.ctx.allocator().flush_allocation(resource.allocation, start_offset as vk::DeviceSize, bytes.len() as vk::DeviceSize)?; }
         }
         Ok(())
     }
-    pub fn size(&self) -> usize {
-        self.elements * self.stride
+
+    /// Read the content of a host visible buffer (GpuToCpu or CpuToGpu). The GPU should be done writing it.
+    pub fn read_mapped(&self, start_offset: usize, size: usize) -> Result<Vec<u8>, Error> {
+        if start_offset + size > self.size() {
+            return Err(anyhow!("Out of bounds read : size={}, expected={}", self.size(), start_offset + size));
+        }
+        let resource = self.current_resource()?;
+        if resource.mapped.is_null() {
+            return Err(anyhow!("Buffer memory is not host visible"));
+        }
+        if !resource.is_coherent() {
+            // Make GPU writes visible to the host
+            unsafe { self.ctx.allocator().invalidate_allocation(resource.allocation, start_offset as vk::DeviceSize, size as vk::DeviceSize)?; }
+        }
+        let mut data = vec![0u8; size];
+        unsafe { resource.mapped.add(start_offset).copy_to_nonoverlapping(data.as_mut_ptr(), size); }
+        Ok(data)
     }
-    pub fn elements(&self) -> usize {
-        self.elements
+
+    /// Copy a range of the buffer back to the CPU, waiting for the work previously submitted on the graphic queue.
+    /// Device local buffers are copied to a GpuToCpu staging buffer, host visible buffers are read in place.
+    pub fn read_async(&self, start_offset: usize, size: usize) -> Result<ReadbackHandle<'_>, Error> {
+        if start_offset + size > self.size() {
+            return Err(anyhow!("Out of bounds read : size={}, expected={}", self.size(), start_offset + size));
+        }
+        let resource = self.current_resource()?;
+        let staging_buffer = if resource.is_host_visible() {
+            None
+        } else {
+            Some(Buffer::new(self.ctx.clone(), 1, size, BufferCreateInfo { usage: vk::BufferUsageFlags::TRANSFER_DST, access: BufferAccess::GpuToCpu, buffer_type: Default::default() })?)
+        };
+
+        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Graphic)?;
+        command_buffer.begin_one_time()?;
+        readback_barrier(&self.ctx, *command_buffer.ptr()?, vk::PipelineStageFlags::ALL_COMMANDS, vk::AccessFlags::MEMORY_WRITE);
+        if let Some(staging_buffer) = &staging_buffer {
+            unsafe {
+                self.ctx.device().cmd_copy_buffer(
+                    *command_buffer.ptr()?,
+                    resource.buffer,
+                    *staging_buffer.ptr()?,
+                    &[vk::BufferCopy::builder()
+                        .src_offset(start_offset as vk::DeviceSize)
+                        .dst_offset(0)
+                        .size(size as vk::DeviceSize)
+                        .build()]);
+            }
+            readback_barrier(&self.ctx, *command_buffer.ptr()?, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+        }
+        command_buffer.end()?;
+
+        let (source, start_offset) = match staging_buffer {
+            None => { (ReadbackSource::Mapped(self), start_offset) }
+            Some(staging_buffer) => { (ReadbackSource::Staging(staging_buffer), 0) }
+        };
+        ReadbackHandle::submit(self.ctx.clone(), command_buffer, source, start_offset, size, self.stride)
     }
-    pub fn stride(&self) -> usize {
-        self.stride
+
+    /// Blocking version of [`Buffer::read_async`]
+    pub fn read(&self, start_offset: usize, size: usize) -> Result<BufferMemory<'static>, Error> {
+        self.read_async(start_offset, size)?.wait()
     }
-    pub fn ptr(&self) -> Result<&vk::Buffer, Error> {
+
+    fn upload_staged(&self, buffer: vk::Buffer, start_offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        let mut staging_buffer = Buffer::new(self.ctx.clone(), 1, bytes.len(), BufferCreateInfo { usage: vk::BufferUsageFlags::TRANSFER_SRC, access: BufferAccess::CpuToGpu, buffer_type: Default::default() })?;
+        staging_buffer.write_bytes(0, bytes)?;
+
+        let queue = upload_queue(&self.ctx);
+        let command_buffer = CommandBuffer::new(self.ctx.clone(), &queue)?;
+        command_buffer.begin_one_time()?;
+        unsafe {
+            self.ctx.device().cmd_copy_buffer(
+                *command_buffer.ptr()?,
+                *staging_buffer.ptr()?,
+                buffer,
+                &[vk::BufferCopy::builder()
+                    .src_offset(0)
+                    .dst_offset(start_offset as vk::DeviceSize)
+                    .size(bytes.len() as vk::DeviceSize)
+                    .build()]);
+        }
+        command_buffer.end()?;
+
+        let command_buffers = vec![*command_buffer.ptr()?];
+        let submit_info = vk::SubmitInfo::builder()
+            .command_buffers(command_buffers.as_slice())
+            .build();
+        let fence = Fence::new(self.ctx.clone());
+        let submit_infos = vec![submit_info];
+        self.ctx.queues().submit(&queue, submit_infos.as_slice(), Some(fence.handle()));
+        fence.wait();
+        Ok(())
+    }
+
+    fn current_resource(&self) -> Result<&BufferResource, Error> {
         if !self.resource.is_valid() {
             return Err(anyhow!("Buffer is null"));
         }
-
         match self.create_infos.buffer_type {
             BufferType::Immutable | BufferType::Static => {
-                Ok(&self.resource[0].buffer)
+                Ok(&self.resource[0])
             }
-            BufferType::Immediate | BufferType::Dynamic => {
+            BufferType::Immediate => {
+                Ok(&self.resource[self.ctx.instance().engine().current_frame()])
+            }
+            BufferType::Dynamic => {
                 let frame = self.ctx.instance().engine().current_frame();
-                Ok(&self.resource[frame].buffer)
+                let resource = &self.resource[frame];
+                let outdated = self.outdated.borrow_mut()[frame].take();
+                if let Some(range) = outdated {
+                    self.write_resource(resource, range.start, &self.shadow[range])?;
+                }
+                Ok(resource)
             }
         }
     }
 
+    /// Default access resolved from the buffer type
+    pub fn access(&self) -> BufferAccess {
+        match (self.create_infos.access, self.create_infos.buffer_type) {
+            (BufferAccess::Default, BufferType::Immutable | BufferType::Static) => { BufferAccess::GpuOnly }
+            (BufferAccess::Default, BufferType::Dynamic | BufferType::Immediate) => { BufferAccess::CpuToGpu }
+            (access, _) => { access }
+        }
+    }
+    pub fn size(&self) -> usize {
+        self.elements * self.stride
+    }
+    pub fn usage(&self) -> vk::BufferUsageFlags {
+        self.create_infos.usage
+    }
+    /// Content of Dynamic buffers as last written by the CPU
+    pub fn cpu_copy(&self) -> Option<&[u8]> {
+        match self.create_infos.buffer_type {
+            BufferType::Dynamic => { Some(self.shadow.as_slice()) }
+            _ => { None }
+        }
+    }
+    pub fn elements(&self) -> usize {
+        self.elements
+    }
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+    pub fn ptr(&self) -> Result<&vk::Buffer, Error> {
+        Ok(&self.current_resource()?.buffer)
+    }
+
     pub fn queue_resource_for_destruction(&mut self) {
         if self.resource.is_valid() {
             self.ctx.queue_resource_cleanup(self.resource.take());
@@ -159,27 +344,48 @@ impl Buffer {
             return Ok(());
         }
 
-        let buffer_info = vk::BufferCreateInfo::builder()
-            .size(self.size() as u64)
-            .usage(self.create_infos.usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let mut usage = self.create_infos.usage;
         let mut options = vulkanalia_vma::AllocationOptions::default();
 
-        match self.create_infos.access {
-            BufferAccess::Default => {}
+        match self.access() {
+            BufferAccess::Default => { unreachable!() }
             BufferAccess::GpuOnly => {
-                options.flags = AllocationCreateFlags::HOST_ACCESS_RANDOM;
-                options.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+                // Written by staging transfers, and read back the same way
+                usage |= vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::TRANSFER_SRC;
+                options.usage = MemoryUsage::AutoPreferDevice;
             }
             BufferAccess::CpuToGpu => {
-                options.flags = AllocationCreateFlags::HOST_ACCESS_RANDOM;
-                options.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+                options.usage = MemoryUsage::Auto;
+                options.flags = AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE | AllocationCreateFlags::MAPPED;
+                options.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE;
             }
             BufferAccess::GpuToCpu => {
-                options.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+                // Destination of readback copies
+                usage |= vk::BufferUsageFlags::TRANSFER_DST;
+                options.usage = MemoryUsage::AutoPreferHost;
+                options.flags = AllocationCreateFlags::HOST_ACCESS_RANDOM | AllocationCreateFlags::MAPPED;
+                options.required_flags = vk::MemoryPropertyFlags::HOST_VISIBLE;
+                options.preferred_flags = vk::MemoryPropertyFlags::HOST_CACHED;
             }
         }
 
+        // GPU only buffers are written from the transfer queue and used from the graphic queue : share them when they are different families
+        let mut queue_family_indices = vec![];
+        if self.access() == BufferAccess::GpuOnly {
+            let graphic_family = self.ctx.queues().find_queue(&QueueFlag::Graphic).map(|queue| queue.index() as u32);
+            let transfer_family = self.ctx.queues().find_queue(&upload_queue(&self.ctx)).map(|queue| queue.index() as u32);
+            if let (Some(graphic_family), Some(transfer_family)) = (graphic_family, transfer_family) {
+                if graphic_family != transfer_family {
+                    queue_family_indices = vec![graphic_family, transfer_family];
+                }
+            }
+        }
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(self.size() as u64)
+            .usage(usage)
+            .sharing_mode(if queue_family_indices.is_empty() { vk::SharingMode::EXCLUSIVE } else { vk::SharingMode::CONCURRENT })
+            .queue_family_indices(queue_family_indices.as_slice());
+
         match self.create_infos.buffer_type {
             BufferType::Immutable | BufferType::Static => {
                 let (buffer, buffer_memory) = unsafe { self.ctx.allocator().create_buffer(buffer_info, &options) }?;
@@ -193,16 +399,127 @@ impl Buffer {
                 }
             }
         }
+        if let BufferType::Dynamic = self.create_infos.buffer_type {
+            // Every new copy receives the preserved content of the CPU copy on first use
+            let preserved = self.shadow.len().min(self.size());
+            self.shadow.resize(self.size(), 0);
+            *self.outdated.get_mut() = vec![if preserved > 0 { Some(0..preserved) } else { None }; self.resource.len()];
+        }
         Ok(())
     }
 }
 
+/// Staged uploads use the dedicated transfer queue when the device has one
+fn upload_queue(ctx: &DeviceCtx) -> QueueFlag {
+    if ctx.queues().find_queue(&QueueFlag::Transfer).is_some() { QueueFlag::Transfer } else { QueueFlag::Graphic }
+}
+
 impl Drop for Buffer {
     fn drop(&mut self) {
         self.queue_resource_for_destruction();
     }
 }
 
+/// Make the writes of the given stage visible to transfers and to the host
+pub(crate) fn readback_barrier(ctx: &DeviceCtx, command_buffer: vk::CommandBuffer, src_stage: vk::PipelineStageFlags, src_access: vk::AccessFlags) {
+    let memory_barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(src_access)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::HOST_READ)
+        .build();
+    unsafe {
+        let buffer_memory_barriers: [vk::BufferMemoryBarrier; 0] = [];
+        let image_memory_barriers: [vk::ImageMemoryBarrier; 0] = [];
+        ctx.device().cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[memory_barrier],
+            &buffer_memory_barriers,
+            &image_memory_barriers);
+    }
+}
+
+enum ReadbackSource<'a> {
+    Mapped(&'a Buffer),
+    Staging(Buffer),
+}
+
+/// Pending copy from the GPU. Dropping the handle waits for the copy to complete.
+pub struct ReadbackHandle<'a> {
+    source: ReadbackSource<'a>,
+    start_offset: usize,
+    size: usize,
+    stride: usize,
+    fence: Resource<Fence>,
+    _command_buffer: CommandBuffer,
+    ctx: DeviceCtx,
+}
+
+impl<'a> ReadbackHandle<'a> {
+    fn submit(ctx: DeviceCtx, command_buffer: CommandBuffer, source: ReadbackSource<'a>, start_offset: usize, size: usize, stride: usize) -> Result<Self, Error> {
+        let command_buffers = vec![*command_buffer.ptr()?];
+        let submit_info = vk::SubmitInfo::builder()
+            .command_buffers(command_buffers.as_slice())
+            .build();
+        let fence = Fence::new(ctx.clone());
+        let submit_infos = vec![submit_info];
+        ctx.queues().submit(&QueueFlag::Graphic, submit_infos.as_slice(), Some(fence.handle()));
+        Ok(Self {
+            source,
+            start_offset,
+            size,
+            stride,
+            fence,
+            _command_buffer: command_buffer,
+            ctx,
+        })
+    }
+
+    /// Staging buffer receiving a copy, for readbacks recorded outside of this module
+    pub(crate) fn submit_staged(ctx: DeviceCtx, command_buffer: CommandBuffer, staging_buffer: Buffer, size: usize, stride: usize) -> Result<ReadbackHandle<'static>, Error> {
+        ReadbackHandle::submit(ctx, command_buffer, ReadbackSource::Staging(staging_buffer), 0, size, stride)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(unsafe { self.ctx.device().get_fence_status(*self.fence.ptr()) }, Ok(vk::SuccessCode::SUCCESS))
+    }
+
+    pub fn wait(self) -> Result<BufferMemory<'static>, Error> {
+        let data = self.wait_bytes()?;
+        let stride = if data.len() % self.stride == 0 { self.stride } else { 1 };
+        Ok(BufferMemory {
+            elements: data.len() / stride,
+            data: BufferDataType::Raw(Box::new(BufferData { object: data })),
+            stride,
+        })
+    }
+
+    /// Read the data as an array of T. The size of the read range should be a multiple of the size of T.
+    pub fn wait_as<T: Pod>(self) -> Result<Vec<T>, Error> {
+        let data = self.wait_bytes()?;
+        if size_of::<T>() == 0 || !data.len().is_multiple_of(size_of::<T>()) {
+            return Err(anyhow!("Read {} bytes, which is not a multiple of {}", data.len(), size_of::<T>()));
+        }
+        Ok(data.chunks_exact(size_of::<T>()).map(bytemuck::pod_read_unaligned).collect())
+    }
+
+    fn wait_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.fence.wait();
+        match &self.source {
+            ReadbackSource::Mapped(buffer) => { buffer.read_mapped(self.start_offset, self.size) }
+            ReadbackSource::Staging(buffer) => { buffer.read_mapped(self.start_offset, self.size) }
+        }
+    }
+}
+
+impl Drop for ReadbackHandle<'_> {
+    fn drop(&mut self) {
+        // The command buffer can't be freed while pending
+        self.fence.wait();
+    }
+}
+
 pub struct BufferMemory<'a> {
     data: BufferDataType<'a>,
     stride: usize,
@@ -0,0 +1,217 @@
+use crate::core::gfx::command_buffer::CommandBuffer;
+use crate::core::gfx::device::{DeviceCtx, Fence};
+use crate::core::gfx::queues::QueueFlag;
+use crate::core::gfx::resources::buffer::{readback_barrier, Buffer, BufferMemory};
+use crate::core::gfx::resources::descriptor_sets::{DescriptorSets, ShaderInstanceBinding};
+use crate::core::gfx::resources::pipeline::{PipelineLayout, PipelineObject};
+use crate::core::gfx::resources::shader_module::ShaderStage;
+use anyhow::{anyhow, Error};
+use vulkanalia::vk;
+use vulkanalia::vk::{DeviceV1_0, HasBuilder, ShaderStageFlags};
+
+pub struct ComputePipeline {
+    layout: PipelineLayout,
+    pipeline: vk::Pipeline,
+    workgroup_size: [u32; 3],
+    ctx: DeviceCtx,
+}
+
+impl ComputePipeline {
+    pub fn new(ctx: DeviceCtx, stage: ShaderStage, bindless: bool) -> Result<Self, Error> {
+        if stage.infos().stage != ShaderStageFlags::COMPUTE {
+            return Err(anyhow!("Compute pipelines require a compute stage, got {:?}", stage.infos().stage));
+        }
+        let layout = PipelineLayout::new(ctx.clone(), std::slice::from_ref(&stage), bindless)?;
+
+        let entry_point_name = format!("{}\0", stage.infos().entry_point);
+        let stage_info = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(ShaderStageFlags::COMPUTE)
+            .module(*stage.shader_module())
+            .name(entry_point_name.as_bytes())
+            .build();
+        let mut feedback = vk::PipelineCreationFeedback::default();
+        let mut stage_feedbacks = [vk::PipelineCreationFeedback::default()];
+        let mut feedback_info = vk::PipelineCreationFeedbackCreateInfo::builder()
+            .pipeline_creation_feedback(&mut feedback)
+            .pipeline_stage_creation_feedbacks(&mut stage_feedbacks);
+        let mut info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage_info)
+            .layout(*layout.ptr());
+        if ctx.pipeline_cache().creation_feedback() {
+            info = info.push_next(&mut feedback_info);
+        }
+        let info = info.build();
+        let pipeline = unsafe { ctx.device().create_compute_pipelines(ctx.pipeline_cache().ptr(), &[info], None) }?.0;
+        ctx.pipeline_cache().record(&feedback);
+
+        Ok(Self {
+            layout,
+            pipeline: pipeline[0],
+            workgroup_size: stage.infos().workgroup_size.unwrap_or([1, 1, 1]),
+            ctx,
+        })
+    }
+
+    /// Local size declared by the shader
+    pub fn workgroup_size(&self) -> [u32; 3] {
+        self.workgroup_size
+    }
+
+    /// Number of workgroups needed to cover `invocations` threads on each axis
+    pub fn group_count(&self, invocations: [u32; 3]) -> [u32; 3] {
+        [0, 1, 2].map(|axis| invocations[axis].div_ceil(self.workgroup_size[axis]))
+    }
+
+    /// Bind the resources to set 0, dispatch the groups and wait for the GPU to complete.
+    /// Writes of the shader are visible to transfers and to the host once this returns.
+    pub fn run(&self, bindings: Vec<(ShaderInstanceBinding, u32)>, push_constants: Option<&BufferMemory>, group_count: [u32; 3]) -> Result<(), Error> {
+        let descriptors = if self.layout.descriptor_set_count() > 0 {
+            let mut descriptors = DescriptorSets::new(self.ctx.clone(), self, 0)?;
+            descriptors.update(bindings)?;
+            Some(descriptors)
+        } else if !bindings.is_empty() {
+            return Err(anyhow!("Compute shader doesn't declare any binding"));
+        } else {
+            None
+        };
+
+        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Graphic)?;
+        command_buffer.begin_one_time()?;
+        command_buffer.bind_pipeline(self);
+        if let Some(descriptors) = &descriptors {
+            command_buffer.bind_descriptors(self, 0, descriptors, &[]);
+        }
+        if let Some(push_constants) = push_constants {
+            command_buffer.push_constant(self, push_constants, ShaderStageFlags::COMPUTE);
+        }
+        command_buffer.dispatch(group_count[0], group_count[1], group_count[2]);
+        readback_barrier(&self.ctx, *command_buffer.ptr()?, vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE);
+        command_buffer.end()?;
+
+        let command_buffers = vec![*command_buffer.ptr()?];
+        let submit_infos = vec![vk::SubmitInfo::builder()
+            .command_buffers(command_buffers.as_slice())
+            .build()];
+        let fence = Fence::new(self.ctx.clone());
+        self.ctx.queues().submit(&QueueFlag::Graphic, submit_infos.as_slice(), Some(fence.handle()));
+        fence.wait();
+        Ok(())
+    }
+
+    /// Same as `run`, then copy the whole `output` buffer back to the CPU
+    pub fn run_and_read(&self, bindings: Vec<(ShaderInstanceBinding, u32)>, push_constants: Option<&BufferMemory>, group_count: [u32; 3], output: &Buffer) -> Result<BufferMemory<'static>, Error> {
+        self.run(bindings, push_constants, group_count)?;
+        output.read(0, output.size())
+    }
+}
+
+impl PipelineObject for ComputePipeline {
+    fn ptr_pipeline(&self) -> &vk::Pipeline {
+        &self.pipeline
+    }
+
+    fn layout(&self) -> &PipelineLayout {
+        &self.layout
+    }
+
+    fn bind_point(&self) -> vk::PipelineBindPoint {
+        vk::PipelineBindPoint::COMPUTE
+    }
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        unsafe { self.ctx.device().destroy_pipeline(self.pipeline, None); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::gfx::resources::buffer::{Buffer, BufferAccess, BufferCreateInfo};
+    use crate::core::gfx::resources::compute_pipeline::ComputePipeline;
+    use crate::core::gfx::resources::descriptor_sets::ShaderInstanceBinding;
+    use crate::core::gfx::resources::shader_module::{ShaderStage, ShaderStageBindings, ShaderStageInfos};
+    use crate::engine::tests::test_device;
+    use vulkanalia::vk;
+
+    // RWStructuredBuffer<uint> output : register(u0); [numthreads(64, 1, 1)] main(uint3 id : SV_DispatchThreadID) { output[id.x] = id.x * 2 + 1; }
+    const SHADER: [u32; 138] = [
+        0x07230203, 0x00010000, 0, 23, 0,
+        2 << 16 | 17, 1,                                // OpCapability Shader
+        3 << 16 | 14, 0, 1,                             // OpMemoryModel Logical GLSL450
+        6 << 16 | 15, 5, 1, 0x6e69616d, 0, 2,           // OpEntryPoint GLCompute %1 "main" %2
+        6 << 16 | 16, 1, 17, 64, 1, 1,                  // OpExecutionMode %1 LocalSize 64 1 1
+        4 << 16 | 71, 2, 11, 28,                        // OpDecorate %2 BuiltIn GlobalInvocationId
+        4 << 16 | 71, 8, 6, 4,                          // OpDecorate %8 ArrayStride 4
+        5 << 16 | 72, 9, 0, 35, 0,                      // OpMemberDecorate %9 0 Offset 0
+        3 << 16 | 71, 9, 3,                             // OpDecorate %9 BufferBlock
+        4 << 16 | 71, 11, 34, 0,                        // OpDecorate %11 DescriptorSet 0
+        4 << 16 | 71, 11, 33, 0,                        // OpDecorate %11 Binding 0
+        2 << 16 | 19, 3,                                // %3 = OpTypeVoid
+        3 << 16 | 33, 4, 3,                             // %4 = OpTypeFunction %3
+        4 << 16 | 21, 5, 32, 0,                         // %5 = OpTypeInt 32 0
+        4 << 16 | 23, 6, 5, 3,                          // %6 = OpTypeVector %5 3
+        4 << 16 | 32, 7, 1, 6,                          // %7 = OpTypePointer Input %6
+        4 << 16 | 59, 7, 2, 1,                          // %2 = OpVariable %7 Input
+        3 << 16 | 29, 8, 5,                             // %8 = OpTypeRuntimeArray %5
+        3 << 16 | 30, 9, 8,                             // %9 = OpTypeStruct %8
+        4 << 16 | 32, 10, 2, 9,                         // %10 = OpTypePointer Uniform %9
+        4 << 16 | 59, 10, 11, 2,                        // %11 = OpVariable %10 Uniform
+        4 << 16 | 32, 12, 2, 5,                         // %12 = OpTypePointer Uniform %5
+        4 << 16 | 21, 13, 32, 1,                        // %13 = OpTypeInt 32 1
+        4 << 16 | 43, 13, 14, 0,                        // %14 = OpConstant %13 0
+        4 << 16 | 43, 5, 15, 2,                         // %15 = OpConstant %5 2
+        4 << 16 | 43, 5, 16, 1,                         // %16 = OpConstant %5 1
+        5 << 16 | 54, 3, 1, 0, 4,                       // %1 = OpFunction %3 None %4
+        2 << 16 | 248, 17,                              // %17 = OpLabel
+        4 << 16 | 61, 6, 18, 2,                         // %18 = OpLoad %6 %2
+        5 << 16 | 81, 5, 19, 18, 0,                     // %19 = OpCompositeExtract %5 %18 0
+        5 << 16 | 132, 5, 20, 19, 15,                   // %20 = OpIMul %5 %19 %15
+        5 << 16 | 128, 5, 21, 20, 16,                   // %21 = OpIAdd %5 %20 %16
+        6 << 16 | 65, 12, 22, 11, 14, 19,               // %22 = OpAccessChain %12 %11 %14 %19
+        3 << 16 | 62, 22, 21,                           // OpStore %22 %21
+        1 << 16 | 253,                                  // OpReturn
+        1 << 16 | 56,                                   // OpFunctionEnd
+    ];
+
+    #[test]
+    fn shader_declares_its_workgroup_size() {
+        let bytecode = SHADER.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+        let reflection = shaders::reflection::reflect(&bytecode).unwrap();
+        assert_eq!(reflection.entry_point("main").unwrap().workgroup_size, Some([64, 1, 1]));
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn run_and_read_returns_shader_output() {
+        let bytecode = SHADER.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+        let device = test_device();
+        let stage = ShaderStage::new(device.ctx.clone(), &bytecode, ShaderStageInfos {
+            descriptor_bindings: vec![ShaderStageBindings { set: 0, binding: 0, descriptor_type: vk::DescriptorType::STORAGE_BUFFER, count: 1 }],
+            push_constant_size: None,
+            stage_input: vec![],
+            stage: vk::ShaderStageFlags::COMPUTE,
+            entry_point: "main".to_string(),
+            workgroup_size: Some([64, 1, 1]),
+        }).unwrap();
+        let pipeline = ComputePipeline::new(device.ctx.clone(), stage, false).unwrap();
+
+        // 200 invocations round up to 4 groups of 64, which cover the whole buffer
+        let elements = 200;
+        let output = Buffer::new(device.ctx.clone(), size_of::<u32>(), 256, BufferCreateInfo {
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            access: BufferAccess::GpuOnly,
+            buffer_type: Default::default(),
+        }).unwrap();
+        let group_count = pipeline.group_count([elements, 1, 1]);
+        assert_eq!(group_count, [4, 1, 1]);
+
+        let binding = ShaderInstanceBinding::StorageBuffer(*output.ptr().unwrap(), 0, output.size() as vk::DeviceSize);
+        let memory = pipeline.run_and_read(vec![(binding, 0)], None, group_count, &output).unwrap();
+        let values = bytemuck::pod_collect_to_vec::<u8, u32>(memory.as_slice());
+        assert_eq!(values.len(), 256);
+        for (index, value) in values.iter().enumerate() {
+            assert_eq!(*value, index as u32 * 2 + 1, "Invalid value at index {index}");
+        }
+    }
+}
@@ -1,32 +1,64 @@
+use crate::core::gfx::descriptor_pool::DescriptorAllocation;
 use crate::core::gfx::device::DeviceCtx;
+use crate::core::gfx::resources::pipeline::PipelineObject;
 use anyhow::{anyhow, Error};
 use std::slice;
 use vulkanalia::vk;
-use vulkanalia::vk::{CopyDescriptorSet, DescriptorImageInfo, DescriptorSetLayout, DeviceV1_0, HasBuilder};
+use vulkanalia::vk::{CopyDescriptorSet, DescriptorBufferInfo, DescriptorImageInfo, DeviceV1_0, HasBuilder};
 
 pub struct DescriptorSets {
     desc_set: Option<vk::DescriptorSet>,
+    // None for transient sets, which are released with their frame
+    allocation: Option<DescriptorAllocation>,
     ctx: DeviceCtx
 }
 pub enum ShaderInstanceBinding {
     Sampler(vk::Sampler),
     SampledImage(vk::ImageView, vk::ImageLayout),
+    InputAttachment(vk::ImageView, vk::ImageLayout),
+    // Buffer, offset and range in bytes
+    UniformBuffer(vk::Buffer, vk::DeviceSize, vk::DeviceSize),
+    StorageBuffer(vk::Buffer, vk::DeviceSize, vk::DeviceSize),
+    // Image in GENERAL layout, read and written by compute shaders
+    StorageImage(vk::ImageView, vk::ImageLayout),
+    // The dynamic offset given when binding the descriptors is added to the offset
+    UniformBufferDynamic(vk::Buffer, vk::DeviceSize, vk::DeviceSize),
+    StorageBufferDynamic(vk::Buffer, vk::DeviceSize, vk::DeviceSize),
 }
 
 impl DescriptorSets {
-    pub fn new(ctx: DeviceCtx, layout: &DescriptorSetLayout) -> Result<Self, Error> {
-        let desc_set = ctx.descriptor_pool().allocate(*layout)?;
+    /// Descriptor set matching the pipeline's layout at index `set`
+    pub fn new(ctx: DeviceCtx, pipeline: &impl PipelineObject, set: u32) -> Result<Self, Error> {
+        let allocation = ctx.descriptor_pool().allocate(*pipeline.layout().descriptor_set_layout(set), pipeline.layout().descriptor_counts(set))?;
+        Ok(Self {
+            desc_set: Some(allocation.set),
+            allocation: Some(allocation),
+            ctx,
+        })
+    }
+
+    /// Descriptor set only valid for the frame being recorded
+    pub fn transient(ctx: DeviceCtx, pipeline: &impl PipelineObject, set: u32) -> Result<Self, Error> {
+        let desc_set = ctx.descriptor_pool().allocate_transient(*pipeline.layout().descriptor_set_layout(set), pipeline.layout().descriptor_counts(set))?;
         Ok(Self {
             desc_set: Some(desc_set),
+            allocation: None,
             ctx,
         })
     }
 
     pub fn update(&mut self, bindings: Vec<(ShaderInstanceBinding, u32)>) -> Result<(), Error> {
-        let mut desc_images = Vec::new();
+        self.update_array(bindings.into_iter().map(|(desc_set, binding)| (desc_set, binding, 0)).collect())
+    }
+
+    /// Same as `update`, with the array element written for each binding
+    pub fn update_array(&mut self, bindings: Vec<(ShaderInstanceBinding, u32, u32)>) -> Result<(), Error> {
+        // Reserved up front : the write infos point into these vectors
+        let mut desc_images = Vec::with_capacity(bindings.len());
+        let mut desc_buffers = Vec::with_capacity(bindings.len());
 
         let mut write_desc_set = Vec::new();
-        for (desc_set, binding) in &bindings {
+        for (desc_set, binding, element) in &bindings {
             let write_set = match &desc_set {
                 ShaderInstanceBinding::Sampler(sampler) => {
                     desc_images.push(
@@ -45,9 +77,68 @@ impl DescriptorSets {
                         .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
                         .image_info(slice::from_ref(&desc_images[desc_images.len() - 1]))
                 }
+                ShaderInstanceBinding::InputAttachment(input_attachment, layout) => {
+                    desc_images.push(
+                        DescriptorImageInfo::builder()
+                            .image_view(*input_attachment)
+                            .image_layout(*layout).build());
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                        .image_info(slice::from_ref(&desc_images[desc_images.len() - 1]))
+                }
+                ShaderInstanceBinding::StorageImage(storage_image, layout) => {
+                    desc_images.push(
+                        DescriptorImageInfo::builder()
+                            .image_view(*storage_image)
+                            .image_layout(*layout).build());
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(slice::from_ref(&desc_images[desc_images.len() - 1]))
+                }
+                ShaderInstanceBinding::UniformBuffer(buffer, offset, range) => {
+                    desc_buffers.push(
+                        DescriptorBufferInfo::builder()
+                            .buffer(*buffer)
+                            .offset(*offset)
+                            .range(*range).build());
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                        .buffer_info(slice::from_ref(&desc_buffers[desc_buffers.len() - 1]))
+                }
+                ShaderInstanceBinding::StorageBuffer(buffer, offset, range) => {
+                    desc_buffers.push(
+                        DescriptorBufferInfo::builder()
+                            .buffer(*buffer)
+                            .offset(*offset)
+                            .range(*range).build());
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(slice::from_ref(&desc_buffers[desc_buffers.len() - 1]))
+                }
+                ShaderInstanceBinding::UniformBufferDynamic(buffer, offset, range) => {
+                    desc_buffers.push(
+                        DescriptorBufferInfo::builder()
+                            .buffer(*buffer)
+                            .offset(*offset)
+                            .range(*range).build());
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                        .buffer_info(slice::from_ref(&desc_buffers[desc_buffers.len() - 1]))
+                }
+                ShaderInstanceBinding::StorageBufferDynamic(buffer, offset, range) => {
+                    desc_buffers.push(
+                        DescriptorBufferInfo::builder()
+                            .buffer(*buffer)
+                            .offset(*offset)
+                            .range(*range).build());
+                    vk::WriteDescriptorSet::builder()
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                        .buffer_info(slice::from_ref(&desc_buffers[desc_buffers.len() - 1]))
+                }
             }
                 .dst_set(self.desc_set.unwrap())
                 .dst_binding(*binding)
+                .dst_array_element(*element)
                 .build();
             write_desc_set.push(write_set);
         }
@@ -66,6 +157,8 @@ impl DescriptorSets {
 
 impl Drop for DescriptorSets {
     fn drop(&mut self) {
-        self.ctx.descriptor_pool().free(self.desc_set.take().unwrap()).unwrap();
+        if let Some(allocation) = self.allocation.take() {
+            self.ctx.descriptor_pool().free(&allocation).unwrap();
+        }
     }
 }
\ No newline at end of file
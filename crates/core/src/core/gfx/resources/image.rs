@@ -1,8 +1,14 @@
+use crate::assets::texture_importer::texture_data::TextureData;
+use crate::core::gfx::bindless::BindlessImageSlot;
 use crate::core::gfx::command_buffer::CommandBuffer;
 use crate::core::gfx::device::{DeviceCtx, Fence};
-use crate::core::gfx::resources::buffer::{Buffer, BufferAccess, BufferCreateInfo, BufferMemory};
+use crate::core::gfx::resources::buffer::{readback_barrier, Buffer, BufferAccess, BufferCreateInfo, BufferMemory, ReadbackHandle};
 use anyhow::{anyhow, Error};
+use glam::{Vec3, Vec4};
+use half::f16;
 use image::{ColorType, DynamicImage, EncodableLayout};
+use std::borrow::Cow;
+use std::f32::consts::PI;
 use vulkanalia::vk;
 use vulkanalia::vk::{DeviceV1_0, HasBuilder};
 use vulkanalia_vma::Alloc;
@@ -15,10 +21,11 @@ pub struct Image {
     view: Option<vk::ImageView>,
     create_infos: ImageCreateOptions,
     current_layout: vk::ImageLayout,
+    bindless_index: Option<u32>,
     ctx: DeviceCtx,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ImageCreateOptions {
     pub image_type: vk::ImageType,
     pub format: vk::Format,
@@ -26,68 +33,136 @@ pub struct ImageCreateOptions {
     pub width: u32,
     pub height: u32,
     pub depth: u32,
+    /// 0 for a full mip chain
     pub mips_levels: u32,
+    /// 0 is treated as 1
+    pub array_layers: u32,
+    /// Cube compatible image, viewed as a cube (array). The layer count should be a multiple of 6.
+    pub cube: bool,
     pub is_depth: bool,
+    /// Empty is treated as a single sample
+    pub samples: vk::SampleCountFlags,
 }
 
 
 impl Image {
     pub fn new(ctx: DeviceCtx, create_infos: ImageCreateOptions) -> Result<Resource<Self>, Error> {
-        let infos = vk::ImageCreateInfo::builder()
+        let create_infos = Self::resolve_create_infos(create_infos)?;
+        let allocation_options = vulkanalia_vma::AllocationOptions::default();
+        let (image, allocation) = unsafe { ctx.allocator().create_image(Self::image_create_info(&create_infos), &allocation_options) }?;
+
+        let mut image = Resource::new(Self {
+            image: Some(image),
+            allocation: Some(allocation),
+            view: None,
+            create_infos,
+            current_layout: vk::ImageLayout::UNDEFINED,
+            bindless_index: None,
+            ctx,
+        });
+        image.create_view()?;
+        Ok(image)
+    }
+
+    /// Create an image without any memory bound to it. The memory should be provided later using [`Image::bind_memory`].
+    /// The allocation is not owned by the image and should outlive it.
+    pub fn new_unbound(ctx: DeviceCtx, create_infos: ImageCreateOptions) -> Result<Resource<Self>, Error> {
+        let create_infos = Self::resolve_create_infos(create_infos)?;
+        let image = unsafe { ctx.device().create_image(&Self::image_create_info(&create_infos), None) }?;
+
+        Ok(Resource::new(Self {
+            image: Some(image),
+            allocation: None,
+            view: None,
+            create_infos,
+            current_layout: vk::ImageLayout::UNDEFINED,
+            bindless_index: None,
+            ctx,
+        }))
+    }
+
+    pub fn memory_requirements(&self) -> Result<vk::MemoryRequirements, Error> {
+        Ok(unsafe { self.ctx.device().get_image_memory_requirements(*self.image()?) })
+    }
+
+    pub fn bind_memory(&mut self, memory: vulkanalia_vma::Allocation) -> Result<(), Error> {
+        if self.allocation.is_some() || self.view.is_some() {
+            return Err(anyhow!("Image memory is already bound"));
+        }
+        unsafe { self.ctx.allocator().bind_image_memory(memory, *self.image()?) }?;
+        self.create_view()
+    }
+
+    fn resolve_create_infos(mut create_infos: ImageCreateOptions) -> Result<ImageCreateOptions, Error> {
+        create_infos.array_layers = create_infos.array_layers.max(1);
+        if create_infos.samples.is_empty() {
+            create_infos.samples = vk::SampleCountFlags::_1;
+        }
+        if create_infos.cube && (!create_infos.array_layers.is_multiple_of(6) || create_infos.width != create_infos.height) {
+            return Err(anyhow!("Cube images should be square with a multiple of 6 layers : {}x{} with {} layers", create_infos.width, create_infos.height, create_infos.array_layers));
+        }
+        let full_chain = mip_count(create_infos.width, create_infos.height, create_infos.depth);
+        if create_infos.mips_levels == 0 {
+            create_infos.mips_levels = full_chain;
+        } else if create_infos.mips_levels > full_chain {
+            return Err(anyhow!("Too many mip levels : {} requested, {} at most for {}x{}x{}", create_infos.mips_levels, full_chain, create_infos.width, create_infos.height, create_infos.depth));
+        }
+        Ok(create_infos)
+    }
+
+    fn image_create_info(create_infos: &ImageCreateOptions) -> vk::ImageCreateInfo {
+        // Transient attachments are never accessed outside of their render pass
+        let usage = if create_infos.usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+            create_infos.usage
+        } else {
+            create_infos.usage | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC
+        };
+
+        vk::ImageCreateInfo::builder()
             .image_type(create_infos.image_type)
             .format(create_infos.format)
             .extent(vk::Extent3D { width: create_infos.width, height: create_infos.height, depth: create_infos.depth })
+            .flags(if create_infos.cube { vk::ImageCreateFlags::CUBE_COMPATIBLE } else { vk::ImageCreateFlags::empty() })
             .mip_levels(create_infos.mips_levels)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::_1)
+            .array_layers(create_infos.array_layers)
+            .samples(create_infos.samples)
             .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(create_infos.usage | vk::ImageUsageFlags::TRANSFER_DST)
+            .usage(usage)
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .build();
-
-        let allocation_options = vulkanalia_vma::AllocationOptions::default();
-        let (image, allocation) = unsafe { ctx.allocator().create_image(infos, &allocation_options) }?;
+            .build()
+    }
 
+    fn create_view(&mut self) -> Result<(), Error> {
         let image_view_ci = vk::ImageViewCreateInfo::builder()
-            .image(image)
-            .view_type(vk::ImageViewType::_2D)
-            .format(create_infos.format)
+            .image(*self.image()?)
+            .view_type(self.view_type())
+            .format(self.create_infos.format)
             .components(vk::ComponentMapping { r: vk::ComponentSwizzle::R, g: vk::ComponentSwizzle::G, b: vk::ComponentSwizzle::B, a: vk::ComponentSwizzle::A })
             .subresource_range(vk::ImageSubresourceRange::builder()
-                .aspect_mask(if create_infos.is_depth { vk::ImageAspectFlags::DEPTH } else { vk::ImageAspectFlags::COLOR })
+                .aspect_mask(if self.create_infos.is_depth { vk::ImageAspectFlags::DEPTH } else { vk::ImageAspectFlags::COLOR })
                 .base_mip_level(0)
-                .level_count(create_infos.mips_levels)
+                .level_count(self.create_infos.mips_levels)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(self.create_infos.array_layers)
                 .build())
             .build();
 
-        let image_view = unsafe { ctx.device().create_image_view(&image_view_ci, None)? };
+        self.view = Some(unsafe { self.ctx.device().create_image_view(&image_view_ci, None)? });
+        Ok(())
+    }
 
-        Ok(Resource::new(Self {
-            image: Some(image),
-            allocation: Some(allocation),
-            view: Some(image_view),
-            create_infos,
-            current_layout: vk::ImageLayout::UNDEFINED,
-            ctx,
-        }))
+    pub fn view_type(&self) -> vk::ImageViewType {
+        let layered = self.create_infos.array_layers > 1;
+        match self.create_infos.image_type {
+            vk::ImageType::_1D => { if layered { vk::ImageViewType::_1D_ARRAY } else { vk::ImageViewType::_1D } }
+            vk::ImageType::_3D => { vk::ImageViewType::_3D }
+            _ if self.create_infos.cube => { if self.create_infos.array_layers > 6 { vk::ImageViewType::CUBE_ARRAY } else { vk::ImageViewType::CUBE } }
+            _ => { if layered { vk::ImageViewType::_2D_ARRAY } else { vk::ImageViewType::_2D } }
+        }
     }
 
     pub fn from_dynamic_image(ctx: DeviceCtx, data: &DynamicImage, create_infos: ImageCreateOptions) -> Result<Resource<Self>, Error> {
-        let format = match data.color() {
-            ColorType::L8 => { vk::Format::R8_UNORM }
-            ColorType::La8 => { vk::Format::R8G8_UNORM }
-            ColorType::Rgb8 => { vk::Format::R8G8B8A8_UNORM }
-            ColorType::Rgba8 => { vk::Format::R8G8B8A8_UNORM }
-            ColorType::L16 => { vk::Format::R16_UNORM }
-            ColorType::La16 => { vk::Format::R16G16_UNORM }
-            ColorType::Rgb16 => { vk::Format::R16G16B16A16_UNORM }
-            ColorType::Rgba16 => { vk::Format::R16G16B16A16_UNORM }
-            ColorType::Rgb32F => { vk::Format::R16G16B16A16_SFLOAT }
-            ColorType::Rgba32F => { vk::Format::R32G32B32A32_SFLOAT }
-            f => { return Err(anyhow!("Unsupported color format {:?}", f)) }
-        };
+        let (format, pixels) = dynamic_image_data(data)?;
         let mut create_infos = create_infos;
         create_infos.image_type = vk::ImageType::_2D;
         create_infos.format = format;
@@ -95,22 +170,110 @@ impl Image {
         create_infos.height = data.height();
         create_infos.depth = 1;
         let mut image = Self::new(ctx, create_infos)?;
+        image.set_data(&BufferMemory::from_slice(&pixels))?;
+        Ok(image)
+    }
 
-        match data.color() {
-            ColorType::Rgb8 => {
-                image.set_data(&BufferMemory::from_slice(data.clone().into_rgba8().as_bytes()))?;
-            }
-            ColorType::Rgb16 => {
-                image.set_data(&BufferMemory::from_slice(data.clone().into_rgba16().as_bytes()))?;
-            }
-            ColorType::Rgb32F => {
-                image.set_data(&BufferMemory::from_slice(data.clone().into_rgba32f().as_bytes()))?;
-            }
-            _ => {
-                image.set_data(&BufferMemory::from_slice(data.as_bytes()))?;
+    /// Upload a texture loaded from a container with its mip chain. Compressed textures the device can't sample are decoded on the CPU when possible.
+    /// A single level texture gets a generated mip chain if `create_infos.mips_levels` is 0 and its format is not compressed.
+    pub fn from_texture_data(ctx: DeviceCtx, texture: &TextureData, create_infos: ImageCreateOptions) -> Result<Resource<Self>, Error> {
+        let texture = if ctx.physical_device().supports_format(&ctx.instance(), texture.format, vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+            Cow::Borrowed(texture)
+        } else {
+            Cow::Owned(texture.decompressed()?)
+        };
+        let mut create_infos = create_infos;
+        create_infos.image_type = texture.image_type;
+        create_infos.format = texture.format;
+        create_infos.width = texture.width;
+        create_infos.height = texture.height;
+        create_infos.depth = texture.depth;
+        create_infos.array_layers = texture.array_layers;
+        create_infos.cube = texture.cube;
+        if texture.mip_count() > 1 || create_infos.mips_levels != 0 || is_compressed_format(texture.format) {
+            create_infos.mips_levels = texture.mip_count();
+        }
+        let mut image = Self::new(ctx, create_infos)?;
+        for (layer, mips) in texture.layers.iter().enumerate() {
+            image.set_layer_mip_chain(layer as u32, &mips.iter().map(|mip| BufferMemory::from_slice(mip)).collect::<Vec<_>>())?;
+        }
+        Ok(image)
+    }
+
+    /// Cubemap from its faces in the +X, -X, +Y, -Y, +Z, -Z order. Faces should be square and share the same size and color type.
+    pub fn cubemap_from_images(ctx: DeviceCtx, faces: &[DynamicImage], create_infos: ImageCreateOptions) -> Result<Resource<Self>, Error> {
+        if faces.len() != 6 {
+            return Err(anyhow!("A cubemap needs 6 faces, got {}", faces.len()));
+        }
+        let mut face_data = vec![];
+        for face in faces {
+            if face.width() != faces[0].width() || face.height() != faces[0].height() || face.color() != faces[0].color() {
+                return Err(anyhow!("Cubemap faces should share the same size and color type"));
             }
+            face_data.push(dynamic_image_data(face)?);
         }
+        let mut create_infos = create_infos;
+        create_infos.image_type = vk::ImageType::_2D;
+        create_infos.format = face_data[0].0;
+        create_infos.width = faces[0].width();
+        create_infos.height = faces[0].height();
+        create_infos.depth = 1;
+        create_infos.array_layers = 6;
+        create_infos.cube = true;
+        let mut image = Self::new(ctx, create_infos)?;
+        for (layer, (_, pixels)) in face_data.iter().enumerate() {
+            image.set_layer_data(layer as u32, &BufferMemory::from_slice(pixels))?;
+        }
+        Ok(image)
+    }
+
+    /// Cubemap of `face_size` texels per side, projected from an equirectangular panorama (usually an HDR image). The faces are stored as R16G16B16A16_SFLOAT.
+    pub fn cubemap_from_equirectangular(ctx: DeviceCtx, panorama: &DynamicImage, face_size: u32, create_infos: ImageCreateOptions) -> Result<Resource<Self>, Error> {
+        let panorama = panorama.to_rgba32f();
+        let (width, height) = (panorama.width() as usize, panorama.height() as usize);
+        let texel = |x: usize, y: usize| Vec4::from_slice(&panorama.as_raw()[(y * width + x) * 4..(y * width + x) * 4 + 4]);
+        let sample = |direction: Vec3| {
+            let direction = direction.normalize();
+            let u = (0.5 + direction.z.atan2(direction.x) / (2.0 * PI)) * width as f32 - 0.5;
+            let v = (0.5 - direction.y.asin() / PI) * height as f32 - 0.5;
+            let (x0, y0) = (u.floor(), v.floor());
+            let (fx, fy) = (u - x0, v - y0);
+            // Wrap horizontally, clamp vertically
+            let x = |x: f32| (x as i64).rem_euclid(width as i64) as usize;
+            let y = |y: f32| (y.max(0.0) as usize).min(height - 1);
+            let top = texel(x(x0), y(y0)).lerp(texel(x(x0 + 1.0), y(y0)), fx);
+            let bottom = texel(x(x0), y(y0 + 1.0)).lerp(texel(x(x0 + 1.0), y(y0 + 1.0)), fx);
+            top.lerp(bottom, fy)
+        };
 
+        let mut create_infos = create_infos;
+        create_infos.image_type = vk::ImageType::_2D;
+        create_infos.format = vk::Format::R16G16B16A16_SFLOAT;
+        create_infos.width = face_size;
+        create_infos.height = face_size;
+        create_infos.depth = 1;
+        create_infos.array_layers = 6;
+        create_infos.cube = true;
+        let mut image = Self::new(ctx, create_infos)?;
+        for layer in 0..6 {
+            let mut pixels = Vec::with_capacity(face_size as usize * face_size as usize * 4);
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let u = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                    let v = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                    let direction = match layer {
+                        0 => { Vec3::new(1.0, -v, -u) }
+                        1 => { Vec3::new(-1.0, -v, u) }
+                        2 => { Vec3::new(u, 1.0, v) }
+                        3 => { Vec3::new(u, -1.0, -v) }
+                        4 => { Vec3::new(u, -v, 1.0) }
+                        _ => { Vec3::new(-u, -v, -1.0) }
+                    };
+                    pixels.extend(sample(direction).to_array().map(f16::from_f32));
+                }
+            }
+            image.set_layer_data(layer, &BufferMemory::from_slice(&pixels))?;
+        }
         Ok(image)
     }
 
@@ -122,54 +285,196 @@ impl Image {
         vk::Extent2D { width: self.create_infos.width, height: self.create_infos.height }
     }
 
+    pub fn create_infos(&self) -> &ImageCreateOptions {
+        &self.create_infos
+    }
+
+    pub fn mip_extent(&self, mip_level: u32) -> vk::Extent3D {
+        vk::Extent3D {
+            width: (self.create_infos.width >> mip_level).max(1),
+            height: (self.create_infos.height >> mip_level).max(1),
+            depth: (self.create_infos.depth >> mip_level).max(1),
+        }
+    }
+
+    /// Upload mip 0 of the first layer, then generate the other mip levels with blits
     pub fn set_data(&mut self, data: &BufferMemory) -> Result<(), Error> {
-        let mut transfer_buffer = Buffer::new(self.ctx.clone(), 1, data.get_size(), BufferCreateInfo { usage: vk::BufferUsageFlags::TRANSFER_SRC, access: BufferAccess::CpuToGpu, buffer_type: Default::default() })?;
+        self.upload(0, &[data])
+    }
+
+    /// Upload precomputed mip levels of the first layer starting from mip 0. The missing levels are generated with blits.
+    pub fn set_mip_chain(&mut self, mips: &[BufferMemory]) -> Result<(), Error> {
+        self.upload(0, &mips.iter().collect::<Vec<_>>())
+    }
+
+    /// Upload mip 0 of an array layer (or cube face), then generate its other mip levels
+    pub fn set_layer_data(&mut self, layer: u32, data: &BufferMemory) -> Result<(), Error> {
+        self.upload(layer, &[data])
+    }
+
+    /// Upload precomputed mip levels of an array layer (or cube face). The missing levels are generated with blits.
+    pub fn set_layer_mip_chain(&mut self, layer: u32, mips: &[BufferMemory]) -> Result<(), Error> {
+        self.upload(layer, &mips.iter().collect::<Vec<_>>())
+    }
+
+    /// Regenerate every mip level of every layer from mip 0
+    pub fn generate_mips(&mut self) -> Result<(), Error> {
+        if self.current_layout == vk::ImageLayout::UNDEFINED {
+            return Err(anyhow!("Cannot generate the mips of an image whose content is undefined"));
+        }
+        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Graphic)?;
+        command_buffer.begin_one_time()?;
+        self.set_image_layout(command_buffer.ptr()?, vk::ImageLayout::TRANSFER_DST_OPTIMAL)?;
+        self.record_mip_generation(command_buffer.ptr()?, 1, 0, self.create_infos.array_layers)?;
+        self.set_image_layout(command_buffer.ptr()?, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
+        command_buffer.end()?;
+        self.submit_and_wait(&command_buffer)
+    }
 
-        transfer_buffer.set_data(0, data)?;
+    fn upload(&mut self, layer: u32, mips: &[&BufferMemory]) -> Result<(), Error> {
+        if layer >= self.create_infos.array_layers {
+            return Err(anyhow!("Invalid array layer {layer} : the image has {} layers", self.create_infos.array_layers));
+        }
+        if mips.is_empty() || mips.len() > self.create_infos.mips_levels as usize {
+            return Err(anyhow!("Expected between 1 and {} mip levels, got {}", self.create_infos.mips_levels, mips.len()));
+        }
+        // Copy offsets should be a multiple of both the texel (or block) size and 4
+        let offset_alignment = format_block(self.create_infos.format).map(|(_, _, block_size)| block_size * 4).unwrap_or(16);
+        let mut offsets = vec![];
+        let mut total_size = 0;
+        for (level, mip) in mips.iter().enumerate() {
+            let extent = self.mip_extent(level as u32);
+            if let Some(expected) = level_size(self.create_infos.format, extent.width, extent.height, extent.depth) {
+                if mip.get_size() < expected {
+                    return Err(anyhow!("Mip {level} is too small : size={}, expected={expected}", mip.get_size()));
+                }
+            }
+            offsets.push(total_size);
+            total_size += mip.get_size().next_multiple_of(offset_alignment);
+        }
+        let mut transfer_buffer = Buffer::new(self.ctx.clone(), 1, total_size, BufferCreateInfo { usage: vk::BufferUsageFlags::TRANSFER_SRC, access: BufferAccess::CpuToGpu, buffer_type: Default::default() })?;
+        for (mip, offset) in mips.iter().zip(&offsets) {
+            transfer_buffer.write_slice(*offset, &mip.as_slice()[..mip.get_size()])?;
+        }
 
-        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Transfer)?;
+        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Graphic)?;
         command_buffer.begin_one_time()?;
 
         self.set_image_layout(command_buffer.ptr()?, vk::ImageLayout::TRANSFER_DST_OPTIMAL)?;
-        // GPU copy command
+        let regions = offsets.iter().enumerate().map(|(level, offset)| {
+            vk::BufferImageCopy::builder()
+                .buffer_offset(*offset as vk::DeviceSize)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(self.copy_aspect())
+                    .mip_level(level as u32)
+                    .base_array_layer(layer)
+                    .layer_count(1)
+                    .build())
+                .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+                .image_extent(self.mip_extent(level as u32))
+                .build()
+        }).collect::<Vec<_>>();
         unsafe {
             self.ctx.device().cmd_copy_buffer_to_image(
                 *command_buffer.ptr()?,
                 *transfer_buffer.ptr()?,
                 self.image.ok_or(anyhow!("invalid image"))?,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &[vk::BufferImageCopy::builder()
-                    .buffer_offset(0)
-                    .buffer_row_length(0)
-                    .buffer_image_height(0)
-                    .image_subresource(vk::ImageSubresourceLayers::builder()
-                        .aspect_mask(if self.create_infos.is_depth { vk::ImageAspectFlags::DEPTH } else { vk::ImageAspectFlags::COLOR })
-                        .mip_level(0)
-                        .base_array_layer(0)
-                        .layer_count(1)
-                        .build())
-                    .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
-                    .image_extent(vk::Extent3D { width: self.create_infos.width, height: self.create_infos.height, depth: self.create_infos.depth })
-                    .build()]);
+                regions.as_slice());
         }
+        if mips.len() < self.create_infos.mips_levels as usize {
+            self.record_mip_generation(command_buffer.ptr()?, mips.len() as u32, layer, 1)?;
+        }
+        self.set_image_layout(command_buffer.ptr()?, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
 
         command_buffer.end()?;
-        let command_buffers = vec![*command_buffer.ptr()?];
-        let submit_info = vk::SubmitInfo::builder()
-            .command_buffers(command_buffers.as_slice())
-            .build();
-
-        let fence = Fence::new(self.ctx.clone());
-        let submit_infos = vec![submit_info];
-        self.ctx.queues().submit(&QueueFlag::Transfer, submit_infos.as_slice(), Some(fence.handle()));
-        fence.wait();
+        self.submit_and_wait(&command_buffer)
+    }
 
-        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Graphic)?;
-        command_buffer.begin_one_time()?;
+    /// Blit each level from `first_level` from the previous one, for the given layers. Every level is expected in TRANSFER_DST_OPTIMAL, and is left in this layout.
+    fn record_mip_generation(&self, command_buffer: &vk::CommandBuffer, first_level: u32, base_layer: u32, layer_count: u32) -> Result<(), Error> {
+        let features = self.ctx.physical_device().format_properties(&self.ctx.instance(), self.create_infos.format).optimal_tiling_features;
+        if !features.contains(vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST) {
+            return Err(anyhow!("Cannot generate mips for {:?} : blits are not supported", self.create_infos.format));
+        }
+        // sRGB formats are filtered in linear space
+        let filter = if features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) { vk::Filter::LINEAR } else { vk::Filter::NEAREST };
+        let image = self.image.ok_or(anyhow!("invalid image"))?;
+        let aspect_mask = format_aspects(self.create_infos.format);
+        let barrier = |base_mip_level: u32, level_count: u32, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout, src_access_mask: vk::AccessFlags, dst_access_mask: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(vk::ImageSubresourceRange::builder()
+                    .aspect_mask(aspect_mask)
+                    .base_mip_level(base_mip_level)
+                    .level_count(level_count)
+                    .base_array_layer(base_layer)
+                    .layer_count(layer_count)
+                    .build())
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .build()
+        };
+        let memory_barriers: [vk::MemoryBarrier; 0] = [];
+        let buffer_memory_barriers: [vk::BufferMemoryBarrier; 0] = [];
+        let offset = |extent: vk::Extent3D| vk::Offset3D { x: extent.width as i32, y: extent.height as i32, z: extent.depth as i32 };
+        let subresource = |mip_level: u32| vk::ImageSubresourceLayers::builder()
+            .aspect_mask(aspect_mask)
+            .mip_level(mip_level)
+            .base_array_layer(base_layer)
+            .layer_count(layer_count)
+            .build();
 
-        self.set_image_layout(command_buffer.ptr()?, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
-        command_buffer.end()?;
+        for level in first_level..self.create_infos.mips_levels {
+            let blit = vk::ImageBlit::builder()
+                .src_subresource(subresource(level - 1))
+                .src_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, offset(self.mip_extent(level - 1))])
+                .dst_subresource(subresource(level))
+                .dst_offsets([vk::Offset3D { x: 0, y: 0, z: 0 }, offset(self.mip_extent(level))])
+                .build();
+            unsafe {
+                self.ctx.device().cmd_pipeline_barrier(
+                    *command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &memory_barriers,
+                    &buffer_memory_barriers,
+                    &[barrier(level - 1, 1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::TRANSFER_READ)]);
+                self.ctx.device().cmd_blit_image(
+                    *command_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    filter);
+            }
+        }
+        // Bring the source levels back to the layout of the last one
+        let source_levels = self.create_infos.mips_levels - first_level;
+        if source_levels > 0 {
+            unsafe {
+                self.ctx.device().cmd_pipeline_barrier(
+                    *command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &memory_barriers,
+                    &buffer_memory_barriers,
+                    &[barrier(first_level - 1, source_levels, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::AccessFlags::empty(), vk::AccessFlags::empty())]);
+            }
+        }
+        Ok(())
+    }
 
+    fn submit_and_wait(&self, command_buffer: &CommandBuffer) -> Result<(), Error> {
         let command_buffers = vec![*command_buffer.ptr()?];
         let submit_info = vk::SubmitInfo::builder()
             .command_buffers(command_buffers.as_slice())
@@ -182,39 +487,122 @@ impl Image {
         Ok(())
     }
 
+    // Depth / stencil copies only address the depth aspect
+    fn copy_aspect(&self) -> vk::ImageAspectFlags {
+        let aspects = format_aspects(self.create_infos.format);
+        if aspects.contains(vk::ImageAspectFlags::DEPTH) { vk::ImageAspectFlags::DEPTH } else { aspects }
+    }
+
+    /// Copy one mip level of one array layer back to the CPU, waiting for the work previously submitted on the graphic queue.
+    /// Depth / stencil images only return their depth aspect. The image is left in its current layout.
+    /// `layout` is the layout the image is in when the copy starts, the layout tracked by the image when None.
+    /// Frame graph targets are not tracked : they end their pass in SHADER_READ_ONLY_OPTIMAL.
+    pub fn read_pixels(&self, mip_level: u32, array_layer: u32, layout: Option<vk::ImageLayout>) -> Result<ReadbackHandle<'static>, Error> {
+        if mip_level >= self.create_infos.mips_levels {
+            return Err(anyhow!("Invalid mip level {mip_level} : the image has {} levels", self.create_infos.mips_levels));
+        }
+        if array_layer >= self.create_infos.array_layers {
+            return Err(anyhow!("Invalid array layer {array_layer} : the image has {} layers", self.create_infos.array_layers));
+        }
+        let layout = layout.unwrap_or(self.current_layout);
+        if layout == vk::ImageLayout::UNDEFINED {
+            return Err(anyhow!("Cannot read an image whose content is undefined"));
+        }
+        let texel_size = format_texel_size(self.create_infos.format).ok_or(anyhow!("Readback is not supported for {:?}", self.create_infos.format))?;
+        let aspects = format_aspects(self.create_infos.format);
+        let aspect = self.copy_aspect();
+        let extent = self.mip_extent(mip_level);
+        let size = extent.width as usize * extent.height as usize * extent.depth as usize * texel_size;
+        let staging_buffer = Buffer::new(self.ctx.clone(), 1, size, BufferCreateInfo { usage: vk::BufferUsageFlags::TRANSFER_DST, access: BufferAccess::GpuToCpu, buffer_type: Default::default() })?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(aspects)
+            .base_mip_level(mip_level)
+            .level_count(1)
+            .base_array_layer(array_layer)
+            .layer_count(1)
+            .build();
+        let image = self.image.ok_or(anyhow!("invalid image"))?;
+        let transition = |old_layout: vk::ImageLayout, new_layout: vk::ImageLayout, src_access_mask: vk::AccessFlags, dst_access_mask: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .build()
+        };
+
+        let command_buffer = CommandBuffer::new(self.ctx.clone(), &QueueFlag::Graphic)?;
+        command_buffer.begin_one_time()?;
+        unsafe {
+            let memory_barriers: [vk::MemoryBarrier; 0] = [];
+            let buffer_memory_barriers: [vk::BufferMemoryBarrier; 0] = [];
+            self.ctx.device().cmd_pipeline_barrier(
+                *command_buffer.ptr()?,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &buffer_memory_barriers,
+                &[transition(layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::TRANSFER_READ)]);
+            self.ctx.device().cmd_copy_image_to_buffer(
+                *command_buffer.ptr()?,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                *staging_buffer.ptr()?,
+                &[vk::BufferImageCopy::builder()
+                    .buffer_offset(0)
+                    .buffer_row_length(0)
+                    .buffer_image_height(0)
+                    .image_subresource(vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(aspect)
+                        .mip_level(mip_level)
+                        .base_array_layer(array_layer)
+                        .layer_count(1)
+                        .build())
+                    .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+                    .image_extent(extent)
+                    .build()]);
+            self.ctx.device().cmd_pipeline_barrier(
+                *command_buffer.ptr()?,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &buffer_memory_barriers,
+                &[transition(vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout, vk::AccessFlags::empty(), vk::AccessFlags::empty())]);
+        }
+        readback_barrier(&self.ctx, *command_buffer.ptr()?, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+        command_buffer.end()?;
+
+        ReadbackHandle::submit_staged(self.ctx.clone(), command_buffer, staging_buffer, size, texel_size)
+    }
 
-    fn set_image_layout(&mut self, command_buffer: &vk::CommandBuffer, new_layout: vk::ImageLayout) -> Result<(), Error> {
-        let mut barrier = vk::ImageMemoryBarrier::builder()
+    /// Record a transition of every mip level to the new layout, waiting for the accesses allowed by the previous layout
+    pub fn set_image_layout(&mut self, command_buffer: &vk::CommandBuffer, new_layout: vk::ImageLayout) -> Result<(), Error> {
+        let (src_access_mask, src_stage) = layout_access(self.current_layout);
+        let (dst_access_mask, dst_stage) = layout_access(new_layout);
+        let barrier = vk::ImageMemoryBarrier::builder()
             .old_layout(self.current_layout)
             .new_layout(new_layout)
             .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .image(*self.image.as_ref().ok_or(anyhow!("Invalid image during layout update"))?)
             .subresource_range(vk::ImageSubresourceRange::builder()
-                .aspect_mask(if self.create_infos.is_depth { vk::ImageAspectFlags::DEPTH } else { vk::ImageAspectFlags::COLOR })
+                .aspect_mask(format_aspects(self.create_infos.format))
                 .base_mip_level(0)
                 .level_count(self.create_infos.mips_levels)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(self.create_infos.array_layers)
                 .build())
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
             .build();
 
-        let source_destination_stages = if self.current_layout == vk::ImageLayout::UNDEFINED && new_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL
-        {
-            barrier.src_access_mask = vk::AccessFlags::empty();
-            barrier.dst_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-
-            (vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER)
-        } else if self.current_layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL && new_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
-        {
-            barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-            barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
-
-            (vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER)
-        } else {
-            panic!("Unsupported layout transition");
-        };
-
         self.current_layout = new_layout;
 
         unsafe {
@@ -222,8 +610,8 @@ impl Image {
             let buffer_memory_barriers: [vk::BufferMemoryBarrier; 0] = [];
             self.ctx.device().cmd_pipeline_barrier(
                 *command_buffer,
-                source_destination_stages.0,
-                source_destination_stages.1,
+                src_stage,
+                dst_stage,
                 vk::DependencyFlags::empty(),
                 &memory_barriers,
                 &buffer_memory_barriers,
@@ -240,6 +628,18 @@ impl Image {
         &self.current_layout
     }
 
+    /// Index of the image in the device's bindless table, registered on first call.
+    /// Shaders sample it in SHADER_READ_ONLY_OPTIMAL layout. The index is recycled once the image is destroyed and its frames are complete.
+    pub fn bindless_index(&mut self) -> Result<u32, Error> {
+        if let Some(index) = self.bindless_index {
+            return Ok(index);
+        }
+        let bindless = self.ctx.bindless().ok_or(anyhow!("Bindless table is not available on this device"))?;
+        let index = bindless.register_image(*self.view()?, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
+        self.bindless_index = Some(index);
+        Ok(index)
+    }
+
     pub fn image(&self) -> Result<&vk::Image, Error> {
         self.image.as_ref().ok_or(anyhow!("Invalid image"))
     }
@@ -247,8 +647,208 @@ impl Image {
 
 impl Drop for Image {
     fn drop(&mut self) {
-        unsafe { self.ctx.allocator().destroy_image(self.image.take().unwrap(), self.allocation.unwrap()) };
+        if let Some(index) = self.bindless_index.take() {
+            self.ctx.queue_resource_cleanup(Box::new(BindlessImageSlot { index, ctx: self.ctx.clone() }));
+        }
+        if let Some(view) = self.view.take() {
+            unsafe { self.ctx.device().destroy_image_view(view, None) };
+        }
+
+        match self.allocation.take() {
+            None => unsafe { self.ctx.device().destroy_image(self.image.take().unwrap(), None) },
+            Some(allocation) => unsafe { self.ctx.allocator().destroy_image(self.image.take().unwrap(), allocation) }
+        }
+    }
+}
+/// Vulkan format and tightly packed pixels of an image. RGB images are expanded to RGBA.
+fn dynamic_image_data(data: &DynamicImage) -> Result<(vk::Format, Cow<'_, [u8]>), Error> {
+    Ok(match data.color() {
+        ColorType::L8 => { (vk::Format::R8_UNORM, Cow::Borrowed(data.as_bytes())) }
+        ColorType::La8 => { (vk::Format::R8G8_UNORM, Cow::Borrowed(data.as_bytes())) }
+        ColorType::Rgb8 => { (vk::Format::R8G8B8A8_UNORM, Cow::Owned(data.to_rgba8().into_raw())) }
+        ColorType::Rgba8 => { (vk::Format::R8G8B8A8_UNORM, Cow::Borrowed(data.as_bytes())) }
+        ColorType::L16 => { (vk::Format::R16_UNORM, Cow::Borrowed(data.as_bytes())) }
+        ColorType::La16 => { (vk::Format::R16G16_UNORM, Cow::Borrowed(data.as_bytes())) }
+        ColorType::Rgb16 => { (vk::Format::R16G16B16A16_UNORM, Cow::Owned(data.to_rgba16().as_bytes().to_vec())) }
+        ColorType::Rgba16 => { (vk::Format::R16G16B16A16_UNORM, Cow::Borrowed(data.as_bytes())) }
+        ColorType::Rgb32F => { (vk::Format::R32G32B32A32_SFLOAT, Cow::Owned(data.to_rgba32f().as_bytes().to_vec())) }
+        ColorType::Rgba32F => { (vk::Format::R32G32B32A32_SFLOAT, Cow::Borrowed(data.as_bytes())) }
+        f => { return Err(anyhow!("Unsupported color format {:?}", f)) }
+    })
+}
+
+/// Number of levels of a full mip chain
+pub fn mip_count(width: u32, height: u32, depth: u32) -> u32 {
+    32 - width.max(height).max(depth).max(1).leading_zeros()
+}
+
+/// Accesses and stages an image in the given layout can be used with
+pub fn layout_access(layout: vk::ImageLayout) -> (vk::AccessFlags, vk::PipelineStageFlags) {
+    match layout {
+        vk::ImageLayout::UNDEFINED | vk::ImageLayout::PREINITIALIZED => { (vk::AccessFlags::empty(), vk::PipelineStageFlags::TOP_OF_PIPE) }
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => { (vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER) }
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => { (vk::AccessFlags::TRANSFER_READ, vk::PipelineStageFlags::TRANSFER) }
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => {
+            (vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER)
+        }
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => {
+            (vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        }
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL | vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL => {
+            (vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE, vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+        }
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL | vk::ImageLayout::STENCIL_READ_ONLY_OPTIMAL => {
+            (vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER)
+        }
+        vk::ImageLayout::PRESENT_SRC_KHR => { (vk::AccessFlags::empty(), vk::PipelineStageFlags::BOTTOM_OF_PIPE) }
+        _ => { (vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE, vk::PipelineStageFlags::ALL_COMMANDS) }
+    }
+}
+
+/// Width, height and size in bytes of the blocks of a compressed format, or 1x1 and the texel size for uncompressed formats
+pub fn format_block(format: vk::Format) -> Option<(u32, u32, usize)> {
+    Some(match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK |
+        vk::Format::BC4_UNORM_BLOCK | vk::Format::BC4_SNORM_BLOCK |
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK |
+        vk::Format::EAC_R11_UNORM_BLOCK | vk::Format::EAC_R11_SNORM_BLOCK => { (4, 4, 8) }
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK | vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK |
+        vk::Format::BC5_UNORM_BLOCK | vk::Format::BC5_SNORM_BLOCK | vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK |
+        vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK |
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK | vk::Format::EAC_R11G11_UNORM_BLOCK | vk::Format::EAC_R11G11_SNORM_BLOCK |
+        vk::Format::ASTC_4X4_UNORM_BLOCK | vk::Format::ASTC_4X4_SRGB_BLOCK => { (4, 4, 16) }
+        vk::Format::ASTC_5X4_UNORM_BLOCK | vk::Format::ASTC_5X4_SRGB_BLOCK => { (5, 4, 16) }
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => { (5, 5, 16) }
+        vk::Format::ASTC_6X5_UNORM_BLOCK | vk::Format::ASTC_6X5_SRGB_BLOCK => { (6, 5, 16) }
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => { (6, 6, 16) }
+        vk::Format::ASTC_8X5_UNORM_BLOCK | vk::Format::ASTC_8X5_SRGB_BLOCK => { (8, 5, 16) }
+        vk::Format::ASTC_8X6_UNORM_BLOCK | vk::Format::ASTC_8X6_SRGB_BLOCK => { (8, 6, 16) }
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => { (8, 8, 16) }
+        vk::Format::ASTC_10X5_UNORM_BLOCK | vk::Format::ASTC_10X5_SRGB_BLOCK => { (10, 5, 16) }
+        vk::Format::ASTC_10X6_UNORM_BLOCK | vk::Format::ASTC_10X6_SRGB_BLOCK => { (10, 6, 16) }
+        vk::Format::ASTC_10X8_UNORM_BLOCK | vk::Format::ASTC_10X8_SRGB_BLOCK => { (10, 8, 16) }
+        vk::Format::ASTC_10X10_UNORM_BLOCK | vk::Format::ASTC_10X10_SRGB_BLOCK => { (10, 10, 16) }
+        vk::Format::ASTC_12X10_UNORM_BLOCK | vk::Format::ASTC_12X10_SRGB_BLOCK => { (12, 10, 16) }
+        vk::Format::ASTC_12X12_UNORM_BLOCK | vk::Format::ASTC_12X12_SRGB_BLOCK => { (12, 12, 16) }
+        format => { (1, 1, format_texel_size(format)?) }
+    })
+}
+
+pub fn is_compressed_format(format: vk::Format) -> bool {
+    format_block(format).is_some_and(|(width, height, _)| width > 1 || height > 1)
+}
+
+/// Size in bytes of a tightly packed image (or mip level) of the given extent. None for unsupported formats or when the size overflows.
+pub fn level_size(format: vk::Format, width: u32, height: u32, depth: u32) -> Option<usize> {
+    let (block_width, block_height, block_size) = format_block(format)?;
+    (width.div_ceil(block_width) as usize).checked_mul(height.div_ceil(block_height) as usize)?.checked_mul(depth as usize)?.checked_mul(block_size)
+}
+
+/// Size of a texel as copied to a buffer (depth aspect only for depth / stencil formats). None for unsupported formats.
+pub fn format_texel_size(format: vk::Format) -> Option<usize> {
+    Some(match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT | vk::Format::R8_SINT | vk::Format::R8_SRGB | vk::Format::S8_UINT => { 1 }
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SNORM | vk::Format::R8G8_UINT | vk::Format::R8G8_SINT |
+        vk::Format::R16_UNORM | vk::Format::R16_SFLOAT | vk::Format::R16_UINT | vk::Format::R16_SINT | vk::Format::D16_UNORM => { 2 }
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SNORM | vk::Format::R8G8B8A8_UINT | vk::Format::R8G8B8A8_SINT | vk::Format::R8G8B8A8_SRGB |
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB | vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::B10G11R11_UFLOAT_PACK32 |
+        vk::Format::R16G16_UNORM | vk::Format::R16G16_SFLOAT | vk::Format::R16G16_UINT | vk::Format::R16G16_SINT |
+        vk::Format::R32_SFLOAT | vk::Format::R32_UINT | vk::Format::R32_SINT |
+        vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => { 4 }
+        vk::Format::R16G16B16A16_UNORM | vk::Format::R16G16B16A16_SFLOAT | vk::Format::R16G16B16A16_UINT | vk::Format::R16G16B16A16_SINT |
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_UINT | vk::Format::R32G32_SINT => { 8 }
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT => { 12 }
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SINT => { 16 }
+        _ => { return None }
+    })
+}
+
+/// Aspects of a depth and / or stencil format, COLOR for every other format
+pub fn format_aspects(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => { vk::ImageAspectFlags::DEPTH }
+        vk::Format::S8_UINT => { vk::ImageAspectFlags::STENCIL }
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => { vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL }
+        _ => { vk::ImageAspectFlags::COLOR }
+    }
+}
+
+/// Color formats whose components are read as signed integers
+pub fn format_is_sint(format: vk::Format) -> bool {
+    matches!(format,
+        vk::Format::R8_SINT | vk::Format::R8G8_SINT | vk::Format::R8G8B8_SINT | vk::Format::B8G8R8_SINT | vk::Format::R8G8B8A8_SINT | vk::Format::B8G8R8A8_SINT |
+        vk::Format::A8B8G8R8_SINT_PACK32 | vk::Format::A2R10G10B10_SINT_PACK32 | vk::Format::A2B10G10R10_SINT_PACK32 |
+        vk::Format::R16_SINT | vk::Format::R16G16_SINT | vk::Format::R16G16B16_SINT | vk::Format::R16G16B16A16_SINT |
+        vk::Format::R32_SINT | vk::Format::R32G32_SINT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32A32_SINT |
+        vk::Format::R64_SINT | vk::Format::R64G64_SINT | vk::Format::R64G64B64_SINT | vk::Format::R64G64B64A64_SINT)
+}
+
+/// Color formats whose components are read as unsigned integers
+pub fn format_is_uint(format: vk::Format) -> bool {
+    matches!(format,
+        vk::Format::R8_UINT | vk::Format::R8G8_UINT | vk::Format::R8G8B8_UINT | vk::Format::B8G8R8_UINT | vk::Format::R8G8B8A8_UINT | vk::Format::B8G8R8A8_UINT |
+        vk::Format::A8B8G8R8_UINT_PACK32 | vk::Format::A2R10G10B10_UINT_PACK32 | vk::Format::A2B10G10R10_UINT_PACK32 |
+        vk::Format::R16_UINT | vk::Format::R16G16_UINT | vk::Format::R16G16B16_UINT | vk::Format::R16G16B16A16_UINT |
+        vk::Format::R32_UINT | vk::Format::R32G32_UINT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32A32_UINT |
+        vk::Format::R64_UINT | vk::Format::R64G64_UINT | vk::Format::R64G64B64_UINT | vk::Format::R64G64B64A64_UINT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::gfx::resources::buffer::BufferMemory;
+    use crate::core::gfx::resources::image::{Image, ImageCreateOptions};
+    use crate::engine::tests::test_device;
+    use vulkanalia::vk;
+
+    fn rgba8_options(size: u32, mips_levels: u32) -> ImageCreateOptions {
+        ImageCreateOptions {
+            image_type: vk::ImageType::_2D,
+            format: vk::Format::R8G8B8A8_UNORM,
+            usage: vk::ImageUsageFlags::SAMPLED,
+            width: size,
+            height: size,
+            depth: 1,
+            mips_levels,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn read_pixels_returns_uploaded_data() {
+        let device = test_device();
+        let mut image = Image::new(device.ctx.clone(), rgba8_options(4, 1)).unwrap();
+        assert!(image.read_pixels(0, 0, None).is_err(), "The content of a new image is undefined");
+
+        let pixels = (0..4 * 4 * 4).map(|value| value as u8).collect::<Vec<_>>();
+        image.set_data(&BufferMemory::from_slice(&pixels)).unwrap();
+        assert_eq!(image.read_pixels(0, 0, None).unwrap().wait_as::<u8>().unwrap(), pixels);
+        // The layout is unchanged : the image can be read again
+        assert_eq!(image.read_pixels(0, 0, None).unwrap().wait_as::<u8>().unwrap(), pixels);
+    }
 
-        unsafe { self.ctx.device().destroy_image_view(self.view.take().unwrap(), None) };
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn read_pixels_reads_generated_mips() {
+        let device = test_device();
+        let mut image = Image::new(device.ctx.clone(), rgba8_options(8, 0)).unwrap();
+        // A uniform color is preserved by the downsampling
+        let pixels = [10u8, 20, 30, 255].repeat(8 * 8);
+        image.set_data(&BufferMemory::from_slice(&pixels)).unwrap();
+        for mip_level in 1..4 {
+            let size = 8 >> mip_level;
+            let mip = image.read_pixels(mip_level, 0, None).unwrap().wait_as::<u8>().unwrap();
+            assert_eq!(mip, [10u8, 20, 30, 255].repeat(size * size), "Invalid content for mip {mip_level}");
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn read_pixels_rejects_invalid_subresources() {
+        let device = test_device();
+        let mut image = Image::new(device.ctx.clone(), rgba8_options(4, 1)).unwrap();
+        image.set_data(&BufferMemory::from_slice(&[0u8; 4 * 4 * 4])).unwrap();
+        assert!(image.read_pixels(1, 0, None).is_err());
+        assert!(image.read_pixels(0, 1, None).is_err());
+    }
+}
@@ -52,7 +52,7 @@ impl Mesh {
         if self.index_buffer.is_none() {
             self.index_buffer = Some(Buffer::from_buffer_memory(self.ctx.clone(), index_data, BufferCreateInfo {
                 usage: vk::BufferUsageFlags::INDEX_BUFFER,
-                access: BufferAccess::GpuOnly,
+                access: BufferAccess::Default,
                 buffer_type: self.mesh_type,
             })?);
         } else {
@@ -70,7 +70,7 @@ impl Mesh {
         if self.vertex_buffer.is_none() {
             self.vertex_buffer = Some(Buffer::from_buffer_memory(self.ctx.clone(), vertex_data, BufferCreateInfo {
                 usage: vk::BufferUsageFlags::VERTEX_BUFFER,
-                access: BufferAccess::GpuOnly,
+                access: BufferAccess::Default,
                 buffer_type: self.mesh_type,
             })?);
         } else {
@@ -89,7 +89,7 @@ impl Mesh {
             None => {
                 self.vertex_buffer = Some(Buffer::new(self.ctx.clone(), self.vertex_structure_size, vertex_count, BufferCreateInfo {
                     usage: vk::BufferUsageFlags::VERTEX_BUFFER,
-                    access: BufferAccess::GpuOnly,
+                    access: BufferAccess::Default,
                     buffer_type: self.mesh_type,
                 })?);
             }
@@ -105,7 +105,7 @@ impl Mesh {
             None => {
                 self.index_buffer = Some(Buffer::new(self.ctx.clone(), self.vertex_structure_size, index_count, BufferCreateInfo {
                     usage: vk::BufferUsageFlags::INDEX_BUFFER,
-                    access: BufferAccess::GpuOnly,
+                    access: BufferAccess::Default,
                     buffer_type: self.mesh_type,
                 })?);
             }
@@ -1,7 +1,10 @@
 pub mod shader_module;
 pub mod pipeline;
 pub mod buffer;
+pub mod typed_buffer;
+pub mod transient_buffer;
 pub mod mesh;
 pub mod descriptor_sets;
 pub mod image;
-pub mod sampler;
\ No newline at end of file
+pub mod sampler;
+pub mod compute_pipeline;
\ No newline at end of file
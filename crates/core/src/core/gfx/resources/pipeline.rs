@@ -1,14 +1,14 @@
+use crate::core::gfx::bindless::BINDLESS_SET;
 use crate::core::gfx::device::DeviceCtx;
+use crate::core::gfx::resources::image::format_aspects;
 use crate::core::gfx::resources::shader_module::ShaderStage;
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use vulkanalia::vk;
-use vulkanalia::vk::{DeviceV1_0, Handle, HasBuilder, ShaderStageFlags};
-use crate::core::gfx::frame_graph::renderer::RenderPassObject;
+use vulkanalia::vk::{DeviceV1_0, HasBuilder, ShaderStageFlags};
 
 pub struct Pipeline {
-    pipeline_layout: vk::PipelineLayout,
+    layout: PipelineLayout,
     pipeline: vk::Pipeline,
-    descriptor_set_layout: vk::DescriptorSetLayout,
     ctx: DeviceCtx
 }
 
@@ -20,52 +20,159 @@ pub enum AlphaMode
     Additive,
 }
 
+/// Blend equation and write mask of a colour attachment
+#[derive(Copy, Clone, Debug)]
+pub struct AttachmentBlend {
+    pub enable: bool,
+    pub src_color: vk::BlendFactor,
+    pub dst_color: vk::BlendFactor,
+    pub color_op: vk::BlendOp,
+    pub src_alpha: vk::BlendFactor,
+    pub dst_alpha: vk::BlendFactor,
+    pub alpha_op: vk::BlendOp,
+    pub write_mask: vk::ColorComponentFlags,
+}
+
+impl AttachmentBlend {
+    pub fn write_mask(mut self, write_mask: vk::ColorComponentFlags) -> Self {
+        self.write_mask = write_mask;
+        self
+    }
+}
+
+impl Default for AttachmentBlend {
+    fn default() -> Self {
+        AlphaMode::Opaque.into()
+    }
+}
+
+impl From<AlphaMode> for AttachmentBlend {
+    fn from(alpha_mode: AlphaMode) -> Self {
+        let (enable, src_color, dst_color, src_alpha, dst_alpha) = match alpha_mode {
+            AlphaMode::Opaque => { (false, vk::BlendFactor::ONE, vk::BlendFactor::ZERO, vk::BlendFactor::ONE, vk::BlendFactor::ZERO) }
+            AlphaMode::Translucent => { (true, vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA, vk::BlendFactor::ZERO) }
+            AlphaMode::Additive => { (true, vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE, vk::BlendFactor::ZERO, vk::BlendFactor::ONE) }
+        };
+        Self {
+            enable,
+            src_color,
+            dst_color,
+            color_op: vk::BlendOp::ADD,
+            src_alpha,
+            dst_alpha,
+            alpha_op: vk::BlendOp::ADD,
+            write_mask: vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B | vk::ColorComponentFlags::A,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: vk::CompareOp,
+    /// Also enabled when DEPTH_BIAS is a dynamic state, the factors then come from the command buffer
+    pub bias: Option<DepthBias>,
+    /// Discard fragments whose stored depth is outside of the bounds
+    pub bounds: Option<(f32, f32)>,
+}
+
+impl DepthState {
+    pub fn disabled() -> Self {
+        Self { test: false, write: false, ..Default::default() }
+    }
+
+    /// Test against the depth buffer without writing to it, for translucent or additive geometry
+    pub fn read_only() -> Self {
+        Self { write: false, ..Default::default() }
+    }
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare_op: vk::CompareOp::LESS,
+            bias: None,
+            bounds: None,
+        }
+    }
+}
+
+/// Stencil operations of front and back faces. References are set with `CommandBuffer::set_stencil_reference` when STENCIL_REFERENCE is dynamic.
+#[derive(Copy, Clone, Debug)]
+pub struct StencilState {
+    pub front: vk::StencilOpState,
+    pub back: vk::StencilOpState,
+}
+
 pub struct PipelineConfig {
     pub culling: vk::CullModeFlags,
     pub front_face: vk::FrontFace,
     pub topology: vk::PrimitiveTopology,
     pub polygon_mode: vk::PolygonMode,
-    pub alpha_mode: AlphaMode,
-    pub depth_test: bool,
+    /// One state per colour attachment, a single state applies to all of them
+    pub blend: Vec<AttachmentBlend>,
+    pub depth: DepthState,
+    pub stencil: Option<StencilState>,
     pub line_width: f32,
+    /// Dynamic states in addition to the viewport and scissor, which are always dynamic
+    pub dynamic_states: Vec<vk::DynamicState>,
+    /// Add the device's bindless table to the layout, at set BINDLESS_SET
+    pub bindless: bool,
 }
 
-impl Pipeline {
-    pub fn new(ctx: DeviceCtx, render_pass: &RenderPassObject, stages: Vec<ShaderStage>, config: &PipelineConfig) -> Result<Self, Error> {
-        let mut push_constant_ranges = vec![];
-        for stage in &stages {
-            if let Some(pc) = stage.infos().push_constant_size {
-                push_constant_ranges.push(vk::PushConstantRange::builder()
-                    .stage_flags(stage.infos().stage)
-                    .offset(0)
-                    .size(pc));
-            }
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            culling: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            blend: vec![AttachmentBlend::default()],
+            depth: DepthState::default(),
+            stencil: None,
+            line_width: 1.0,
+            dynamic_states: vec![],
+            bindless: false,
         }
-        
-        
-        // Layout
-        let mut bindings = Vec::<vk::DescriptorSetLayoutBinding>::new();
-        for stage in &stages {
-            for binding in &stage.infos().descriptor_bindings {
-                bindings.push(vk::DescriptorSetLayoutBinding::builder()
-                    .binding(binding.binding)
-                    .descriptor_type(binding.descriptor_type)
-                    .descriptor_count(1)
-                    .stage_flags(stage.infos().stage)
-                    .build());
-            }
-        }
-        let ci_descriptor_set_layout = vk::DescriptorSetLayoutCreateInfo::builder()
-            .bindings(bindings.as_slice())
-            .build();
-        let descriptor_set_layout = unsafe {ctx.device().create_descriptor_set_layout(&ci_descriptor_set_layout, None)}?;
+    }
+}
 
-        let set_layouts = &[descriptor_set_layout];
-        let layout_info = vk::PipelineLayoutCreateInfo::builder()
-            .set_layouts(set_layouts)
-            .push_constant_ranges(push_constant_ranges.as_slice());
+/// Attachments a pipeline draws into
+#[derive(Clone, Debug)]
+pub struct AttachmentFormats {
+    pub color_formats: Vec<vk::Format>,
+    pub depth_format: Option<vk::Format>,
+    pub sample_count: vk::SampleCountFlags,
+    /// Compatible render pass and subpass index. None when drawing with dynamic rendering.
+    pub render_pass: Option<(vk::RenderPass, u32)>,
+}
 
-        let pipeline_layout = unsafe { ctx.device().create_pipeline_layout(&layout_info, None) }?;
+// Dynamic states of VK_EXT_extended_dynamic_state, core since Vulkan 1.3
+const EXTENDED_DYNAMIC_STATES: [vk::DynamicState; 12] = [
+    vk::DynamicState::CULL_MODE, vk::DynamicState::FRONT_FACE, vk::DynamicState::PRIMITIVE_TOPOLOGY,
+    vk::DynamicState::VIEWPORT_WITH_COUNT, vk::DynamicState::SCISSOR_WITH_COUNT, vk::DynamicState::VERTEX_INPUT_BINDING_STRIDE,
+    vk::DynamicState::DEPTH_TEST_ENABLE, vk::DynamicState::DEPTH_WRITE_ENABLE, vk::DynamicState::DEPTH_COMPARE_OP,
+    vk::DynamicState::DEPTH_BOUNDS_TEST_ENABLE, vk::DynamicState::STENCIL_TEST_ENABLE, vk::DynamicState::STENCIL_OP,
+];
+// Dynamic states of VK_EXT_extended_dynamic_state2, only supported through Vulkan 1.3
+const EXTENDED_DYNAMIC_STATES_2: [vk::DynamicState; 3] = [
+    vk::DynamicState::RASTERIZER_DISCARD_ENABLE, vk::DynamicState::DEPTH_BIAS_ENABLE, vk::DynamicState::PRIMITIVE_RESTART_ENABLE,
+];
+
+impl Pipeline {
+    pub fn new(ctx: DeviceCtx, attachments: &AttachmentFormats, stages: Vec<ShaderStage>, config: &PipelineConfig) -> Result<Self, Error> {
+        Self::validate_config(&ctx, config)?;
+        let layout = PipelineLayout::new(ctx.clone(), &stages, config.bindless)?;
 
         let mut vertex_attribute_description = Vec::<vk::VertexInputAttributeDescription>::new();
 
@@ -100,17 +207,21 @@ impl Pipeline {
 
         let mut color_blend_attachment = Vec::<vk::PipelineColorBlendAttachmentState>::new();
 
-        for _ in 0..render_pass.base().color_attachments.len()
+        if config.blend.len() > 1 && config.blend.len() != attachments.color_formats.len() {
+            return Err(anyhow!("{} blend states given for {} colour attachments", config.blend.len(), attachments.color_formats.len()));
+        }
+        for index in 0..attachments.color_formats.len()
         {
+            let blend = config.blend.get(index).or(config.blend.first()).copied().unwrap_or_default();
             color_blend_attachment.push(vk::PipelineColorBlendAttachmentState::builder()
-                .blend_enable(config.alpha_mode != AlphaMode::Opaque)
-                .src_color_blend_factor(if config.alpha_mode == AlphaMode::Opaque { vk::BlendFactor::ZERO } else { vk::BlendFactor::SRC_ALPHA })
-                .dst_color_blend_factor(if config.alpha_mode == AlphaMode::Opaque { vk::BlendFactor::ZERO } else { vk::BlendFactor::ONE_MINUS_SRC_ALPHA })
-                .color_blend_op(vk::BlendOp::ADD)
-                .src_alpha_blend_factor(if config.alpha_mode == AlphaMode::Opaque { vk::BlendFactor::ONE } else { vk::BlendFactor::ONE_MINUS_SRC_ALPHA })
-                .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-                .alpha_blend_op(vk::BlendOp::ADD)
-                .color_write_mask(vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B | vk::ColorComponentFlags::A)
+                .blend_enable(blend.enable)
+                .src_color_blend_factor(blend.src_color)
+                .dst_color_blend_factor(blend.dst_color)
+                .color_blend_op(blend.color_op)
+                .src_alpha_blend_factor(blend.src_alpha)
+                .dst_alpha_blend_factor(blend.dst_alpha)
+                .alpha_blend_op(blend.alpha_op)
+                .color_write_mask(blend.write_mask)
                 .build());
         }
 
@@ -134,15 +245,15 @@ impl Pipeline {
             .polygon_mode(config.polygon_mode)
             .cull_mode(config.culling)
             .front_face(config.front_face)
-            .depth_bias_enable(false)
-            .depth_bias_constant_factor(0.0)
-            .depth_bias_clamp(0.0)
-            .depth_bias_slope_factor(0.0)
+            .depth_bias_enable(config.depth.bias.is_some() || config.dynamic_states.contains(&vk::DynamicState::DEPTH_BIAS))
+            .depth_bias_constant_factor(config.depth.bias.map_or(0.0, |bias| bias.constant_factor))
+            .depth_bias_clamp(config.depth.bias.map_or(0.0, |bias| bias.clamp))
+            .depth_bias_slope_factor(config.depth.bias.map_or(0.0, |bias| bias.slope_factor))
             .line_width(config.line_width)
             .build();
 
         let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::_1)
+            .rasterization_samples(attachments.sample_count)
             .sample_shading_enable(false)
             .min_sample_shading(1.0)
             .alpha_to_coverage_enable(false)
@@ -150,40 +261,19 @@ impl Pipeline {
             .build();
 
 
-        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
-            .depth_test_enable(config.depth_test)
-            .depth_write_enable(config.depth_test)
-            .depth_compare_op(vk::CompareOp::LESS)
-            .depth_bounds_test_enable(false)
-            .stencil_test_enable(false)
-            .min_depth_bounds(0.0)
-            .max_depth_bounds(1.0)
-            .build();
-
-        // Create descriptor sets
-        let mut bindings = Vec::<vk::DescriptorSetLayoutBinding>::new();
-        for stage in &stages {
-            for binding in &stage.infos().descriptor_bindings {
-                bindings.push(vk::DescriptorSetLayoutBinding::builder()
-                    .binding(binding.binding)
-                    .descriptor_type(binding.descriptor_type)
-                    .descriptor_count(1)
-                    .stage_flags(stage.infos().stage)
-                    .build());
-            }
-        }
-
-        // Create pipeline layout
-        let mut push_constants = Vec::<vk::PushConstantRange>::new();
-        for stage in &stages {
-            if let Some(push_constant_size) = stage.infos().push_constant_size {
-                push_constants.push(vk::PushConstantRange::builder()
-                    .stage_flags(stage.infos().stage)
-                    .offset(0)
-                    .size(push_constant_size)
-                    .build());
-            }
+        let (min_depth_bounds, max_depth_bounds) = config.depth.bounds.unwrap_or((0.0, 1.0));
+        let mut depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(config.depth.test)
+            .depth_write_enable(config.depth.write)
+            .depth_compare_op(config.depth.compare_op)
+            .depth_bounds_test_enable(config.depth.bounds.is_some())
+            .stencil_test_enable(config.stencil.is_some())
+            .min_depth_bounds(min_depth_bounds)
+            .max_depth_bounds(max_depth_bounds);
+        if let Some(stencil) = &config.stencil {
+            depth_stencil_state = depth_stencil_state.front(stencil.front).back(stencil.back);
         }
+        let depth_stencil_state = depth_stencil_state.build();
 
         // Create
         let mut stage_modules = vec![];
@@ -198,14 +288,26 @@ impl Pipeline {
         }
 
         let mut dynamic_states_array = Vec::from([vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]);
-        if config.line_width != 1.0 {
-            dynamic_states_array.push(vk::DynamicState::LINE_WIDTH);
+        for state in &config.dynamic_states {
+            if !dynamic_states_array.contains(state) {
+                dynamic_states_array.push(*state);
+            }
         }
         let dynamic_states = vk::PipelineDynamicStateCreateInfo::builder()
             .dynamic_states(dynamic_states_array.as_slice())
             .build();
 
-        let info = vk::GraphicsPipelineCreateInfo::builder()
+        // Same split as the frame graph : the depth and stencil aspects of the depth attachment
+        let depth_stencil_format = |aspect: vk::ImageAspectFlags| match attachments.depth_format {
+            Some(format) if format_aspects(format).contains(aspect) => { format }
+            _ => { vk::Format::UNDEFINED }
+        };
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+            .color_attachment_formats(attachments.color_formats.as_slice())
+            .depth_attachment_format(depth_stencil_format(vk::ImageAspectFlags::DEPTH))
+            .stencil_attachment_format(depth_stencil_format(vk::ImageAspectFlags::STENCIL));
+
+        let mut info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(stage_modules.as_slice())
             .vertex_input_state(&vertex_input_state)
             .input_assembly_state(&input_assembly_state)
@@ -215,38 +317,231 @@ impl Pipeline {
             .depth_stencil_state(&depth_stencil_state)
             .color_blend_state(&color_blend_state)
             .dynamic_state(&dynamic_states)
-            .layout(pipeline_layout)
-            .render_pass(*render_pass.ptr())
-            .subpass(0)
-            .build();
+            .layout(*layout.ptr());
+        info = match attachments.render_pass {
+            Some((render_pass, subpass)) => { info.render_pass(render_pass).subpass(subpass) }
+            None => { info.push_next(&mut rendering_info) }
+        };
+        let mut feedback = vk::PipelineCreationFeedback::default();
+        let mut stage_feedbacks = vec![vk::PipelineCreationFeedback::default(); stage_modules.len()];
+        let mut feedback_info = vk::PipelineCreationFeedbackCreateInfo::builder()
+            .pipeline_creation_feedback(&mut feedback)
+            .pipeline_stage_creation_feedbacks(stage_feedbacks.as_mut_slice());
+        if ctx.pipeline_cache().creation_feedback() {
+            info = info.push_next(&mut feedback_info);
+        }
+        let info = info.build();
+
+        let pipeline = unsafe { ctx.device().create_graphics_pipelines(ctx.pipeline_cache().ptr(), &[info], None) }?.0;
+        ctx.pipeline_cache().record(&feedback);
 
-        let pipeline = unsafe { ctx.device().create_graphics_pipelines(vk::PipelineCache::null(), &[info], None) }?.0;
-        
         Ok(Self {
-            pipeline_layout,
+            layout,
             pipeline: pipeline[0],
-            descriptor_set_layout,
             ctx,
         })
     }
 
+    /// Reject the states relying on features or extensions the device doesn't provide
+    fn validate_config(ctx: &DeviceCtx, config: &PipelineConfig) -> Result<(), Error> {
+        let features = ctx.features();
+        if (config.depth.bounds.is_some() || config.dynamic_states.contains(&vk::DynamicState::DEPTH_BOUNDS)) && features.depth_bounds != vk::TRUE {
+            return Err(anyhow!("Depth bounds test requires the depthBounds feature, which is not supported by this device"));
+        }
+        if config.line_width != 1.0 && features.wide_lines != vk::TRUE {
+            return Err(anyhow!("Line width {} requires the wideLines feature, which is not supported by this device", config.line_width));
+        }
+        for state in &config.dynamic_states {
+            if EXTENDED_DYNAMIC_STATES.contains(state) && !ctx.extended_dynamic_state() {
+                return Err(anyhow!("Dynamic state {:?} requires Vulkan 1.3 or VK_EXT_extended_dynamic_state", state));
+            }
+            if EXTENDED_DYNAMIC_STATES_2.contains(state) && ctx.physical_device().properties().api_version < vk::make_version(1, 3, 0) {
+                return Err(anyhow!("Dynamic state {:?} requires Vulkan 1.3", state));
+            }
+        }
+        Ok(())
+    }
+
     pub fn ptr_pipeline(&self) -> &vk::Pipeline {
         &self.pipeline
     }
 
-    pub fn descriptor_set_layout(&self) -> &vk::DescriptorSetLayout {
-        &self.descriptor_set_layout
+    pub fn descriptor_set_layout(&self, set: u32) -> &vk::DescriptorSetLayout {
+        self.layout.descriptor_set_layout(set)
+    }
+
+    pub fn descriptor_set_count(&self) -> u32 {
+        self.layout.descriptor_set_count()
+    }
+
+    /// Number of descriptors of each type in the set layout
+    pub fn descriptor_counts(&self, set: u32) -> &[vk::DescriptorPoolSize] {
+        self.layout.descriptor_counts(set)
     }
 
     pub fn ptr_pipeline_layout(&self) -> &vk::PipelineLayout {
-        &self.pipeline_layout
+        self.layout.ptr()
+    }
+}
+
+impl PipelineObject for Pipeline {
+    fn ptr_pipeline(&self) -> &vk::Pipeline {
+        &self.pipeline
+    }
+
+    fn layout(&self) -> &PipelineLayout {
+        &self.layout
+    }
+
+    fn bind_point(&self) -> vk::PipelineBindPoint {
+        vk::PipelineBindPoint::GRAPHICS
     }
 }
 
 impl Drop for Pipeline {
     fn drop(&mut self) {
-        unsafe { self.ctx.device().destroy_pipeline_layout(self.pipeline_layout, None); }
-        unsafe { self.ctx.device().destroy_descriptor_set_layout(self.descriptor_set_layout, None); }
         unsafe { self.ctx.device().destroy_pipeline(self.pipeline, None); }
     }
+}
+
+/// Graphics or compute pipeline, as bound by command buffers and descriptor sets
+pub trait PipelineObject {
+    fn ptr_pipeline(&self) -> &vk::Pipeline;
+    fn layout(&self) -> &PipelineLayout;
+    fn bind_point(&self) -> vk::PipelineBindPoint;
+}
+
+/// Descriptor set layouts and push constant ranges declared by the stages of a pipeline
+pub struct PipelineLayout {
+    pipeline_layout: vk::PipelineLayout,
+    // One layout per set index, gaps are filled with empty layouts
+    descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    descriptor_counts: Vec<Vec<vk::DescriptorPoolSize>>,
+    bindless: bool,
+    ctx: DeviceCtx,
+}
+
+impl PipelineLayout {
+    pub fn new(ctx: DeviceCtx, stages: &[ShaderStage], bindless: bool) -> Result<Self, Error> {
+        let mut push_constant_ranges = vec![];
+        for stage in stages {
+            if let Some(pc) = stage.infos().push_constant_size {
+                push_constant_ranges.push(vk::PushConstantRange::builder()
+                    .stage_flags(stage.infos().stage)
+                    .offset(0)
+                    .size(pc));
+            }
+        }
+
+        for (index, stage) in stages.iter().enumerate() {
+            if stages[..index].iter().any(|other| other.infos().stage.intersects(stage.infos().stage)) {
+                return Err(anyhow!("Stage {:?} is given more than once", stage.infos().stage));
+            }
+        }
+
+        // Layouts : a binding declared by several stages is visible from all of them
+        let mut set_bindings = Vec::<Vec<vk::DescriptorSetLayoutBinding>>::new();
+        for stage in stages {
+            for binding in &stage.infos().descriptor_bindings {
+                if bindless && binding.set == BINDLESS_SET {
+                    // Declarations of the table itself are replaced by its layout
+                    if !matches!((binding.binding, binding.descriptor_type), (0, vk::DescriptorType::SAMPLED_IMAGE) | (1, vk::DescriptorType::SAMPLER)) {
+                        return Err(anyhow!("Set {} is reserved for the bindless table", BINDLESS_SET));
+                    }
+                    continue;
+                }
+                if binding.count == 0 {
+                    return Err(anyhow!("Binding {} of set {} is a runtime sized array, which is only supported by the bindless table", binding.binding, binding.set));
+                }
+                if set_bindings.len() <= binding.set as usize {
+                    set_bindings.resize(binding.set as usize + 1, vec![]);
+                }
+                let bindings = &mut set_bindings[binding.set as usize];
+                match bindings.iter_mut().find(|existing| existing.binding == binding.binding) {
+                    None => {
+                        bindings.push(vk::DescriptorSetLayoutBinding::builder()
+                            .binding(binding.binding)
+                            .descriptor_type(binding.descriptor_type)
+                            .descriptor_count(binding.count)
+                            .stage_flags(stage.infos().stage)
+                            .build());
+                    }
+                    Some(existing) => {
+                        if existing.descriptor_type != binding.descriptor_type || existing.descriptor_count != binding.count {
+                            return Err(anyhow!("Binding {} of set {} is declared with different types or counts across stages", binding.binding, binding.set));
+                        }
+                        existing.stage_flags |= stage.infos().stage;
+                    }
+                }
+            }
+        }
+        if bindless && set_bindings.len() <= BINDLESS_SET as usize {
+            set_bindings.resize(BINDLESS_SET as usize + 1, vec![]);
+        }
+
+        let mut descriptor_set_layouts = vec![];
+        let mut descriptor_counts = vec![];
+        for (set, bindings) in set_bindings.iter().enumerate() {
+            if bindless && set as u32 == BINDLESS_SET {
+                descriptor_set_layouts.push(*ctx.bindless().ok_or(anyhow!("Bindless table is not available on this device"))?.layout());
+                descriptor_counts.push(vec![]);
+                continue;
+            }
+            let mut counts = Vec::<vk::DescriptorPoolSize>::new();
+            for binding in bindings {
+                match counts.iter_mut().find(|size| size.type_ == binding.descriptor_type) {
+                    None => { counts.push(vk::DescriptorPoolSize { type_: binding.descriptor_type, descriptor_count: binding.descriptor_count }) }
+                    Some(size) => { size.descriptor_count += binding.descriptor_count }
+                }
+            }
+            let ci_descriptor_set_layout = vk::DescriptorSetLayoutCreateInfo::builder()
+                .bindings(bindings.as_slice())
+                .build();
+            descriptor_set_layouts.push(unsafe { ctx.device().create_descriptor_set_layout(&ci_descriptor_set_layout, None) }?);
+            descriptor_counts.push(counts);
+        }
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(descriptor_set_layouts.as_slice())
+            .push_constant_ranges(push_constant_ranges.as_slice());
+
+        let pipeline_layout = unsafe { ctx.device().create_pipeline_layout(&layout_info, None) }?;
+
+        Ok(Self {
+            pipeline_layout,
+            descriptor_set_layouts,
+            descriptor_counts,
+            bindless,
+            ctx,
+        })
+    }
+
+    pub fn ptr(&self) -> &vk::PipelineLayout {
+        &self.pipeline_layout
+    }
+
+    pub fn descriptor_set_layout(&self, set: u32) -> &vk::DescriptorSetLayout {
+        &self.descriptor_set_layouts[set as usize]
+    }
+
+    pub fn descriptor_set_count(&self) -> u32 {
+        self.descriptor_set_layouts.len() as u32
+    }
+
+    /// Number of descriptors of each type in the set layout
+    pub fn descriptor_counts(&self, set: u32) -> &[vk::DescriptorPoolSize] {
+        &self.descriptor_counts[set as usize]
+    }
+}
+
+impl Drop for PipelineLayout {
+    fn drop(&mut self) {
+        unsafe { self.ctx.device().destroy_pipeline_layout(self.pipeline_layout, None); }
+        for (set, layout) in self.descriptor_set_layouts.iter().enumerate() {
+            // The bindless layout is owned by the device
+            if !(self.bindless && set as u32 == BINDLESS_SET) {
+                unsafe { self.ctx.device().destroy_descriptor_set_layout(*layout, None); }
+            }
+        }
+    }
 }
\ No newline at end of file
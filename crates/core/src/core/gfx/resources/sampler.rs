@@ -1,30 +1,192 @@
 use crate::core::gfx::device::DeviceCtx;
 use anyhow::Error;
+use std::hash::{Hash, Hasher};
 use vulkanalia::vk;
 use vulkanalia::vk::{DeviceV1_0, HasBuilder};
 
+/// Sampler configuration. Identical descriptions share the same sampler through [`crate::core::gfx::device::Device::sampler`].
+#[derive(Copy, Clone, Debug)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    /// Ignored when the device doesn't support anisotropic filtering, clamped to the device limit otherwise
+    pub max_anisotropy: Option<f32>,
+    pub mip_lod_bias: f32,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    /// Depth comparison, for shadow maps
+    pub compare_op: Option<vk::CompareOp>,
+    pub border_color: vk::BorderColor,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            compare_op: None,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        }
+    }
+}
+
+impl SamplerDesc {
+    /// Nearest texel, without mip interpolation
+    pub fn nearest() -> Self {
+        Self::default()
+            .filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+    }
+
+    /// Hardware PCF on a depth map, everything outside of the map is lit
+    pub fn shadow() -> Self {
+        Self::default()
+            .address_mode(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+    }
+
+    pub fn filter(mut self, filter: vk::Filter) -> Self {
+        self.mag_filter = filter;
+        self.min_filter = filter;
+        self
+    }
+
+    pub fn mag_filter(mut self, filter: vk::Filter) -> Self {
+        self.mag_filter = filter;
+        self
+    }
+
+    pub fn min_filter(mut self, filter: vk::Filter) -> Self {
+        self.min_filter = filter;
+        self
+    }
+
+    pub fn mipmap_mode(mut self, mipmap_mode: vk::SamplerMipmapMode) -> Self {
+        self.mipmap_mode = mipmap_mode;
+        self
+    }
+
+    /// Same address mode on every axis
+    pub fn address_mode(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self.address_mode_w = address_mode;
+        self
+    }
+
+    pub fn address_modes(mut self, u: vk::SamplerAddressMode, v: vk::SamplerAddressMode, w: vk::SamplerAddressMode) -> Self {
+        self.address_mode_u = u;
+        self.address_mode_v = v;
+        self.address_mode_w = w;
+        self
+    }
+
+    pub fn anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.max_anisotropy = Some(max_anisotropy);
+        self
+    }
+
+    pub fn lod(mut self, min_lod: f32, max_lod: f32, mip_lod_bias: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+        self.mip_lod_bias = mip_lod_bias;
+        self
+    }
+
+    pub fn compare_op(mut self, compare_op: vk::CompareOp) -> Self {
+        self.compare_op = Some(compare_op);
+        self
+    }
+
+    pub fn border_color(mut self, border_color: vk::BorderColor) -> Self {
+        self.border_color = border_color;
+        self
+    }
+
+    // Floats are compared by their bits, so that the description can be used as a key
+    fn key(&self) -> impl Eq + Hash {
+        (
+            (self.mag_filter, self.min_filter, self.mipmap_mode),
+            (self.address_mode_u, self.address_mode_v, self.address_mode_w),
+            self.max_anisotropy.map(f32::to_bits),
+            (self.mip_lod_bias.to_bits(), self.min_lod.to_bits(), self.max_lod.to_bits()),
+            self.compare_op,
+            self.border_color,
+        )
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
 pub struct Sampler {
     sampler: vk::Sampler,
+    desc: SamplerDesc,
     ctx: DeviceCtx,
 }
 
 impl Sampler {
-    pub fn new(ctx: DeviceCtx) -> Result<Self, Error> {
+    pub fn new(ctx: DeviceCtx, desc: &SamplerDesc) -> Result<Self, Error> {
+        let max_anisotropy = desc.max_anisotropy
+            .filter(|_| ctx.sampler_anisotropy())
+            .map(|anisotropy| anisotropy.clamp(1.0, ctx.physical_device().properties().limits.max_sampler_anisotropy));
         let create_infos = vk::SamplerCreateInfo::builder()
+            .mag_filter(desc.mag_filter)
+            .min_filter(desc.min_filter)
+            .mipmap_mode(desc.mipmap_mode)
+            .address_mode_u(desc.address_mode_u)
+            .address_mode_v(desc.address_mode_v)
+            .address_mode_w(desc.address_mode_w)
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+            .mip_lod_bias(desc.mip_lod_bias)
+            .min_lod(desc.min_lod)
+            .max_lod(desc.max_lod)
+            .compare_enable(desc.compare_op.is_some())
+            .compare_op(desc.compare_op.unwrap_or(vk::CompareOp::ALWAYS))
+            .border_color(desc.border_color)
             .build();
 
         let sampler = unsafe { ctx.device().create_sampler(&create_infos, None) }?;
 
-        Ok(Self { sampler, ctx })
+        Ok(Self { sampler, desc: *desc, ctx })
     }
 
     pub fn ptr(&self) -> &vk::Sampler {
         &self.sampler
     }
+
+    pub fn desc(&self) -> &SamplerDesc {
+        &self.desc
+    }
 }
 
 impl Drop for Sampler {
     fn drop(&mut self) {
         unsafe { self.ctx.device().destroy_sampler(self.sampler, None); }
     }
-}
\ No newline at end of file
+}
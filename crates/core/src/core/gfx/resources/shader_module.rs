@@ -1,5 +1,7 @@
 use std::ops::Deref;
-use anyhow::Error;
+use anyhow::{anyhow, Error};
+use shaders::compiler::SpirV;
+use shaders::reflection::{DescriptorKind, ScalarType};
 use vulkanalia::bytecode::Bytecode;
 use vulkanalia::vk;
 use vulkanalia::vk::{DeviceV1_0, HasBuilder};
@@ -7,8 +9,11 @@ use crate::core::gfx::device::DeviceCtx;
 
 #[derive(Debug)]
 pub struct ShaderStageBindings {
+    pub set: u32,
     pub binding: u32,
     pub descriptor_type: vk::DescriptorType,
+    /// Array size, 0 for runtime sized arrays which are only allowed in the bindless table
+    pub count: u32,
 }
 
 #[derive(Debug)]
@@ -25,7 +30,80 @@ pub struct ShaderStageInfos {
     pub push_constant_size: Option<u32>,
     pub stage_input: Vec<ShaderStageInputs>,
     pub stage: vk::ShaderStageFlags,
-    pub entry_point: String
+    pub entry_point: String,
+    /// Local size of compute shaders
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+impl ShaderStageInfos {
+    /// Layout reflected from the SPIR-V. Fields can still be overridden before creating the stage,
+    /// for example to read packed vertex attributes.
+    pub fn from_spirv(spirv: &SpirV, entry_point: &str) -> Result<Self, Error> {
+        let reflection = spirv.reflect()?;
+        let reflected_entry_point = reflection.entry_point(entry_point).ok_or(anyhow!("Entry point {} not found in SPIR-V", entry_point))?;
+
+        let descriptor_bindings = reflection.bindings.iter().map(|binding| ShaderStageBindings {
+            set: binding.set,
+            binding: binding.binding,
+            descriptor_type: match binding.kind {
+                DescriptorKind::Sampler => { vk::DescriptorType::SAMPLER }
+                DescriptorKind::SampledImage => { vk::DescriptorType::SAMPLED_IMAGE }
+                DescriptorKind::CombinedImageSampler => { vk::DescriptorType::COMBINED_IMAGE_SAMPLER }
+                DescriptorKind::StorageImage => { vk::DescriptorType::STORAGE_IMAGE }
+                DescriptorKind::UniformTexelBuffer => { vk::DescriptorType::UNIFORM_TEXEL_BUFFER }
+                DescriptorKind::StorageTexelBuffer => { vk::DescriptorType::STORAGE_TEXEL_BUFFER }
+                DescriptorKind::UniformBuffer => { vk::DescriptorType::UNIFORM_BUFFER }
+                DescriptorKind::StorageBuffer => { vk::DescriptorType::STORAGE_BUFFER }
+                DescriptorKind::InputAttachment => { vk::DescriptorType::INPUT_ATTACHMENT }
+                DescriptorKind::AccelerationStructure => { vk::DescriptorType::ACCELERATION_STRUCTURE_KHR }
+            },
+            count: binding.count,
+        }).collect();
+
+        let mut stage_input = vec![];
+        let mut offset = 0;
+        for input in &reflected_entry_point.inputs {
+            let input_size = input.width / 8 * input.components;
+            stage_input.push(ShaderStageInputs {
+                location: input.location,
+                offset,
+                input_size,
+                property_type: input_format(input.scalar, input.width, input.components)
+                    .ok_or(anyhow!("Unsupported vertex input {} ({:?}{}x{})", input.name, input.scalar, input.width, input.components))?,
+            });
+            offset += input_size;
+        }
+
+        Ok(Self {
+            descriptor_bindings,
+            push_constant_size: reflection.push_constant_size,
+            stage_input,
+            stage: match reflected_entry_point.stage {
+                shaders::compiler::ShaderStage::VERTEX => { vk::ShaderStageFlags::VERTEX }
+                shaders::compiler::ShaderStage::HULL => { vk::ShaderStageFlags::TESSELLATION_CONTROL }
+                shaders::compiler::ShaderStage::DOMAIN => { vk::ShaderStageFlags::TESSELLATION_EVALUATION }
+                shaders::compiler::ShaderStage::GEOMETRY => { vk::ShaderStageFlags::GEOMETRY }
+                shaders::compiler::ShaderStage::PIXEL => { vk::ShaderStageFlags::FRAGMENT }
+                shaders::compiler::ShaderStage::COMPUTE => { vk::ShaderStageFlags::COMPUTE }
+            },
+            entry_point: entry_point.to_string(),
+            workgroup_size: reflected_entry_point.workgroup_size,
+        })
+    }
+}
+
+fn input_format(scalar: ScalarType, width: u32, components: u32) -> Option<vk::Format> {
+    let formats = match (scalar, width) {
+        (ScalarType::Float, 16) => { [vk::Format::R16_SFLOAT, vk::Format::R16G16_SFLOAT, vk::Format::R16G16B16_SFLOAT, vk::Format::R16G16B16A16_SFLOAT] }
+        (ScalarType::Float, 32) => { [vk::Format::R32_SFLOAT, vk::Format::R32G32_SFLOAT, vk::Format::R32G32B32_SFLOAT, vk::Format::R32G32B32A32_SFLOAT] }
+        (ScalarType::Float, 64) => { [vk::Format::R64_SFLOAT, vk::Format::R64G64_SFLOAT, vk::Format::R64G64B64_SFLOAT, vk::Format::R64G64B64A64_SFLOAT] }
+        (ScalarType::Int, 16) => { [vk::Format::R16_SINT, vk::Format::R16G16_SINT, vk::Format::R16G16B16_SINT, vk::Format::R16G16B16A16_SINT] }
+        (ScalarType::Int, 32) => { [vk::Format::R32_SINT, vk::Format::R32G32_SINT, vk::Format::R32G32B32_SINT, vk::Format::R32G32B32A32_SINT] }
+        (ScalarType::UInt, 16) => { [vk::Format::R16_UINT, vk::Format::R16G16_UINT, vk::Format::R16G16B16_UINT, vk::Format::R16G16B16A16_UINT] }
+        (ScalarType::UInt, 32) | (ScalarType::Bool, 32) => { [vk::Format::R32_UINT, vk::Format::R32G32_UINT, vk::Format::R32G32B32_UINT, vk::Format::R32G32B32A32_UINT] }
+        _ => { return None }
+    };
+    formats.get(components.checked_sub(1)? as usize).copied()
 }
 
 
@@ -0,0 +1,97 @@
+use crate::core::gfx::device::DeviceCtx;
+use crate::core::gfx::resources::buffer::{Buffer, BufferAccess, BufferCreateInfo, BufferType};
+use crate::core::gfx::resources::descriptor_sets::ShaderInstanceBinding;
+use anyhow::{anyhow, Error};
+use bytemuck::Pod;
+use std::sync::Mutex;
+use vulkanalia::vk;
+
+/// Range written in the transient buffer for the current frame
+#[derive(Copy, Clone, Debug)]
+pub struct TransientAllocation {
+    /// To be passed as a dynamic offset when binding the descriptors
+    pub offset: u32,
+    pub size: usize,
+}
+
+struct TransientBufferState {
+    buffer: Buffer,
+    // Next free byte of each frame's region
+    heads: Vec<usize>,
+}
+
+/// Linear allocator for uniform and storage data living for a single frame.
+/// Each frame in flight owns a region of one host visible buffer, which is reset once the frame's fence signaled.
+/// The buffer is bound once with a dynamic descriptor, then each draw selects its data with a dynamic offset.
+pub struct TransientBuffer {
+    state: Mutex<TransientBufferState>,
+    frame_size: usize,
+    alignment: usize,
+    ctx: DeviceCtx,
+}
+
+impl TransientBuffer {
+    pub fn new(ctx: DeviceCtx, frame_size: usize) -> Result<Self, Error> {
+        let frames = ctx.instance().engine().params().rendering.image_count;
+        let limits = &ctx.physical_device().properties().limits;
+        let alignment = (limits.min_uniform_buffer_offset_alignment as usize).max(limits.min_storage_buffer_offset_alignment as usize);
+        let frame_size = frame_size.next_multiple_of(alignment);
+        let buffer = Buffer::new(ctx.clone(), 1, frame_size * frames, BufferCreateInfo {
+            usage: vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            access: BufferAccess::CpuToGpu,
+            buffer_type: BufferType::Static,
+        })?;
+        Ok(Self {
+            state: Mutex::new(TransientBufferState { buffer, heads: vec![0; frames] }),
+            frame_size,
+            alignment,
+            ctx,
+        })
+    }
+
+    /// Release every allocation of the frame. Its fence should have been signaled.
+    pub fn reset(&self, frame: usize) {
+        self.state.lock().unwrap().heads[frame] = 0;
+    }
+
+    pub fn allocate<T: Pod>(&self, value: &T) -> Result<TransientAllocation, Error> {
+        self.allocate_slice(std::slice::from_ref(value))
+    }
+
+    pub fn allocate_slice<T: Pod>(&self, values: &[T]) -> Result<TransientAllocation, Error> {
+        let size = size_of_val(values);
+        let frame = self.ctx.instance().engine().current_frame();
+        let mut state = self.state.lock().unwrap();
+        let head = state.heads[frame];
+        if head + size > self.frame_size {
+            return Err(anyhow!("Transient buffer is full : {} bytes per frame, {} requested", self.frame_size, head + size));
+        }
+        let offset = frame * self.frame_size + head;
+        state.buffer.write_slice(offset, values)?;
+        state.heads[frame] = (head + size).next_multiple_of(self.alignment);
+        Ok(TransientAllocation { offset: offset as u32, size })
+    }
+
+    /// Dynamic uniform buffer binding, exposing `range` bytes after each dynamic offset
+    pub fn uniform_binding(&self, range: usize) -> Result<ShaderInstanceBinding, Error> {
+        Ok(ShaderInstanceBinding::UniformBufferDynamic(self.ptr()?, 0, range as vk::DeviceSize))
+    }
+
+    /// Dynamic storage buffer binding, exposing `range` bytes after each dynamic offset
+    pub fn storage_binding(&self, range: usize) -> Result<ShaderInstanceBinding, Error> {
+        Ok(ShaderInstanceBinding::StorageBufferDynamic(self.ptr()?, 0, range as vk::DeviceSize))
+    }
+
+    pub fn ptr(&self) -> Result<vk::Buffer, Error> {
+        Ok(*self.state.lock().unwrap().buffer.ptr()?)
+    }
+
+    /// Bytes allocated for the given frame
+    pub fn used(&self, frame: usize) -> usize {
+        self.state.lock().unwrap().heads[frame]
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+}
@@ -0,0 +1,151 @@
+use crate::core::gfx::device::DeviceCtx;
+use crate::core::gfx::resources::buffer::{Buffer, BufferAccess, BufferCreateInfo, BufferType};
+use crate::core::gfx::resources::descriptor_sets::ShaderInstanceBinding;
+use anyhow::{anyhow, Error};
+use bytemuck::Pod;
+use std::marker::PhantomData;
+use vulkanalia::vk;
+
+/// Buffer of T. Elements of uniform buffers are padded to the device's minimal offset alignment, so any element can be bound with an offset.
+/// Storage buffers are tightly packed to match std430 arrays, unless created with [`TypedBuffer::new_per_element`].
+pub struct TypedBuffer<T: Pod> {
+    buffer: Buffer,
+    ctx: DeviceCtx,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Pod> TypedBuffer<T> {
+    pub fn new(ctx: DeviceCtx, elements: usize, create_infos: BufferCreateInfo) -> Result<Self, Error> {
+        Self::with_stride(ctx, elements, create_infos, false)
+    }
+
+    /// Storage buffer elements are also padded, so each one can be bound individually with a dynamic offset
+    pub fn new_per_element(ctx: DeviceCtx, elements: usize, create_infos: BufferCreateInfo) -> Result<Self, Error> {
+        Self::with_stride(ctx, elements, create_infos, true)
+    }
+
+    fn with_stride(ctx: DeviceCtx, elements: usize, create_infos: BufferCreateInfo, per_element: bool) -> Result<Self, Error> {
+        let stride = aligned_stride::<T>(&ctx, create_infos.usage, per_element);
+        Ok(Self {
+            buffer: Buffer::new(ctx.clone(), stride, elements, create_infos)?,
+            ctx,
+            _phantom: Default::default(),
+        })
+    }
+
+    pub fn from_slice(ctx: DeviceCtx, data: &[T], create_infos: BufferCreateInfo) -> Result<Self, Error> {
+        let mut buffer = Self::new(ctx, data.len(), create_infos)?;
+        buffer.set_slice(0, data)?;
+        Ok(buffer)
+    }
+
+    pub fn uniform(ctx: DeviceCtx, elements: usize, buffer_type: BufferType) -> Result<Self, Error> {
+        Self::new(ctx, elements, BufferCreateInfo { usage: vk::BufferUsageFlags::UNIFORM_BUFFER, access: BufferAccess::Default, buffer_type })
+    }
+
+    pub fn storage(ctx: DeviceCtx, elements: usize, buffer_type: BufferType) -> Result<Self, Error> {
+        Self::new(ctx, elements, BufferCreateInfo { usage: vk::BufferUsageFlags::STORAGE_BUFFER, access: BufferAccess::Default, buffer_type })
+    }
+
+    pub fn set(&mut self, index: usize, value: &T) -> Result<(), Error> {
+        self.check_range(index, 1)?;
+        self.buffer.write_at(index, value)
+    }
+
+    pub fn set_slice(&mut self, start_index: usize, values: &[T]) -> Result<(), Error> {
+        self.check_range(start_index, values.len())?;
+        if self.stride() == size_of::<T>() || values.is_empty() {
+            return self.buffer.write_slice(self.offset(start_index), values);
+        }
+        // Upload every padded element at once
+        let mut data = vec![0u8; (values.len() - 1) * self.stride() + size_of::<T>()];
+        for (i, value) in values.iter().enumerate() {
+            data[i * self.stride()..i * self.stride() + size_of::<T>()].copy_from_slice(bytemuck::bytes_of(value));
+        }
+        self.buffer.write_slice(self.offset(start_index), data.as_slice())
+    }
+
+    /// Dynamic buffers are read from their CPU copy, other buffers are read back from the GPU.
+    /// A GPU readback submits a copy and waits for it, with a staging buffer for device local memory : use [`TypedBuffer::get_slice`] to read several elements.
+    pub fn get(&self, index: usize) -> Result<T, Error> {
+        Ok(self.get_slice(index, 1)?[0])
+    }
+
+    /// Read `count` elements with a single readback
+    pub fn get_slice(&self, start_index: usize, count: usize) -> Result<Vec<T>, Error> {
+        self.check_range(start_index, count)?;
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        let range = self.offset(start_index)..self.offset(start_index) + (count - 1) * self.stride() + size_of::<T>();
+        let read_elements = |data: &[u8]| (0..count).map(|i| bytemuck::pod_read_unaligned(&data[i * self.stride()..i * self.stride() + size_of::<T>()])).collect();
+        match self.buffer.cpu_copy() {
+            Some(data) => { Ok(read_elements(&data[range])) }
+            None => {
+                let data = self.buffer.read(range.start, range.len())?;
+                Ok(read_elements(data.as_slice()))
+            }
+        }
+    }
+
+    pub fn resize(&mut self, elements: usize) -> Result<(), Error> {
+        self.buffer.resize(elements)
+    }
+
+    /// Offset of an element in bytes, to be used as a dynamic offset
+    pub fn offset(&self, index: usize) -> usize {
+        index * self.stride()
+    }
+
+    /// Binding of a single element of a uniform or storage buffer
+    pub fn binding(&self, index: usize) -> Result<ShaderInstanceBinding, Error> {
+        self.check_range(index, 1)?;
+        let buffer = *self.buffer.ptr()?;
+        let offset = self.offset(index) as vk::DeviceSize;
+        let range = size_of::<T>() as vk::DeviceSize;
+        if self.buffer.usage().contains(vk::BufferUsageFlags::UNIFORM_BUFFER) {
+            Ok(ShaderInstanceBinding::UniformBuffer(buffer, offset, range))
+        } else if self.buffer.usage().contains(vk::BufferUsageFlags::STORAGE_BUFFER) {
+            let alignment = self.ctx.physical_device().properties().limits.min_storage_buffer_offset_alignment;
+            if !offset.is_multiple_of(alignment) {
+                return Err(anyhow!("Element {index} is not aligned to {alignment} bytes : create the buffer with new_per_element to bind its elements individually"));
+            }
+            Ok(ShaderInstanceBinding::StorageBuffer(buffer, offset, range))
+        } else {
+            Err(anyhow!("Buffer is neither a uniform nor a storage buffer"))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.elements()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.buffer.elements() == 0
+    }
+    pub fn stride(&self) -> usize {
+        self.buffer.stride()
+    }
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn check_range(&self, start_index: usize, count: usize) -> Result<(), Error> {
+        if start_index + count > self.len() {
+            return Err(anyhow!("Out of bounds access : elements={}, expected={}", self.len(), start_index + count));
+        }
+        Ok(())
+    }
+}
+
+/// Size of T rounded up to the minimal offset alignment of the uniform usage, and of the storage usage when elements are bound individually
+pub fn aligned_stride<T>(ctx: &DeviceCtx, usage: vk::BufferUsageFlags, per_element: bool) -> usize {
+    let limits = &ctx.physical_device().properties().limits;
+    let mut alignment = 1;
+    if usage.contains(vk::BufferUsageFlags::UNIFORM_BUFFER) {
+        alignment = alignment.max(limits.min_uniform_buffer_offset_alignment as usize);
+    }
+    if per_element && usage.contains(vk::BufferUsageFlags::STORAGE_BUFFER) {
+        alignment = alignment.max(limits.min_storage_buffer_offset_alignment as usize);
+    }
+    size_of::<T>().next_multiple_of(alignment)
+}
@@ -3,6 +3,7 @@ use crate::core::gfx::frame_graph::frame_graph_definition::Renderer;
 use crate::core::gfx::frame_graph::renderer::{FrameGraphTargetInstance, RendererInstance};
 use crate::core::gfx::physical_device::SwapchainSupport;
 use crate::core::gfx::queues::QueueFlag;
+use crate::core::gfx::resources::transient_buffer::TransientBuffer;
 use crate::core::window::WindowCtx;
 use anyhow::{anyhow, Error};
 use types::resource_handle::{Resource, ResourceHandle};
@@ -35,6 +36,9 @@ pub struct Swapchain {
 
     renderer: Resource<RendererInstance>,
 
+    // Per-frame uniform and storage data
+    transient_buffer: TransientBuffer,
+
     device: DeviceCtx,
     window: WindowCtx,
 
@@ -54,6 +58,7 @@ impl Swapchain {
             window_ctx.surface().ptr(),
             *device.physical_device().ptr())?;
         let surface_format = get_swapchain_surface_format(&swapchain_support);
+        let transient_buffer = TransientBuffer::new(device.clone(), device.instance().engine().params().rendering.transient_buffer_size)?;
 
         let mut swapchain = Resource::new(Self {
             swapchain: None,
@@ -62,6 +67,7 @@ impl Swapchain {
             image_available_semaphores: vec![],
             in_flight_fences: vec![],
             renderer: Resource::default(),
+            transient_buffer,
             device,
             window: window_ctx,
             surface_format: surface_format.format,
@@ -71,9 +77,11 @@ impl Swapchain {
         Ok(swapchain)
     }
 
-    pub fn set_renderer(&mut self, renderer: Renderer) {
-        self.create_or_recreate_swapchain().unwrap();
-        self.renderer = RendererInstance::new(self.device.clone(), renderer, FrameGraphTargetInstance::Swapchain(self.self_ctx.clone()));
+    /// The current renderer is kept if the new one can't be created
+    pub fn set_renderer(&mut self, renderer: Renderer) -> Result<(), Error> {
+        self.create_or_recreate_swapchain()?;
+        self.renderer = RendererInstance::new(self.device.clone(), renderer, FrameGraphTargetInstance::Swapchain(self.self_ctx.clone()))?;
+        Ok(())
     }
 
     pub fn create_or_recreate_swapchain(&mut self) -> Result<(), Error> {
@@ -185,6 +193,10 @@ impl Swapchain {
         &self.swapchain_image_views
     }
 
+    pub fn get_swapchain_image_handles(&self) -> &Vec<Image> {
+        &self.swapchain_images
+    }
+
     fn destroy_swapchain(&mut self) -> Result<(), Error> {
         self.device.wait_idle();
         unsafe {
@@ -235,6 +247,7 @@ impl Swapchain {
         self.in_flight_fences[current_frame].wait();
 
         self.device.free_resources_for_window(self.window.id()?, current_frame);
+        self.transient_buffer.reset(current_frame);
 
         let result = unsafe { device_vulkan.acquire_next_image_khr(swapchain, u64::MAX, *self.image_available_semaphores[current_frame], vk::Fence::null()) };
         let image_index = match result {
@@ -276,11 +289,17 @@ impl Swapchain {
         &self.window
     }
     pub fn format(&self) -> vk::Format { self.surface_format }
+    pub fn transient_buffer(&self) -> &TransientBuffer {
+        &self.transient_buffer
+    }
 }
 
 impl Drop for Swapchain {
     fn drop(&mut self) {
         self.destroy_swapchain().unwrap();
+        if let Ok(window) = self.window.id() {
+            self.device.release_window(window);
+        }
         unsafe {
             self.image_available_semaphores
                 .iter()
@@ -5,16 +5,15 @@ use crate::core::gfx::resources::buffer::{BufferMemory, BufferType};
 use crate::core::gfx::resources::descriptor_sets::{DescriptorSets, ShaderInstanceBinding};
 use crate::core::gfx::resources::image::{Image, ImageCreateOptions};
 use crate::core::gfx::resources::mesh::Mesh;
-use crate::core::gfx::resources::pipeline::AlphaMode;
-use crate::core::gfx::resources::pipeline::{Pipeline, PipelineConfig};
-use crate::core::gfx::resources::sampler::Sampler;
-use crate::core::gfx::resources::shader_module::{ShaderStage, ShaderStageBindings, ShaderStageInfos, ShaderStageInputs};
+use crate::core::gfx::resources::pipeline::{AlphaMode, Pipeline, PipelineConfig};
+use crate::core::gfx::resources::sampler::SamplerDesc;
+use crate::core::gfx::resources::shader_module::{ShaderStage, ShaderStageInfos};
 use crate::core::gfx::ui::context::{ImGuiContext, SuspendedContext};
 use crate::core::gfx::ui::ui::Ui;
 use crate::core::window::WindowCtx;
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use imgui::sys::{igGetIO, igGetMainViewport, igGetStyle, igStyleColorsDark, ImDrawIdx, ImDrawVert, ImFontAtlas_GetTexDataAsRGBA32, ImGuiBackendFlags_HasMouseCursors, ImGuiBackendFlags_HasSetMousePos, ImGuiBackendFlags_PlatformHasViewports, ImGuiConfigFlags_DockingEnable, ImGuiConfigFlags_NavEnableGamepad, ImGuiConfigFlags_NavEnableKeyboard, ImGuiConfigFlags_ViewportsEnable, ImVec2, ImVec4};
-use shaders::compiler::{HlslCompiler, RawShaderDefinition};
+use shaders::compiler::{HlslCompiler, RawShaderDefinition, SpirV};
 use std::ffi::c_char;
 use std::ops::{Deref, DerefMut};
 use std::ptr::null_mut;
@@ -24,6 +23,9 @@ use vulkanalia::vk;
 use vulkanalia::vk::{Extent2D, ImageType};
 use winit::event::MouseButton;
 
+// Compiled once per process, every window renderer reuses the same modules
+static SHADERS: Mutex<Option<(SpirV, SpirV)>> = Mutex::new(None);
+
 const PIXEL: &str = r#"
 struct VSInput {
     [[vk::location(0)]] float2 aPos 	: POSITION;
@@ -64,12 +66,10 @@ float4 main(VsToFs input) : SV_TARGET {
 "#;
 
 pub struct ImGui {
-    _compiler: HlslCompiler,
     mesh: RwLock<Mesh>,
     pipeline: Pipeline,
     descriptor_sets: DescriptorSets,
     _font_texture: Resource<Image>,
-    _sampler: Sampler,
     window_ctx: RwLock<WindowCtx>,
     context: RwLock<Option<SuspendedContext>>,
     self_ref: ResourceHandle<ImGui>,
@@ -117,62 +117,32 @@ impl<'a> DerefMut for UiPtr<'a> {
 
 impl ImGui {
     pub fn new(ctx: DeviceCtx, render_res: Extent2D, render_pass: &ResourceHandle<RenderPassObject>) -> Result<Resource<Self>, Error> {
-        let mut compiler = HlslCompiler::new()?;
-
-        let vertex = compiler.compile(&RawShaderDefinition::new("imgui-vertex", "vs_6_0", PIXEL.to_string()))?;
-        let fragment = compiler.compile(&RawShaderDefinition::new("imgui-fragment", "ps_6_0", FRAGMENT.to_string()))?;
-
-        let vertex = ShaderStage::new(ctx.clone(), &vertex.raw(), ShaderStageInfos {
-            descriptor_bindings: vec![],
-            push_constant_size: Some(size_of::<ImGuiPushConstants>() as u32),
-            stage_input: vec![
-                ShaderStageInputs {
-                    location: 0,
-                    offset: 0,
-                    input_size: 8,
-                    property_type: vk::Format::R32G32_SFLOAT,
-                },
-                ShaderStageInputs {
-                    location: 1,
-                    offset: 8,
-                    input_size: 8,
-                    property_type: vk::Format::R32G32_SFLOAT,
-                },
-                ShaderStageInputs {
-                    location: 2,
-                    offset: 16,
-                    input_size: 4,
-                    property_type: vk::Format::R8G8B8A8_UNORM,
-                }],
-            stage: vk::ShaderStageFlags::VERTEX,
-            entry_point: "main".to_string(),
-        })?;
-        let fragment = ShaderStage::new(ctx.clone(), &fragment.raw(),
-                                        ShaderStageInfos {
-                                            descriptor_bindings: vec![
-                                                ShaderStageBindings {
-                                                    binding: 0,
-                                                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
-                                                },
-                                                ShaderStageBindings {
-                                                    binding: 1,
-                                                    descriptor_type: vk::DescriptorType::SAMPLER,
-                                                }],
-                                            push_constant_size: None,
-                                            stage_input: vec![],
-                                            stage: vk::ShaderStageFlags::FRAGMENT,
-                                            entry_point: "main".to_string(),
-                                        })?;
-
-
-        let pipeline = Pipeline::new(ctx.clone(), &render_pass, vec![vertex, fragment], &PipelineConfig {
+        let (vertex, fragment) = {
+            let mut shaders = SHADERS.lock().unwrap();
+            if shaders.is_none() {
+                let mut compiler = HlslCompiler::new()?;
+                *shaders = Some((
+                    compiler.compile(&RawShaderDefinition::new("imgui-vertex", "vs_6_0", PIXEL.to_string()))?,
+                    compiler.compile(&RawShaderDefinition::new("imgui-fragment", "ps_6_0", FRAGMENT.to_string()))?));
+            }
+            shaders.clone().unwrap()
+        };
+
+        let mut vertex_infos = ShaderStageInfos::from_spirv(&vertex, "main")?;
+        // ImGui vertex colors are packed in a single u32
+        let color = vertex_infos.stage_input.iter_mut().find(|input| input.location == 2).ok_or(anyhow!("Missing ImGui color input"))?;
+        color.input_size = 4;
+        color.property_type = vk::Format::R8G8B8A8_UNORM;
+        let vertex = ShaderStage::new(ctx.clone(), &vertex.raw(), vertex_infos)?;
+        let fragment_infos = ShaderStageInfos::from_spirv(&fragment, "main")?;
+        let fragment = ShaderStage::new(ctx.clone(), &fragment.raw(), fragment_infos)?;
+
+
+        // UI is drawn after every render callback
+        let pipeline = Pipeline::new(ctx.clone(), &render_pass.attachment_formats(render_pass.subpass_count() as u32 - 1), vec![vertex, fragment], &PipelineConfig {
             culling: vk::CullModeFlags::NONE,
-            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            polygon_mode: vk::PolygonMode::FILL,
-            alpha_mode: AlphaMode::Translucent,
-            depth_test: true,
-            line_width: 1.0,
+            blend: vec![AlphaMode::Translucent.into()],
+            ..Default::default()
         })?;
 
         let context = ImGuiContext::new(null_mut());
@@ -224,7 +194,10 @@ impl ImGui {
             height: height as u32,
             depth: 1,
             mips_levels: 1,
+            array_layers: 1,
+            cube: false,
             is_depth: false,
+            samples: vk::SampleCountFlags::_1,
         })?;
 
         font_texture.set_data(&BufferMemory::from_raw(pixels as *const u8, 1, data_size as usize))?;
@@ -233,9 +206,9 @@ impl ImGui {
 
         //unsafe { (&mut *io.Fonts).TexID = font_texture.__static_view_handle() as ImTextureID; }
 
-        let mut desc_set = DescriptorSets::new(ctx.clone(), pipeline.descriptor_set_layout())?;
+        let mut desc_set = DescriptorSets::new(ctx.clone(), &pipeline, 0)?;
 
-        let sampler = Sampler::new(ctx.clone())?;
+        let sampler = ctx.sampler(&SamplerDesc::default())?;
 
         desc_set.update(vec![
             (ShaderInstanceBinding::SampledImage(*font_texture.view()?, *font_texture.layout()), 0),
@@ -243,12 +216,10 @@ impl ImGui {
         ])?;
 
         let mut imgui = Resource::new(Self {
-            _compiler: compiler,
             mesh: RwLock::new(mesh),
             pipeline,
             descriptor_sets: desc_set,
             _font_texture: font_texture,
-            _sampler: sampler,
             window_ctx: Default::default(),
             context: RwLock::new(Some(context.suspend())),
             self_ref: Default::default(),
@@ -434,7 +405,7 @@ impl ImGui {
                              */
 
                             command_buffer.bind_pipeline(&self.pipeline);
-                            command_buffer.bind_descriptors(&self.pipeline, &self.descriptor_sets);
+                            command_buffer.bind_descriptors(&self.pipeline, 0, &self.descriptor_sets, &[]);
 
                             command_buffer.draw_mesh_advanced(&self.mesh.write().unwrap(), pcmd.IdxOffset + global_idx_offset, pcmd.VtxOffset + global_vtx_offset, pcmd.ElemCount, 1, 0);
                         }
@@ -1,10 +1,11 @@
 use anyhow::{anyhow, Error};
-use tracing::{error};
+use tracing::{error, info};
 use winit::event::{WindowEvent};
 use winit::event_loop::{ActiveEventLoop};
 use winit::window::{Window, WindowAttributes, WindowId};
 use types::resource_handle::{Resource, ResourceHandle, ResourceHandleMut};
 use crate::core::gfx::frame_graph::frame_graph_definition::{Renderer};
+use crate::core::gfx::frame_graph::frame_graph_file::FrameGraphFile;
 use crate::core::gfx::surface::{Surface, SurfaceCtx};
 use crate::core::gfx::swapchain::{Swapchain, SwapchainCtx};
 use crate::core::input_manager::InputManager;
@@ -21,6 +22,7 @@ pub struct AppWindow {
     window: Option<Window>,
     engine: EngineCtx,
     input_manager: InputManager,
+    frame_graph_file: Option<FrameGraphFile>,
     self_ctx: ResourceHandle<AppWindow>,
 }
 
@@ -40,6 +42,7 @@ impl AppWindow {
             engine: ctx,
             input_manager: InputManager::default(),
             minimized: false,
+            frame_graph_file: None,
             self_ctx: Default::default(),
         });
         window.self_ctx = window.handle();
@@ -51,10 +54,36 @@ impl AppWindow {
     }
 
     pub fn set_renderer(&mut self, renderer: Renderer) -> Result<(), Error> {
-        self.swapchain.set_renderer(renderer);
+        self.swapchain.set_renderer(renderer)?;
+        self.frame_graph_file = None;
         Ok(())
     }
 
+    /// Use the renderer described by this file. The renderer is rebuilt whenever the file is modified.
+    pub fn set_renderer_from_file(&mut self, mut file: FrameGraphFile) -> Result<(), Error> {
+        let renderer = file.load(self.self_ctx.clone())?;
+        self.swapchain.set_renderer(renderer)?;
+        file.release_retired_passes();
+        self.frame_graph_file = Some(file);
+        Ok(())
+    }
+
+    fn reload_frame_graph_if_modified(&mut self) {
+        if let Some(file) = &mut self.frame_graph_file {
+            if !file.is_modified() {
+                return;
+            }
+            // The previous renderer keeps running if the new description is invalid or can't be instantiated
+            match file.load(self.self_ctx.clone()).and_then(|renderer| self.swapchain.set_renderer(renderer)) {
+                Ok(()) => {
+                    info!("Reloaded frame graph {}", file.path().display());
+                    file.release_retired_passes();
+                }
+                Err(err) => { error!("Failed to reload frame graph : {err}") }
+            }
+        }
+    }
+
     pub fn engine(&self) -> &EngineCtx {
         &self.engine
     }
@@ -62,14 +91,13 @@ impl AppWindow {
     pub fn window_event(&mut self, _: &ActiveEventLoop, event: WindowEvent) -> Result<(), Error> {
         self.input_manager.consume_event(&event);
         match event {
-            WindowEvent::RedrawRequested => {
-                if !self.minimized {
-                    self.input_manager.begin_frame();
-                    if self.swapchain.is_valid() {
-                        if let Err(err) = self.swapchain.render() {
-                            error!("Failed to render frame : {}", err);
-                        };
-                    }
+            WindowEvent::RedrawRequested if !self.minimized => {
+                self.input_manager.begin_frame();
+                self.reload_frame_graph_if_modified();
+                if self.swapchain.is_valid() {
+                    if let Err(err) = self.swapchain.render() {
+                        error!("Failed to render frame : {}", err);
+                    };
                 }
             }
             WindowEvent::Resized(size) => {
@@ -13,6 +13,7 @@ use types::profiler::Profiler;
 use types::resource_handle::{Resource, ResourceHandle, ResourceHandleMut};
 use types::time_delta::TimeDelta;
 use crate::application::Application;
+use crate::core::gfx::device::DeviceCtx;
 use crate::core::gfx::instance::{GfxConfig, Instance, InstanceCtx};
 use crate::options::{Options, WindowOptions};
 use crate::core::window::{AppWindow, WindowCtxMut};
@@ -43,6 +44,9 @@ impl Engine {
         let mut config = GfxConfig {
             validation_layers: true,
             required_extensions: vec![vk::KHR_SWAPCHAIN_EXTENSION.name],
+            dynamic_rendering: options.rendering.dynamic_rendering,
+            bindless: options.rendering.bindless,
+            pipeline_cache_path: options.rendering.pipeline_cache_path.clone(),
         };
         let mut data = Resource::new(Self
         {
@@ -84,6 +88,11 @@ impl Engine {
         &self.options
     }
 
+    /// Create the device without opening any window, for offscreen and compute work
+    pub fn create_headless_device(&mut self) -> Result<DeviceCtx, Error> {
+        self.instance.create_headless_device()
+    }
+
     pub fn create_window(&mut self, options: &WindowOptions) -> Result<WindowCtxMut, Error> {
         let record = Profiler::get().record(format!("Create window {}", options.name).as_str());
         let mut attributes = WindowAttributes::default();
@@ -119,6 +128,10 @@ impl Engine {
 
     pub fn render_frame(&mut self, event_loop: &ActiveEventLoop, event: WindowEvent) {
         Profiler::get().new_frame();
+        // Counters are only published for profiled frames
+        if Profiler::get().is_enabled() && self.instance.get_device().is_valid() {
+            self.instance.device().report_stats();
+        }
         let record = Profiler::get().record("Render frame");
         self.delta_time.next();
         // Draw all windows (sequentially, no need to parallelize this work for now)
@@ -193,4 +206,42 @@ impl Drop for Engine {
         self.instance = Resource::default();
         unsafe { ENGINE = None; }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Mutex, MutexGuard};
+    use types::resource_handle::Resource;
+    use crate::application::Application;
+    use crate::core::gfx::device::DeviceCtx;
+    use crate::core::window::{WindowCtx, WindowCtxMut};
+    use crate::engine::{Engine, EngineCtx};
+    use crate::options::Options;
+
+    // The engine is a global : tests using a device run one at a time
+    static DEVICE_LOCK: Mutex<()> = Mutex::new(());
+
+    #[derive(Default)]
+    struct EmptyApplication;
+
+    impl Application for EmptyApplication {
+        fn instantiate(&mut self, _: &mut WindowCtxMut) {}
+        fn create_window(&mut self, _: &mut WindowCtxMut) {}
+        fn pre_draw_window(&mut self, _: &WindowCtx) {}
+        fn tick(&mut self, _: &EngineCtx) {}
+        fn destroy(&mut self) {}
+    }
+
+    pub(crate) struct TestDevice {
+        pub ctx: DeviceCtx,
+        _engine: Resource<Engine>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    /// Engine with a headless device. Tests using it are ignored by default : run them with `cargo test -- --ignored` on a machine with a Vulkan driver (lavapipe works).
+    pub(crate) fn test_device() -> TestDevice {
+        let lock = DEVICE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut engine = Engine::new::<EmptyApplication>(Options::default()).unwrap_or_else(|err| panic!("Failed to create the engine : {err}"));
+        let ctx = engine.create_headless_device().unwrap_or_else(|err| panic!("Failed to create a headless device : {err}"));
+        TestDevice { ctx, _engine: engine, _lock: lock }
+    }
+}
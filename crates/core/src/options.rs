@@ -1,8 +1,18 @@
 
+use std::path::PathBuf;
+
 #[derive(Clone)]
 pub struct RenderingOption {
     pub validation_layers: bool,
-    pub image_count: usize
+    pub image_count: usize,
+    /// Draw the frame graph with dynamic rendering instead of render pass and framebuffer objects, when the device supports it
+    pub dynamic_rendering: bool,
+    /// Bytes of per-frame uniform and storage data each swapchain can allocate
+    pub transient_buffer_size: usize,
+    /// Create the bindless texture table, when the device supports descriptor indexing
+    pub bindless: bool,
+    /// File the pipeline cache is loaded from on startup and saved to on shutdown. None keeps the cache in memory.
+    pub pipeline_cache_path: Option<PathBuf>,
 }
 
 impl Default for RenderingOption {
@@ -10,6 +20,10 @@ impl Default for RenderingOption {
         Self {
             validation_layers: true,
             image_count: 2,
+            dynamic_rendering: false,
+            transient_buffer_size: 4 << 20,
+            bindless: false,
+            pipeline_cache_path: None,
         }
     }
 }
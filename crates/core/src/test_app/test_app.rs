@@ -3,9 +3,8 @@ use crate::core::gfx::resources::buffer::{BufferMemory, BufferType};
 use crate::core::gfx::resources::descriptor_sets::{DescriptorSets, ShaderInstanceBinding};
 use crate::core::gfx::resources::image::{Image, ImageCreateOptions};
 use crate::core::gfx::resources::mesh::Mesh;
-use crate::core::gfx::resources::pipeline::{AlphaMode, Pipeline, PipelineConfig};
-use crate::core::gfx::resources::sampler::Sampler;
-use crate::core::gfx::resources::shader_module::{ShaderStage, ShaderStageBindings, ShaderStageInfos, ShaderStageInputs};
+use crate::core::gfx::resources::pipeline::{Pipeline, PipelineConfig};
+use crate::core::gfx::resources::shader_module::{ShaderStage, ShaderStageInfos};
 use crate::core::gfx::swapchain::SwapchainCtx;
 use crate::assets::gltf_importer::gltf_importer::GltfImporter;
 use crate::test_app::camera::Camera;
@@ -21,6 +20,7 @@ use winit::keyboard::{Key, NamedKey, SmolStr};
 use job_sys::{Job, JobSystem};
 use types::resource_handle::Resource;
 use types::rwarc::RwArc;
+use macros::Std430;
 use crate::core::gfx::frame_graph::renderer::RenderPassObject;
 
 const PIXEL: &str = r#"
@@ -74,9 +74,10 @@ pub struct TestApp {
     speed: f32,
     last_mouse: DVec2,
     _images: Vec<Resource<Image>>,
-    _sampler: Sampler,
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Std430)]
 pub struct Pc {
     pub model: Mat4,
     pub camera: Mat4,
@@ -88,40 +89,12 @@ impl TestApp {
         let vertex = compiler.compile(&RawShaderDefinition::new("imgui-vertex", "vs_6_0", PIXEL.to_string()))?;
         let fragment = compiler.compile(&RawShaderDefinition::new("imgui-fragment", "ps_6_0", FRAGMENT.to_string()))?;
 
-        let vertex = ShaderStage::new(ctx.device().clone(), &vertex.raw(), ShaderStageInfos {
-            descriptor_bindings: vec![],
-            push_constant_size: Some(size_of::<Pc>() as u32),
-            stage_input: vec![
-                ShaderStageInputs {
-                    location: 0,
-                    offset: 0,
-                    input_size: 12,
-                    property_type: vk::Format::R32G32B32_SFLOAT,
-                }],
-            stage: vk::ShaderStageFlags::VERTEX,
-            entry_point: "main".to_string(),
-        })?;
-        let fragment = ShaderStage::new(ctx.device().clone(), &fragment.raw(),
-                                        ShaderStageInfos {
-                                            descriptor_bindings: vec![
-                                                ShaderStageBindings { binding: 0, descriptor_type: vk::DescriptorType::SAMPLER },
-                                                ShaderStageBindings { binding: 1, descriptor_type: vk::DescriptorType::SAMPLED_IMAGE },
-                                            ],
-                                            push_constant_size: None,
-                                            stage_input: vec![],
-                                            stage: vk::ShaderStageFlags::FRAGMENT,
-                                            entry_point: "main".to_string(),
-                                        })?;
-
-        let pipeline = Pipeline::new(ctx.device().clone(), render_pass, vec![vertex, fragment], &PipelineConfig {
-            culling: vk::CullModeFlags::BACK,
-            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            polygon_mode: vk::PolygonMode::FILL,
-            alpha_mode: AlphaMode::Opaque,
-            depth_test: true,
-            line_width: 1.0,
-        })?;
+        let vertex_infos = ShaderStageInfos::from_spirv(&vertex, "main")?;
+        let vertex = ShaderStage::new(ctx.device().clone(), &vertex.raw(), vertex_infos)?;
+        let fragment_infos = ShaderStageInfos::from_spirv(&fragment, "main")?;
+        let fragment = ShaderStage::new(ctx.device().clone(), &fragment.raw(), fragment_infos)?;
+
+        let pipeline = Pipeline::new(ctx.device().clone(), &render_pass.attachment_formats(0), vec![vertex, fragment], &PipelineConfig::default())?;
 
         let mut camera = Camera::default();
         camera.set_position(Vec3::new(0f32, 0f32, 0.5f32));
@@ -143,8 +116,9 @@ impl TestApp {
                 images_handles.push((js.push(Job::new(move || {
                     Image::from_dynamic_image(ctx, &gltf.read().load_image(i)?, ImageCreateOptions {
                         usage: vk::ImageUsageFlags::SAMPLED,
-                        mips_levels: 1,
+                        mips_levels: 0,
                         is_depth: false,
+                        samples: vk::SampleCountFlags::_1,
                         ..Default::default()
                     })
                 })), i));
@@ -156,12 +130,11 @@ impl TestApp {
                 info!("Load image {} / {}", res.1, num_images);
             }
         }
-        let sampler = Sampler::new(ctx.device().clone())?;
-
         let mut descriptor_sets = vec![];
 
-        for image in &images {
-            let mut descriptor_set = DescriptorSets::new(ctx.device().clone(), pipeline.descriptor_set_layout())?;
+        for (i, image) in images.iter().enumerate() {
+            let sampler = ctx.device().sampler(&gltf.read().image_sampler(i))?;
+            let mut descriptor_set = DescriptorSets::new(ctx.device().clone(), &pipeline, 0)?;
             descriptor_set.update(vec![
                 (ShaderInstanceBinding::Sampler(*sampler.ptr()), 0),
                 (ShaderInstanceBinding::SampledImage(*image.view()?, *image.layout()), 1)
@@ -191,7 +164,6 @@ impl TestApp {
             speed: 2f32,
             last_mouse: Default::default(),
             _images: images,
-            _sampler: sampler,
         })
     }
 
@@ -264,7 +236,7 @@ impl TestApp {
         });
 
         for (i, mesh) in self.meshes.iter().enumerate() {
-            command_buffer.bind_descriptors(&self.pipeline, &self.descriptor_sets[i % self.descriptor_sets.len()]);
+            command_buffer.bind_descriptors(&self.pipeline, 0, &self.descriptor_sets[i % self.descriptor_sets.len()], &[]);
 
             command_buffer.draw_mesh(mesh, 1, 0);
         }
@@ -1,4 +1,6 @@
 use proc_macro::{TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
 
 #[proc_macro_derive(ResourceObject)]
 pub fn derive_answer_fn(input: TokenStream) -> TokenStream {
@@ -28,4 +30,73 @@ fn impl_resource_object(ast: &syn::DeriveInput) -> TokenStream {
     } else {
         panic!("The ResourceObject derive macro can only be applied to resources.");
     }
-}
\ No newline at end of file
+}
+
+/// Implements types::gpu_layout::Std140, and checks at compile time that the repr(C) layout matches the std140 rules
+#[proc_macro_derive(Std140)]
+pub fn derive_std140(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).unwrap();
+    impl_gpu_layout(&ast, "Std140", 16)
+}
+
+/// Implements types::gpu_layout::Std430, and checks at compile time that the repr(C) layout matches the std430 rules
+#[proc_macro_derive(Std430)]
+pub fn derive_std430(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).unwrap();
+    impl_gpu_layout(&ast, "Std430", 1)
+}
+
+fn impl_gpu_layout(ast: &syn::DeriveInput, layout: &str, min_align: usize) -> TokenStream {
+    let name = &ast.ident;
+    let syn::Data::Struct(data) = &ast.data else {
+        panic!("The {layout} derive macro can only be applied to structs.");
+    };
+    if !ast.generics.params.is_empty() {
+        panic!("The {layout} derive macro does not support generic structs.");
+    }
+    let mut repr_c = false;
+    for attr in &ast.attrs {
+        if attr.path().is_ident("repr") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("C") {
+                    repr_c = true;
+                }
+                Ok(())
+            }).unwrap();
+        }
+    }
+    if !repr_c {
+        panic!("{name} should be #[repr(C)] to derive {layout}.");
+    }
+
+    let layout = format_ident!("{layout}");
+    let mut align = quote!(#min_align);
+    let mut checks = vec![];
+    for field in &data.fields {
+        let Some(field_name) = &field.ident else {
+            panic!("The {layout} derive macro can only be applied to structs with named fields.");
+        };
+        let field_type = &field.ty;
+        align = quote!(types::gpu_layout::max_align(#align, <#field_type as types::gpu_layout::#layout>::ALIGN));
+        // Rust may add more padding than the GPU layout : the offset should be exactly the end of the previous field rounded up to the alignment
+        let message = format!("{name}::{field_name} is not at its {layout} offset");
+        checks.push(quote_spanned! {field_type.span()=>
+            offset = offset.next_multiple_of(<#field_type as types::gpu_layout::#layout>::ALIGN);
+            assert!(std::mem::offset_of!(#name, #field_name) == offset, #message);
+            offset += std::mem::size_of::<#field_type>();
+        });
+    }
+    let size_message = format!("The size of {name} should be padded to a multiple of its {layout} alignment");
+
+    quote! {
+        impl types::gpu_layout::#layout for #name {
+            const ALIGN: usize = #align;
+        }
+
+        const _: () = {
+            let mut offset = 0usize;
+            #(#checks)*
+            assert!(std::mem::size_of::<#name>() == offset.next_multiple_of(<#name as types::gpu_layout::#layout>::ALIGN), #size_message);
+        };
+    }.into()
+}
@@ -1,3 +1,4 @@
+use crate::reflection::{reflect, ShaderReflection};
 use anyhow::{anyhow, Error};
 use hassle_rs::{Dxc, DxcIncludeHandler};
 use std::fs;
@@ -41,11 +42,14 @@ impl DxcIncludeHandler for IncludeHandler {
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ShaderStage {
     VERTEX,
+    HULL,
+    DOMAIN,
+    GEOMETRY,
     PIXEL,
     COMPUTE,
-
 }
 
 
@@ -133,6 +137,7 @@ impl ShaderDefinition for RawShaderDefinition {
     }
 }
 
+#[derive(Clone)]
 pub struct SpirV {
     raw: Vec<u8>,
 }
@@ -141,6 +146,15 @@ impl SpirV {
     pub fn raw(self) -> Vec<u8> {
         self.raw
     }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Descriptors, push constants, inputs and entry points declared by the module
+    pub fn reflect(&self) -> Result<ShaderReflection, Error> {
+        reflect(&self.raw)
+    }
 }
 
 pub struct HlslCompiler {
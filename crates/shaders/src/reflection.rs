@@ -0,0 +1,417 @@
+use crate::compiler::ShaderStage;
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+
+const MAGIC: u32 = 0x07230203;
+
+// Opcodes
+const OP_NAME: u16 = 5;
+const OP_ENTRY_POINT: u16 = 15;
+const OP_EXECUTION_MODE: u16 = 16;
+const OP_TYPE_BOOL: u16 = 20;
+const OP_TYPE_INT: u16 = 21;
+const OP_TYPE_FLOAT: u16 = 22;
+const OP_TYPE_VECTOR: u16 = 23;
+const OP_TYPE_MATRIX: u16 = 24;
+const OP_TYPE_IMAGE: u16 = 25;
+const OP_TYPE_SAMPLER: u16 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u16 = 27;
+const OP_TYPE_ARRAY: u16 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u16 = 29;
+const OP_TYPE_STRUCT: u16 = 30;
+const OP_TYPE_POINTER: u16 = 32;
+const OP_CONSTANT: u16 = 43;
+const OP_SPEC_CONSTANT: u16 = 50;
+const OP_VARIABLE: u16 = 59;
+const OP_DECORATE: u16 = 71;
+const OP_MEMBER_DECORATE: u16 = 72;
+const OP_EXECUTION_MODE_ID: u16 = 331;
+const OP_TYPE_ACCELERATION_STRUCTURE: u16 = 5341;
+
+// Decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+// Execution modes
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+// Image dimensions
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DescriptorKind {
+    Sampler,
+    SampledImage,
+    CombinedImageSampler,
+    StorageImage,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+    UniformBuffer,
+    StorageBuffer,
+    InputAttachment,
+    AccelerationStructure,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub name: String,
+    pub kind: DescriptorKind,
+    /// Array size, 0 for runtime sized arrays
+    pub count: u32,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ScalarType {
+    Float,
+    Int,
+    UInt,
+    Bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedInput {
+    pub location: u32,
+    pub name: String,
+    pub scalar: ScalarType,
+    /// Bits per component
+    pub width: u32,
+    pub components: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReflectedEntryPoint {
+    pub name: String,
+    pub stage: ShaderStage,
+    /// Non built-in inputs, sorted by location
+    pub inputs: Vec<ReflectedInput>,
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+/// Resources declared by a SPIR-V module
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<ReflectedEntryPoint>,
+    /// Sorted by set then binding
+    pub bindings: Vec<ReflectedBinding>,
+    /// Size in bytes of the push constant block
+    pub push_constant_size: Option<u32>,
+}
+
+impl ShaderReflection {
+    pub fn entry_point(&self, name: &str) -> Option<&ReflectedEntryPoint> {
+        self.entry_points.iter().find(|entry_point| entry_point.name == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+struct EntryPointDeclaration {
+    id: u32,
+    name: String,
+    execution_model: u32,
+    interface: Vec<u32>,
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    // (id, storage class, type)
+    variables: Vec<(u32, u32, u32)>,
+    decorations: HashMap<u32, HashMap<u32, u32>>,
+    member_decorations: HashMap<(u32, u32), HashMap<u32, u32>>,
+    entry_points: Vec<EntryPointDeclaration>,
+    local_sizes: HashMap<u32, [u32; 3]>,
+    local_size_ids: HashMap<u32, [u32; 3]>,
+}
+
+pub fn reflect(code: &[u8]) -> Result<ShaderReflection, Error> {
+    if !code.len().is_multiple_of(4) || code.len() < 20 {
+        return Err(anyhow!("Invalid SPIR-V : size is not a multiple of 4 words"));
+    }
+    let words = code.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect::<Vec<_>>();
+    if words[0] != MAGIC {
+        return Err(anyhow!("Invalid SPIR-V : wrong magic number {:#x}", words[0]));
+    }
+    let module = Module::parse(&words[5..])?;
+    module.reflect()
+}
+
+impl Module {
+    fn parse(mut words: &[u32]) -> Result<Self, Error> {
+        let mut module = Self::default();
+        while !words.is_empty() {
+            let word_count = (words[0] >> 16) as usize;
+            let opcode = (words[0] & 0xFFFF) as u16;
+            if word_count == 0 || word_count > words.len() {
+                return Err(anyhow!("Invalid SPIR-V : truncated instruction {}", opcode));
+            }
+            let operands = &words[1..word_count];
+            words = &words[word_count..];
+            let operand = |index: usize| operands.get(index).copied().ok_or(anyhow!("Invalid SPIR-V : missing operand of instruction {}", opcode));
+            match opcode {
+                OP_NAME => {
+                    module.names.insert(operand(0)?, literal_string(&operands[1..]).0);
+                }
+                OP_ENTRY_POINT => {
+                    if operands.len() < 3 {
+                        return Err(anyhow!("Invalid SPIR-V : truncated entry point declaration"));
+                    }
+                    let (name, words) = literal_string(&operands[2..]);
+                    module.entry_points.push(EntryPointDeclaration {
+                        id: operand(1)?,
+                        name,
+                        execution_model: operand(0)?,
+                        interface: operands[2 + words..].to_vec(),
+                    });
+                }
+                OP_EXECUTION_MODE if operand(1)? == EXECUTION_MODE_LOCAL_SIZE => {
+                    module.local_sizes.insert(operand(0)?, [operand(2)?, operand(3)?, operand(4)?]);
+                }
+                OP_EXECUTION_MODE_ID if operand(1)? == EXECUTION_MODE_LOCAL_SIZE_ID => {
+                    module.local_size_ids.insert(operand(0)?, [operand(2)?, operand(3)?, operand(4)?]);
+                }
+                OP_TYPE_BOOL => { module.types.insert(operand(0)?, Type::Bool); }
+                OP_TYPE_INT => { module.types.insert(operand(0)?, Type::Int { width: operand(1)?, signed: operand(2)? != 0 }); }
+                OP_TYPE_FLOAT => { module.types.insert(operand(0)?, Type::Float { width: operand(1)? }); }
+                OP_TYPE_VECTOR => { module.types.insert(operand(0)?, Type::Vector { component: operand(1)?, count: operand(2)? }); }
+                OP_TYPE_MATRIX => { module.types.insert(operand(0)?, Type::Matrix { column: operand(1)?, count: operand(2)? }); }
+                OP_TYPE_IMAGE => { module.types.insert(operand(0)?, Type::Image { dim: operand(2)?, sampled: operand(6)? }); }
+                OP_TYPE_SAMPLER => { module.types.insert(operand(0)?, Type::Sampler); }
+                OP_TYPE_SAMPLED_IMAGE => { module.types.insert(operand(0)?, Type::SampledImage); }
+                OP_TYPE_ARRAY => {
+                    let length = *module.constants.get(&operand(2)?).ok_or(anyhow!("Array length is not a constant"))?;
+                    module.types.insert(operand(0)?, Type::Array { element: operand(1)?, length });
+                }
+                OP_TYPE_RUNTIME_ARRAY => { module.types.insert(operand(0)?, Type::RuntimeArray { element: operand(1)? }); }
+                OP_TYPE_STRUCT => { module.types.insert(operand(0)?, Type::Struct { members: operands[1..].to_vec() }); }
+                OP_TYPE_POINTER => { module.types.insert(operand(0)?, Type::Pointer { pointee: operand(2)? }); }
+                OP_TYPE_ACCELERATION_STRUCTURE => { module.types.insert(operand(0)?, Type::AccelerationStructure); }
+                OP_CONSTANT | OP_SPEC_CONSTANT => {
+                    // Only the low word matters for sizes and counts
+                    module.constants.insert(operand(1)?, operand(2)?);
+                }
+                OP_VARIABLE => { module.variables.push((operand(1)?, operand(2)?, operand(0)?)); }
+                OP_DECORATE => {
+                    module.decorations.entry(operand(0)?).or_default().insert(operand(1)?, operands.get(2).copied().unwrap_or(0));
+                }
+                OP_MEMBER_DECORATE => {
+                    module.member_decorations.entry((operand(0)?, operand(1)?)).or_default().insert(operand(2)?, operands.get(3).copied().unwrap_or(0));
+                }
+                _ => {}
+            }
+        }
+        Ok(module)
+    }
+
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&id).and_then(|decorations| decorations.get(&decoration)).copied()
+    }
+
+    fn member_decoration(&self, id: u32, member: u32, decoration: u32) -> Option<u32> {
+        self.member_decorations.get(&(id, member)).and_then(|decorations| decorations.get(&decoration)).copied()
+    }
+
+    fn get_type(&self, id: u32) -> Result<&Type, Error> {
+        self.types.get(&id).ok_or(anyhow!("Invalid SPIR-V : unknown type {}", id))
+    }
+
+    fn reflect(&self) -> Result<ShaderReflection, Error> {
+        let mut reflection = ShaderReflection::default();
+        for (id, storage_class, type_id) in &self.variables {
+            let pointee = match self.get_type(*type_id)? {
+                Type::Pointer { pointee, .. } => { *pointee }
+                _ => { return Err(anyhow!("Invalid SPIR-V : variable {} is not a pointer", id)) }
+            };
+            match *storage_class {
+                STORAGE_PUSH_CONSTANT => {
+                    reflection.push_constant_size = Some(self.size_of(pointee)?);
+                }
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let (Some(set), Some(binding)) = (self.decoration(*id, DECORATION_DESCRIPTOR_SET), self.decoration(*id, DECORATION_BINDING)) else {
+                        continue;
+                    };
+                    let (element, count) = match self.get_type(pointee)? {
+                        Type::Array { element, length } => { (*element, *length) }
+                        Type::RuntimeArray { element } => { (*element, 0) }
+                        _ => { (pointee, 1) }
+                    };
+                    reflection.bindings.push(ReflectedBinding {
+                        set,
+                        binding,
+                        name: self.names.get(id).cloned().unwrap_or_default(),
+                        kind: self.descriptor_kind(element, *storage_class)?,
+                        count,
+                    });
+                }
+                _ => {}
+            }
+        }
+        reflection.bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        for entry_point in &self.entry_points {
+            let stage = match entry_point.execution_model {
+                0 => { ShaderStage::VERTEX }
+                1 => { ShaderStage::HULL }
+                2 => { ShaderStage::DOMAIN }
+                3 => { ShaderStage::GEOMETRY }
+                4 => { ShaderStage::PIXEL }
+                5 => { ShaderStage::COMPUTE }
+                model => { return Err(anyhow!("Unsupported execution model {} for entry point {}", model, entry_point.name)) }
+            };
+            let mut inputs = vec![];
+            for (id, storage_class, type_id) in &self.variables {
+                if *storage_class != STORAGE_INPUT || !entry_point.interface.contains(id) || self.decoration(*id, DECORATION_BUILT_IN).is_some() {
+                    continue;
+                }
+                let Some(location) = self.decoration(*id, DECORATION_LOCATION) else { continue };
+                let Type::Pointer { pointee, .. } = self.get_type(*type_id)? else { continue };
+                let (component, components) = match self.get_type(*pointee)? {
+                    Type::Vector { component, count } => { (*component, *count) }
+                    _ => { (*pointee, 1) }
+                };
+                let (scalar, width) = match self.get_type(component)? {
+                    Type::Float { width } => { (ScalarType::Float, *width) }
+                    Type::Int { width, signed: true } => { (ScalarType::Int, *width) }
+                    Type::Int { width, signed: false } => { (ScalarType::UInt, *width) }
+                    Type::Bool => { (ScalarType::Bool, 32) }
+                    _ => { return Err(anyhow!("Unsupported type for input {} of entry point {}", location, entry_point.name)) }
+                };
+                inputs.push(ReflectedInput {
+                    location,
+                    name: self.names.get(id).cloned().unwrap_or_default(),
+                    scalar,
+                    width,
+                    components,
+                });
+            }
+            inputs.sort_by_key(|input| input.location);
+
+            let workgroup_size = match (self.local_sizes.get(&entry_point.id), self.local_size_ids.get(&entry_point.id)) {
+                (Some(size), _) => { Some(*size) }
+                (None, Some(ids)) => {
+                    let mut size = [1; 3];
+                    for (axis, id) in ids.iter().enumerate() {
+                        size[axis] = *self.constants.get(id).ok_or(anyhow!("Workgroup size is not a constant"))?;
+                    }
+                    Some(size)
+                }
+                (None, None) => { None }
+            };
+
+            reflection.entry_points.push(ReflectedEntryPoint {
+                name: entry_point.name.clone(),
+                stage,
+                inputs,
+                workgroup_size,
+            });
+        }
+        Ok(reflection)
+    }
+
+    fn descriptor_kind(&self, type_id: u32, storage_class: u32) -> Result<DescriptorKind, Error> {
+        Ok(match self.get_type(type_id)? {
+            Type::Sampler => { DescriptorKind::Sampler }
+            Type::SampledImage => { DescriptorKind::CombinedImageSampler }
+            Type::Image { dim: DIM_BUFFER, sampled: 2 } => { DescriptorKind::StorageTexelBuffer }
+            Type::Image { dim: DIM_BUFFER, .. } => { DescriptorKind::UniformTexelBuffer }
+            Type::Image { dim: DIM_SUBPASS_DATA, .. } => { DescriptorKind::InputAttachment }
+            Type::Image { sampled: 2, .. } => { DescriptorKind::StorageImage }
+            Type::Image { .. } => { DescriptorKind::SampledImage }
+            Type::AccelerationStructure => { DescriptorKind::AccelerationStructure }
+            Type::Struct { .. } => {
+                if storage_class == STORAGE_STORAGE_BUFFER || self.decoration(type_id, DECORATION_BUFFER_BLOCK).is_some() {
+                    DescriptorKind::StorageBuffer
+                } else if self.decoration(type_id, DECORATION_BLOCK).is_some() {
+                    DescriptorKind::UniformBuffer
+                } else {
+                    return Err(anyhow!("Buffer type {} is not a block", type_id));
+                }
+            }
+            _ => { return Err(anyhow!("Unsupported descriptor type {}", type_id)) }
+        })
+    }
+
+    // Size of a type laid out with its explicit offsets and strides
+    fn size_of(&self, type_id: u32) -> Result<u32, Error> {
+        Ok(match self.get_type(type_id)? {
+            Type::Bool => { 4 }
+            Type::Int { width, .. } | Type::Float { width } => { width / 8 }
+            Type::Vector { component, count } => { self.size_of(*component)? * count }
+            Type::Matrix { column, count } => {
+                match self.decoration(type_id, DECORATION_MATRIX_STRIDE) {
+                    Some(stride) => { stride * count }
+                    None => { self.size_of(*column)? * count }
+                }
+            }
+            Type::Array { element, length } => {
+                match self.decoration(type_id, DECORATION_ARRAY_STRIDE) {
+                    Some(stride) => { stride * length }
+                    None => { self.size_of(*element)? * length }
+                }
+            }
+            Type::RuntimeArray { .. } => { 0 }
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (member, member_type) in members.iter().enumerate() {
+                    let offset = self.member_decoration(type_id, member as u32, DECORATION_OFFSET).unwrap_or(size);
+                    let member_size = match (self.get_type(*member_type)?, self.member_decoration(type_id, member as u32, DECORATION_MATRIX_STRIDE)) {
+                        (Type::Matrix { count, .. }, Some(stride)) => { stride * count }
+                        _ => { self.size_of(*member_type)? }
+                    };
+                    size = size.max(offset + member_size);
+                }
+                size
+            }
+            t => { return Err(anyhow!("Type {:?} has no size", t)) }
+        })
+    }
+}
+
+// Nul terminated UTF-8 string packed in words, returns the string and the number of words it used
+fn literal_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = vec![];
+    for (index, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).to_string(), index + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).to_string(), words.len())
+}
@@ -0,0 +1,48 @@
+use glam::{IVec2, IVec3, IVec4, Mat2, Mat3A, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec3A, Vec4};
+
+/// Type usable in a std140 (uniform buffer) block. ALIGN is the base alignment of the type in the block.
+/// Implemented for user structs with `#[derive(Std140)]`, which checks the layout at compile time.
+pub trait Std140: Copy {
+    const ALIGN: usize;
+}
+
+/// Type usable in a std430 (storage buffer / push constant) block. ALIGN is the base alignment of the type in the block.
+/// Implemented for user structs with `#[derive(Std430)]`, which checks the layout at compile time.
+pub trait Std430: Copy {
+    const ALIGN: usize;
+}
+
+pub const fn max_align(a: usize, b: usize) -> usize {
+    if a > b { a } else { b }
+}
+
+macro_rules! impl_layout {
+    ($align:expr, $($t:ty),*) => {
+        $(
+        impl Std140 for $t { const ALIGN: usize = $align; }
+        impl Std430 for $t { const ALIGN: usize = $align; }
+        )*
+    };
+}
+
+impl_layout!(4, f32, i32, u32);
+impl_layout!(8, Vec2, IVec2, UVec2);
+impl_layout!(16, Vec3, IVec3, UVec3, Vec3A, Vec4, IVec4, UVec4, Mat3A, Mat4);
+
+// Columns are not padded to 16 bytes
+impl Std430 for Mat2 { const ALIGN: usize = 8; }
+
+// Array elements are padded to 16 bytes in std140
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGN: usize = {
+        assert!(size_of::<T>().is_multiple_of(16), "std140 array elements should have a size multiple of 16");
+        max_align(T::ALIGN, 16)
+    };
+}
+
+impl<T: Std430, const N: usize> Std430 for [T; N] {
+    const ALIGN: usize = {
+        assert!(size_of::<T>().is_multiple_of(T::ALIGN), "std430 array elements should have a size multiple of their alignment");
+        T::ALIGN
+    };
+}
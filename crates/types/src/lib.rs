@@ -4,3 +4,4 @@ pub mod error;
 pub mod resource_handle;
 pub mod time_delta;
 pub mod profiler;
+pub mod gpu_layout;
\ No newline at end of file
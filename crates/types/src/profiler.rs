@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::sync::{RwLock, RwLockReadGuard};
 use std::time::{Duration, Instant};
 
@@ -5,7 +6,8 @@ static mut GLOBAL_PROFILER: Option<Profiler> = None;
 
 pub struct Profiler {
     history: RwLock<Vec<Vec<RecordData>>>,
-    current_frame: RwLock<Option<Vec<RecordData>>>
+    current_frame: RwLock<Option<Vec<RecordData>>>,
+    counters: RwLock<BTreeMap<String, u64>>,
 }
 
 impl Profiler {
@@ -15,6 +17,7 @@ impl Profiler {
             GLOBAL_PROFILER = Some(Profiler {
                 history: Default::default(),
                 current_frame: Default::default(),
+                counters: Default::default(),
             })
         }
     }
@@ -53,6 +56,10 @@ impl Profiler {
         }
     }
     
+    pub fn is_enabled(&self) -> bool {
+        self.current_frame.read().unwrap().is_some()
+    }
+
     pub fn history(&self) -> RwLockReadGuard<Vec<Vec<RecordData>>> {
         self.history.read().unwrap()
     }
@@ -65,6 +72,19 @@ impl Profiler {
         }
     }
     
+    /// Latest value of a named statistic (memory, allocation count...)
+    pub fn set_counter(&self, name: &str, value: u64) {
+        if let Some(counter) = self.counters.write().unwrap().get_mut(name) {
+            *counter = value;
+            return;
+        }
+        self.counters.write().unwrap().insert(name.to_string(), value);
+    }
+
+    pub fn counters(&self) -> Vec<(String, u64)> {
+        self.counters.read().unwrap().iter().map(|(name, value)| (name.clone(), *value)).collect()
+    }
+
     pub fn clear(&self) {
         self.history.write().unwrap().clear();
         let mut current = self.current_frame.write().unwrap();
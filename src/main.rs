@@ -14,6 +14,10 @@ fn main() -> Result<(), Error> {
         rendering: RenderingOption {
             validation_layers: true,
             image_count: 2,
+            dynamic_rendering: false,
+            transient_buffer_size: 4 << 20,
+            bindless: false,
+            pipeline_cache_path: Some("pipeline_cache.bin".into()),
         },
         main_window: WindowOptions {
             name: "Asaogea".to_string()
@@ -34,7 +38,7 @@ impl Application for GameTestApp {
 
         window.engine().instance().device().declare_render_pass(RenderPass::new(RenderPassName::Named("forward".to_string()))
             .color_attachment(RenderPassAttachment::new(RenderTarget::Internal(vk::Format::R16G16B16A16_SFLOAT)))
-            .depth_attachment(RenderPassAttachment::new(RenderTarget::Internal(vk::Format::D32_SFLOAT)))).unwrap();
+            .depth_attachment(RenderPassAttachment::new(RenderTarget::Internal(vk::Format::D32_SFLOAT)).transient())).unwrap();
 
 
         device.declare_render_pass(RenderPass::new(RenderPassName::Present(window.as_ref()))
@@ -45,20 +49,27 @@ impl Application for GameTestApp {
         let renderer = Renderer {
             present_stage: RendererStage {
                 render_callback: Box::new(|| {}),
+                subpass_callbacks: vec![],
+                clear_callback: None,
                 name: RenderPassName::Present(window.as_ref()),
                 dependencies: vec![
                     RendererStage {
                         render_callback: Box::new(|| {}),
+                        subpass_callbacks: vec![],
+                        clear_callback: None,
                         name: RenderPassName::Named("forward".to_string()),
                         dependencies: vec![],
                     },
                     RendererStage {
                         render_callback: Box::new(move || {
                         }),
+                        subpass_callbacks: vec![],
+                        clear_callback: None,
                         name: RenderPassName::Named("depth_pass".to_string()),
                         dependencies: vec![],
                     }],
             },
+            ui: Some(UiStage { pass: RenderPassName::Present(window.as_ref()) }),
             name: format!("MAIN_WINDOW"),
         };
         window.set_renderer(renderer).unwrap();
@@ -75,10 +86,14 @@ impl Application for GameTestApp {
         let renderer = Renderer {
             present_stage: RendererStage {
                 render_callback: Box::new(|| {}),
+                subpass_callbacks: vec![],
+                clear_callback: None,
                 name: RenderPassName::Present(secondary_window.as_ref()),
                 dependencies: vec![
                     RendererStage {
                         render_callback: Box::new(|| {}),
+                        subpass_callbacks: vec![],
+                        clear_callback: None,
                         name: RenderPassName::Named("forward".to_string()),
                         dependencies: vec![],
                     },
@@ -101,10 +116,13 @@ impl Application for GameTestApp {
                                 println!("{} : {:?}", elem.name, elem.elapsed);
                             }
                         }),
+                        subpass_callbacks: vec![],
+                        clear_callback: None,
                         name: RenderPassName::Named("depth_pass".to_string()),
                         dependencies: vec![],
                     }],
             },
+            ui: Some(UiStage { pass: RenderPassName::Present(secondary_window.as_ref()) }),
             name: format!("MAIN_WINDOW"),
         };
         secondary_window.set_renderer(renderer).unwrap();